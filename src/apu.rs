@@ -0,0 +1,192 @@
+// the spc700's address space: 64kb of aram, the ipl rom, timers and the $f0-$ff registers
+
+use crate::{dsp::Dsp, state::{Serializer, Value}};
+
+pub struct Apu {
+    aram: Box<[u8; 0x10000]>,
+    ipl_enable: bool,
+
+    // the dsp's registers are reached through $f2/$f3
+    dsp_addr: u8,
+    dsp: Dsp,
+
+    // $2140-$2143 as seen from each side
+    cpu_in: [u8; 4],
+    cpu_out: [u8; 4],
+
+    timers: [Timer; 3],
+
+    // spc cycles since the last 8khz timer tick, which also clocks the dsp at 32khz
+    divider: u32,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Timer {
+    enable: bool,
+    target: u8,
+    stage: u8,
+    out: u8,
+}
+
+impl Timer {
+    #[inline]
+    const fn step(&mut self) {
+        if !self.enable {
+            return
+        }
+        // a target of 0 counts 256 steps
+        self.stage = self.stage.wrapping_add(1);
+        if self.stage == self.target {
+            self.stage = 0;
+            self.out = (self.out + 1) & 0xf;
+        }
+    }
+}
+
+impl Value for Timer {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.value(&mut self.enable);
+        s.value(&mut self.target);
+        s.value(&mut self.stage);
+        s.value(&mut self.out);
+    }
+}
+
+impl Apu {
+    // timer 2 runs at 64khz, timers 0 and 1 at 8khz
+    const TIMER_FAST_CYCLES: u32 = 16;
+    const TIMER_SLOW_CYCLES: u32 = 128;
+    const SAMPLE_CYCLES: u32 = 32;
+
+    const IPL_START: u16 = 0xffc0;
+    const IPL_ROM: [u8; 0x40] = [
+        0xcd, 0xef, 0xbd, 0xe8, 0x00, 0xc6, 0x1d, 0xd0, 0xfc, 0x8f, 0xaa, 0xf4, 0x8f, 0xbb, 0xf5, 0x78,
+        0xcc, 0xf4, 0xd0, 0xfb, 0x2f, 0x19, 0xeb, 0xf4, 0xd0, 0xfc, 0x7e, 0xf4, 0xd0, 0x0b, 0xe4, 0xf5,
+        0xcb, 0xf4, 0xd7, 0x00, 0xfc, 0xd0, 0xf3, 0xab, 0x01, 0x10, 0xef, 0x7e, 0xf4, 0x10, 0xeb, 0xba,
+        0xf6, 0xda, 0x00, 0xba, 0xf4, 0xc4, 0xf4, 0xdd, 0x5d, 0xd0, 0xdb, 0x1f, 0x00, 0x00, 0xc0, 0xff,
+    ];
+
+    pub fn new() -> Self {
+        Self {
+            aram: Box::new([0; _]),
+            ipl_enable: true,
+            dsp_addr: 0,
+            dsp: Dsp::new(),
+            cpu_in: [0; _],
+            cpu_out: [0; _],
+            timers: [Timer::default(); _],
+            divider: 0,
+        }
+    }
+
+    pub fn state(&mut self, s: &mut Serializer) {
+        s.section(*b"APU ", |s| {
+            s.value(&mut self.aram);
+            s.value(&mut self.ipl_enable);
+            s.value(&mut self.dsp_addr);
+            s.value(&mut self.cpu_in);
+            s.value(&mut self.cpu_out);
+            s.value(&mut self.timers);
+            s.value(&mut self.divider);
+            s.check(self.divider < Self::TIMER_SLOW_CYCLES);
+        });
+        self.dsp.state(s);
+    }
+
+    // advances the timers and dsp by a number of spc cycles
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.divider += 1;
+            if self.divider.is_multiple_of(Self::TIMER_FAST_CYCLES) {
+                self.timers[2].step();
+            }
+            if self.divider.is_multiple_of(Self::SAMPLE_CYCLES) {
+                self.dsp.run(&mut self.aram);
+            }
+            if self.divider == Self::TIMER_SLOW_CYCLES {
+                self.divider = 0;
+                self.timers[0].step();
+                self.timers[1].step();
+            }
+        }
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            // control, test and the timer targets are write only
+            0x00f0 | 0x00f1 | 0x00fa..=0x00fc => 0,
+            0x00f2 => self.dsp_addr,
+            0x00f3 => self.dsp.read(self.dsp_addr),
+            0x00f4..=0x00f7 => self.cpu_in[(addr & 3) as usize],
+            0x00fd..=0x00ff => {
+                // reading a timer output clears it
+                let timer = &mut self.timers[(addr - 0xfd) as usize];
+                let out = timer.out;
+                timer.out = 0;
+                out
+            },
+            Self::IPL_START.. if self.ipl_enable => Self::IPL_ROM[(addr - Self::IPL_START) as usize],
+            _ => self.aram[addr as usize],
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x00f0 => {}, // test
+            0x00f1 => self.write_control(value),
+            0x00f2 => self.dsp_addr = value,
+            0x00f3 => {
+                // $80-$ff mirror $00-$7f for reads, but writes there are ignored
+                if self.dsp_addr < 0x80 {
+                    self.dsp.write(self.dsp_addr, value)
+                }
+            },
+            0x00f4..=0x00f7 => self.cpu_out[(addr & 3) as usize] = value,
+            0x00fa..=0x00fc => self.timers[(addr - 0xfa) as usize].target = value,
+            0x00fd..=0x00ff => {},
+            // writes always reach the ram, even under the ipl rom
+            _ => self.aram[addr as usize] = value,
+        }
+    }
+
+    fn write_control(&mut self, value: u8) {
+        for (index, timer) in self.timers.iter_mut().enumerate() {
+            let enable = (value & (1 << index)) != 0;
+            // starting a timer resets its counters
+            if enable && !timer.enable {
+                timer.stage = 0;
+                timer.out = 0;
+            }
+            timer.enable = enable;
+        }
+        if (value & 0x10) != 0 {
+            self.cpu_in[0] = 0;
+            self.cpu_in[1] = 0;
+        }
+        if (value & 0x20) != 0 {
+            self.cpu_in[2] = 0;
+            self.cpu_in[3] = 0;
+        }
+        self.ipl_enable = (value & 0x80) != 0;
+    }
+
+    // the cpu side of the communication ports
+    #[inline]
+    pub const fn read_port(&self, index: usize) -> u8 {
+        self.cpu_out[index]
+    }
+    #[inline]
+    pub const fn write_port(&mut self, index: usize, value: u8) {
+        self.cpu_in[index] = value
+    }
+
+    // interleaved stereo samples from the dsp
+    #[inline]
+    pub fn samples(&self) -> &[i16] {
+        self.dsp.samples()
+    }
+    #[inline]
+    pub fn clear_samples(&mut self) {
+        self.dsp.clear_samples()
+    }
+}
@@ -1,4 +1,41 @@
-use crate::mem::Mem;
+use crate::{mem::Mem, state::Serializer};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Interrupt {
+    Cop,
+    Brk,
+    Abort,
+    Nmi,
+    Irq,
+}
+
+impl Interrupt {
+    #[inline]
+    const fn vector(self, emulation_mode: bool) -> u32 {
+        if emulation_mode {
+            match self {
+                Self::Cop => 0xfff4,
+                Self::Abort => 0xfff8,
+                Self::Nmi => 0xfffa,
+                // brk shares the irq vector in emulation mode
+                Self::Brk | Self::Irq => 0xfffe,
+            }
+        } else {
+            match self {
+                Self::Cop => 0xffe4,
+                Self::Brk => 0xffe6,
+                Self::Abort => 0xffe8,
+                Self::Nmi => 0xffea,
+                Self::Irq => 0xffee,
+            }
+        }
+    }
+
+    #[inline]
+    const fn is_software(self) -> bool {
+        matches!(self, Self::Cop | Self::Brk)
+    }
+}
 
 pub struct Cpu {
     // cpu state
@@ -12,6 +49,14 @@ pub struct Cpu {
     dbr: u8,
     ps: u8,
     emulation_mode: bool,
+    waiting: bool,
+    stopped: bool,
+
+    // interrupt lines
+    nmi_pending: bool,
+    irq_line: bool,
+    // nothing drives the abort pin on the snes, but its vector is still honored
+    abort_pending: bool,
 
     // memory
     mem: Mem,
@@ -29,7 +74,14 @@ impl Cpu {
 
     const RESET_VECTOR: u32 = 0xfffc;
 
-    pub fn new(mem: Mem) -> Cpu {
+    // master cycles per internal operation
+    const IO_CYCLES: u32 = 6;
+
+    // tags direct page and stack addresses, whose word accesses wrap within
+    // bank 0 instead of carrying into bank 1
+    const BANK_0: u32 = 0x1000000;
+
+    pub fn new(mut mem: Mem) -> Cpu {
         Cpu {
             a: 0,
             x: 0,
@@ -39,276 +91,355 @@ impl Cpu {
             pc: mem.read16(Self::RESET_VECTOR),
             k: 0,
             dbr: 0,
-            ps: Self::IRQ_DISABLE,
+            ps: Self::IRQ_DISABLE | Self::INDEX_MODE_8 | Self::MEMORY_MODE_8,
             emulation_mode: true,
+            waiting: false,
+            stopped: false,
+            nmi_pending: false,
+            irq_line: false,
+            abort_pending: false,
             mem,
         }
     }
 
+    // savestate sections for the cpu and everything on its buses
+    pub fn state(&mut self, s: &mut Serializer) {
+        s.section(*b"CPU ", |s| {
+            s.value(&mut self.a);
+            s.value(&mut self.x);
+            s.value(&mut self.y);
+            s.value(&mut self.sp);
+            s.value(&mut self.d);
+            s.value(&mut self.pc);
+            s.value(&mut self.k);
+            s.value(&mut self.dbr);
+            s.value(&mut self.ps);
+            s.value(&mut self.emulation_mode);
+            s.value(&mut self.waiting);
+            s.value(&mut self.stopped);
+            s.value(&mut self.nmi_pending);
+            s.value(&mut self.irq_line);
+            s.value(&mut self.abort_pending);
+        });
+        self.mem.state(s);
+    }
+
     pub fn run(&mut self) {
-        loop {
+        while !self.mem.poll_frame() {
+            self.step()
+        }
+    }
+
+    fn step(&mut self) {
+        if self.mem.poll_nmi() {
+            self.nmi()
+        }
+        self.set_irq(self.mem.irq());
+
+        if self.stopped {
+            // only a reset gets the cpu out of stp
+            self.idle();
+            return
+        }
+
+        if self.abort_pending {
+            self.abort_pending = false;
+            self.waiting = false;
+            self.interrupt(Interrupt::Abort)
+        } else if self.nmi_pending {
+            self.nmi_pending = false;
+            self.waiting = false;
+            self.interrupt(Interrupt::Nmi)
+        } else if self.irq_line && (self.waiting || !self.irq_disable()) {
+            // wai resumes on irq even when it's masked, it just doesn't take the vector
+            self.waiting = false;
+            if !self.irq_disable() {
+                self.interrupt(Interrupt::Irq)
+            }
+        } else if self.waiting {
+            self.idle()
+        } else {
             self.exec()
         }
     }
 
+    #[inline]
+    pub const fn mem(&self) -> &Mem {
+        &self.mem
+    }
+    #[inline]
+    pub const fn mem_mut(&mut self) -> &mut Mem {
+        &mut self.mem
+    }
+
+    // interrupt lines
+    #[inline]
+    pub fn nmi(&mut self) {
+        self.nmi_pending = true
+    }
+    #[inline]
+    pub fn set_irq(&mut self, irq: bool) {
+        self.irq_line = irq
+    }
+
     fn exec(&mut self) {
         match self.read_code() {
-            0x00 => panic!("unknown opcode 0x00"),
-            0x01 => panic!("unknown opcode 0x01"),
-            0x02 => panic!("unknown opcode 0x02"),
-            0x03 => panic!("unknown opcode 0x03"),
-            0x04 => panic!("unknown opcode 0x04"),
-            0x05 => panic!("unknown opcode 0x05"),
-            0x06 => panic!("unknown opcode 0x06"),
-            0x07 => panic!("unknown opcode 0x07"),
-            0x08 => panic!("unknown opcode 0x08"),
-            0x09 => panic!("unknown opcode 0x09"),
-            0x0a => panic!("unknown opcode 0x0a"),
-            0x0b => panic!("unknown opcode 0x0b"),
-            0x0c => panic!("unknown opcode 0x0c"),
-            0x0d => panic!("unknown opcode 0x0d"),
-            0x0e => panic!("unknown opcode 0x0e"),
-            0x0f => panic!("unknown opcode 0x0f"),
+            0x00 => { let rhs = self.read_code(); self.brk(rhs) },
+            0x01 => { let addr = self.read_addr_dir_idx_x_ind(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x02 => { let rhs = self.read_code(); self.cop(rhs) },
+            0x03 => { let addr = self.read_addr_stk_rel(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x04 => { let addr = self.read_addr_dir(); self.modify_m(addr, Self::tsb) },
+            0x05 => { let addr = self.read_addr_dir(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x06 => { let addr = self.read_addr_dir(); self.modify_m(addr, Self::asl) },
+            0x07 => { let addr = self.read_addr_dir_ind_lng(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x08 => self.php(),
+            0x09 => { let rhs = self.read_imm_m(); self.ora(rhs) },
+            0x0a => self.asl_a(),
+            0x0b => self.phd(),
+            0x0c => { let addr = self.read_addr_abs(); self.modify_m(addr, Self::tsb) },
+            0x0d => { let addr = self.read_addr_abs(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x0e => { let addr = self.read_addr_abs(); self.modify_m(addr, Self::asl) },
+            0x0f => { let addr = self.read_addr_abs_lng(); let rhs = self.read_m(addr); self.ora(rhs) },
             0x10 => { let rhs = self.read_code(); self.bpl(rhs) },
-            0x11 => panic!("unknown opcode 0x11"),
-            0x12 => panic!("unknown opcode 0x12"),
-            0x13 => panic!("unknown opcode 0x13"),
-            0x14 => panic!("unknown opcode 0x14"),
-            0x15 => panic!("unknown opcode 0x15"),
-            0x16 => panic!("unknown opcode 0x16"),
-            0x17 => panic!("unknown opcode 0x17"),
+            0x11 => { let addr = self.read_addr_dir_ind_idx_y(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x12 => { let addr = self.read_addr_dir_ind(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x13 => { let addr = self.read_addr_stk_rel_ind_idx_y(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x14 => { let addr = self.read_addr_dir(); self.modify_m(addr, Self::trb) },
+            0x15 => { let addr = self.read_addr_dir_idx_x(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x16 => { let addr = self.read_addr_dir_idx_x(); self.modify_m(addr, Self::asl) },
+            0x17 => { let addr = self.read_addr_dir_ind_lng_idx_y(); let rhs = self.read_m(addr); self.ora(rhs) },
             0x18 => self.clc(),
-            0x19 => panic!("unknown opcode 0x19"),
-            0x1a => panic!("unknown opcode 0x1a"),
-            0x1b => panic!("unknown opcode 0x1b"),
-            0x1c => panic!("unknown opcode 0x1c"),
-            0x1d => panic!("unknown opcode 0x1d"),
-            0x1e => panic!("unknown opcode 0x1e"),
-            0x1f => panic!("unknown opcode 0x1f"),
+            0x19 => { let addr = self.read_addr_abs_idx_y(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x1a => self.inc_a(),
+            0x1b => self.tcs(),
+            0x1c => { let addr = self.read_addr_abs(); self.modify_m(addr, Self::trb) },
+            0x1d => { let addr = self.read_addr_abs_idx_x(); let rhs = self.read_m(addr); self.ora(rhs) },
+            0x1e => { let addr = self.read_addr_abs_idx_x(); self.modify_m(addr, Self::asl) },
+            0x1f => { let addr = self.read_addr_abs_lng_idx_x(); let rhs = self.read_m(addr); self.ora(rhs) },
             0x20 => { let rhs = self.read_code_word(); self.jsr(rhs) },
-            0x21 => panic!("unknown opcode 0x21"),
+            0x21 => { let addr = self.read_addr_dir_idx_x_ind(); let rhs = self.read_m(addr); self.and(rhs) },
             0x22 => { let rhs = self.read_code_long(); self.jsl(rhs) },
-            0x23 => panic!("unknown opcode 0x23"),
-            0x24 => panic!("unknown opcode 0x24"),
-            0x25 => panic!("unknown opcode 0x25"),
-            0x26 => panic!("unknown opcode 0x26"),
-            0x27 => panic!("unknown opcode 0x27"),
-            0x28 => panic!("unknown opcode 0x28"),
-            0x29 => panic!("unknown opcode 0x29"),
-            0x2a => panic!("unknown opcode 0x2a"),
-            0x2b => panic!("unknown opcode 0x2b"),
-            0x2c => panic!("unknown opcode 0x2c"),
-            0x2d => panic!("unknown opcode 0x2d"),
-            0x2e => panic!("unknown opcode 0x2e"),
-            0x2f => panic!("unknown opcode 0x2f"),
+            0x23 => { let addr = self.read_addr_stk_rel(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x24 => { let addr = self.read_addr_dir(); let rhs = self.read_m(addr); self.bit(rhs) },
+            0x25 => { let addr = self.read_addr_dir(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x26 => { let addr = self.read_addr_dir(); self.modify_m(addr, Self::rol) },
+            0x27 => { let addr = self.read_addr_dir_ind_lng(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x28 => self.plp(),
+            0x29 => { let rhs = self.read_imm_m(); self.and(rhs) },
+            0x2a => self.rol_a(),
+            0x2b => self.pld(),
+            0x2c => { let addr = self.read_addr_abs(); let rhs = self.read_m(addr); self.bit(rhs) },
+            0x2d => { let addr = self.read_addr_abs(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x2e => { let addr = self.read_addr_abs(); self.modify_m(addr, Self::rol) },
+            0x2f => { let addr = self.read_addr_abs_lng(); let rhs = self.read_m(addr); self.and(rhs) },
             0x30 => { let rhs = self.read_code(); self.bmi(rhs) },
-            0x31 => panic!("unknown opcode 0x31"),
-            0x32 => panic!("unknown opcode 0x32"),
-            0x33 => panic!("unknown opcode 0x33"),
-            0x34 => panic!("unknown opcode 0x34"),
-            0x35 => panic!("unknown opcode 0x35"),
-            0x36 => panic!("unknown opcode 0x36"),
-            0x37 => panic!("unknown opcode 0x37"),
+            0x31 => { let addr = self.read_addr_dir_ind_idx_y(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x32 => { let addr = self.read_addr_dir_ind(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x33 => { let addr = self.read_addr_stk_rel_ind_idx_y(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x34 => { let addr = self.read_addr_dir_idx_x(); let rhs = self.read_m(addr); self.bit(rhs) },
+            0x35 => { let addr = self.read_addr_dir_idx_x(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x36 => { let addr = self.read_addr_dir_idx_x(); self.modify_m(addr, Self::rol) },
+            0x37 => { let addr = self.read_addr_dir_ind_lng_idx_y(); let rhs = self.read_m(addr); self.and(rhs) },
             0x38 => self.sec(),
-            0x39 => panic!("unknown opcode 0x39"),
-            0x3a => panic!("unknown opcode 0x3a"),
-            0x3b => panic!("unknown opcode 0x3b"),
-            0x3c => panic!("unknown opcode 0x3c"),
-            0x3d => panic!("unknown opcode 0x3d"),
-            0x3e => panic!("unknown opcode 0x3e"),
-            0x3f => panic!("unknown opcode 0x3f"),
-            0x40 => panic!("unknown opcode 0x40"),
-            0x41 => panic!("unknown opcode 0x41"),
-            0x42 => panic!("unknown opcode 0x42"),
-            0x43 => panic!("unknown opcode 0x43"),
-            0x44 => panic!("unknown opcode 0x44"),
-            0x45 => panic!("unknown opcode 0x45"),
-            0x46 => panic!("unknown opcode 0x46"),
-            0x47 => panic!("unknown opcode 0x47"),
-            0x48 => panic!("unknown opcode 0x48"),
-            0x49 => panic!("unknown opcode 0x49"),
-            0x4a => panic!("unknown opcode 0x4a"),
-            0x4b => panic!("unknown opcode 0x4b"),
-            0x4c => panic!("unknown opcode 0x4c"),
-            0x4d => panic!("unknown opcode 0x4d"),
-            0x4e => panic!("unknown opcode 0x4e"),
-            0x4f => panic!("unknown opcode 0x4f"),
+            0x39 => { let addr = self.read_addr_abs_idx_y(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x3a => self.dec_a(),
+            0x3b => self.tsc(),
+            0x3c => { let addr = self.read_addr_abs_idx_x(); let rhs = self.read_m(addr); self.bit(rhs) },
+            0x3d => { let addr = self.read_addr_abs_idx_x(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x3e => { let addr = self.read_addr_abs_idx_x(); self.modify_m(addr, Self::rol) },
+            0x3f => { let addr = self.read_addr_abs_lng_idx_x(); let rhs = self.read_m(addr); self.and(rhs) },
+            0x40 => self.rti(),
+            0x41 => { let addr = self.read_addr_dir_idx_x_ind(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x42 => { let rhs = self.read_code(); self.wdm(rhs) },
+            0x43 => { let addr = self.read_addr_stk_rel(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x44 => { let dst = self.read_code(); let src = self.read_code(); self.mvp(dst, src) },
+            0x45 => { let addr = self.read_addr_dir(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x46 => { let addr = self.read_addr_dir(); self.modify_m(addr, Self::lsr) },
+            0x47 => { let addr = self.read_addr_dir_ind_lng(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x48 => self.pha(),
+            0x49 => { let rhs = self.read_imm_m(); self.eor(rhs) },
+            0x4a => self.lsr_a(),
+            0x4b => self.phk(),
+            0x4c => { let rhs = self.read_code_word(); self.jmp(rhs) },
+            0x4d => { let addr = self.read_addr_abs(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x4e => { let addr = self.read_addr_abs(); self.modify_m(addr, Self::lsr) },
+            0x4f => { let addr = self.read_addr_abs_lng(); let rhs = self.read_m(addr); self.eor(rhs) },
             0x50 => { let rhs = self.read_code(); self.bvc(rhs) },
-            0x51 => panic!("unknown opcode 0x51"),
-            0x52 => panic!("unknown opcode 0x52"),
-            0x53 => panic!("unknown opcode 0x53"),
-            0x54 => panic!("unknown opcode 0x54"),
-            0x55 => panic!("unknown opcode 0x55"),
-            0x56 => panic!("unknown opcode 0x56"),
-            0x57 => panic!("unknown opcode 0x57"),
+            0x51 => { let addr = self.read_addr_dir_ind_idx_y(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x52 => { let addr = self.read_addr_dir_ind(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x53 => { let addr = self.read_addr_stk_rel_ind_idx_y(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x54 => { let dst = self.read_code(); let src = self.read_code(); self.mvn(dst, src) },
+            0x55 => { let addr = self.read_addr_dir_idx_x(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x56 => { let addr = self.read_addr_dir_idx_x(); self.modify_m(addr, Self::lsr) },
+            0x57 => { let addr = self.read_addr_dir_ind_lng_idx_y(); let rhs = self.read_m(addr); self.eor(rhs) },
             0x58 => self.cli(),
-            0x59 => panic!("unknown opcode 0x59"),
-            0x5a => panic!("unknown opcode 0x5a"),
-            0x5b => panic!("unknown opcode 0x5b"),
-            0x5c => panic!("unknown opcode 0x5c"),
-            0x5d => panic!("unknown opcode 0x5d"),
-            0x5e => panic!("unknown opcode 0x5e"),
-            0x5f => panic!("unknown opcode 0x5f"),
+            0x59 => { let addr = self.read_addr_abs_idx_y(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x5a => self.phy(),
+            0x5b => self.tcd(),
+            0x5c => { let rhs = self.read_addr_abs_lng(); self.jml(rhs) },
+            0x5d => { let addr = self.read_addr_abs_idx_x(); let rhs = self.read_m(addr); self.eor(rhs) },
+            0x5e => { let addr = self.read_addr_abs_idx_x(); self.modify_m(addr, Self::lsr) },
+            0x5f => { let addr = self.read_addr_abs_lng_idx_x(); let rhs = self.read_m(addr); self.eor(rhs) },
             0x60 => self.rts(),
-            0x61 => panic!("unknown opcode 0x61"),
-            0x62 => panic!("unknown opcode 0x62"),
-            0x63 => panic!("unknown opcode 0x63"),
-            0x64 => panic!("unknown opcode 0x64"),
-            0x65 => panic!("unknown opcode 0x65"),
-            0x66 => panic!("unknown opcode 0x66"),
-            0x67 => panic!("unknown opcode 0x67"),
-            0x68 => panic!("unknown opcode 0x68"),
-            0x69 => panic!("unknown opcode 0x69"),
-            0x6a => panic!("unknown opcode 0x6a"),
+            0x61 => { let addr = self.read_addr_dir_idx_x_ind(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x62 => { let rhs = self.read_code_word(); self.per(rhs) },
+            0x63 => { let addr = self.read_addr_stk_rel(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x64 => { let addr = self.read_addr_dir(); self.stz(addr) },
+            0x65 => { let addr = self.read_addr_dir(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x66 => { let addr = self.read_addr_dir(); self.modify_m(addr, Self::ror) },
+            0x67 => { let addr = self.read_addr_dir_ind_lng(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x68 => self.pla(),
+            0x69 => { let rhs = self.read_imm_m(); self.adc(rhs) },
+            0x6a => self.ror_a(),
             0x6b => self.rtl(),
-            0x6c => panic!("unknown opcode 0x6c"),
-            0x6d => panic!("unknown opcode 0x6d"),
-            0x6e => panic!("unknown opcode 0x6e"),
-            0x6f => panic!("unknown opcode 0x6f"),
+            0x6c => { let rhs = self.read_addr_abs_ind(); self.jmp(rhs as u16) },
+            0x6d => { let addr = self.read_addr_abs(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x6e => { let addr = self.read_addr_abs(); self.modify_m(addr, Self::ror) },
+            0x6f => { let addr = self.read_addr_abs_lng(); let rhs = self.read_m(addr); self.adc(rhs) },
             0x70 => { let rhs = self.read_code(); self.bvs(rhs) },
-            0x71 => panic!("unknown opcode 0x71"),
-            0x72 => panic!("unknown opcode 0x72"),
-            0x73 => panic!("unknown opcode 0x73"),
-            0x74 => panic!("unknown opcode 0x74"),
-            0x75 => panic!("unknown opcode 0x75"),
-            0x76 => panic!("unknown opcode 0x76"),
-            0x77 => panic!("unknown opcode 0x77"),
+            0x71 => { let addr = self.read_addr_dir_ind_idx_y(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x72 => { let addr = self.read_addr_dir_ind(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x73 => { let addr = self.read_addr_stk_rel_ind_idx_y(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x74 => { let addr = self.read_addr_dir_idx_x(); self.stz(addr) },
+            0x75 => { let addr = self.read_addr_dir_idx_x(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x76 => { let addr = self.read_addr_dir_idx_x(); self.modify_m(addr, Self::ror) },
+            0x77 => { let addr = self.read_addr_dir_ind_lng_idx_y(); let rhs = self.read_m(addr); self.adc(rhs) },
             0x78 => self.sei(),
-            0x79 => panic!("unknown opcode 0x79"),
-            0x7a => panic!("unknown opcode 0x7a"),
-            0x7b => panic!("unknown opcode 0x7b"),
-            0x7c => panic!("unknown opcode 0x7c"),
-            0x7d => panic!("unknown opcode 0x7d"),
-            0x7e => panic!("unknown opcode 0x7e"),
-            0x7f => panic!("unknown opcode 0x7f"),
+            0x79 => { let addr = self.read_addr_abs_idx_y(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x7a => self.ply(),
+            0x7b => self.tdc(),
+            0x7c => { let rhs = self.read_addr_abs_idx_x_ind(); self.jmp(rhs as u16) },
+            0x7d => { let addr = self.read_addr_abs_idx_x(); let rhs = self.read_m(addr); self.adc(rhs) },
+            0x7e => { let addr = self.read_addr_abs_idx_x(); self.modify_m(addr, Self::ror) },
+            0x7f => { let addr = self.read_addr_abs_lng_idx_x(); let rhs = self.read_m(addr); self.adc(rhs) },
             0x80 => { let rhs = self.read_code(); self.bra(rhs) },
-            0x81 => panic!("unknown opcode 0x81"),
+            0x81 => { let addr = self.read_addr_dir_idx_x_ind(); self.sta(addr) },
             0x82 => { let rhs = self.read_code_word(); self.brl(rhs) },
-            0x83 => panic!("unknown opcode 0x83"),
-            0x84 => panic!("unknown opcode 0x84"),
-            0x85 => panic!("unknown opcode 0x85"),
-            0x86 => panic!("unknown opcode 0x86"),
-            0x87 => panic!("unknown opcode 0x87"),
-            0x88 => panic!("unknown opcode 0x88"),
-            0x89 => panic!("unknown opcode 0x89"),
-            0x8a => panic!("unknown opcode 0x8a"),
-            0x8b => panic!("unknown opcode 0x8b"),
-            0x8c => panic!("unknown opcode 0x8c"),
-            0x8d => panic!("unknown opcode 0x8d"),
-            0x8e => panic!("unknown opcode 0x8e"),
-            0x8f => panic!("unknown opcode 0x8f"),
+            0x83 => { let addr = self.read_addr_stk_rel(); self.sta(addr) },
+            0x84 => { let addr = self.read_addr_dir(); self.sty(addr) },
+            0x85 => { let addr = self.read_addr_dir(); self.sta(addr) },
+            0x86 => { let addr = self.read_addr_dir(); self.stx(addr) },
+            0x87 => { let addr = self.read_addr_dir_ind_lng(); self.sta(addr) },
+            0x88 => self.dey(),
+            0x89 => { let rhs = self.read_imm_m(); self.bit_imm(rhs) },
+            0x8a => self.txa(),
+            0x8b => self.phb(),
+            0x8c => { let addr = self.read_addr_abs(); self.sty(addr) },
+            0x8d => { let addr = self.read_addr_abs(); self.sta(addr) },
+            0x8e => { let addr = self.read_addr_abs(); self.stx(addr) },
+            0x8f => { let addr = self.read_addr_abs_lng(); self.sta(addr) },
             0x90 => { let rhs = self.read_code(); self.bcc(rhs) },
-            0x91 => panic!("unknown opcode 0x91"),
-            0x92 => panic!("unknown opcode 0x92"),
-            0x93 => panic!("unknown opcode 0x93"),
-            0x94 => panic!("unknown opcode 0x94"),
-            0x95 => panic!("unknown opcode 0x95"),
-            0x96 => panic!("unknown opcode 0x96"),
-            0x97 => panic!("unknown opcode 0x97"),
-            0x98 => panic!("unknown opcode 0x98"),
-            0x99 => panic!("unknown opcode 0x99"),
-            0x9a => panic!("unknown opcode 0x9a"),
-            0x9b => panic!("unknown opcode 0x9b"),
-            0x9c => panic!("unknown opcode 0x9c"),
-            0x9d => panic!("unknown opcode 0x9d"),
-            0x9e => panic!("unknown opcode 0x9e"),
-            0x9f => panic!("unknown opcode 0x9f"),
-            0xa0 => panic!("unknown opcode 0xa0"),
-            0xa1 => panic!("unknown opcode 0xa1"),
-            0xa2 => panic!("unknown opcode 0xa2"),
-            0xa3 => panic!("unknown opcode 0xa3"),
-            0xa4 => panic!("unknown opcode 0xa4"),
-            0xa5 => panic!("unknown opcode 0xa5"),
-            0xa6 => panic!("unknown opcode 0xa6"),
-            0xa7 => panic!("unknown opcode 0xa7"),
-            0xa8 => panic!("unknown opcode 0xa8"),
-            0xa9 => panic!("unknown opcode 0xa9"),
-            0xaa => panic!("unknown opcode 0xaa"),
-            0xab => panic!("unknown opcode 0xab"),
-            0xac => panic!("unknown opcode 0xac"),
-            0xad => panic!("unknown opcode 0xad"),
-            0xae => panic!("unknown opcode 0xae"),
-            0xaf => panic!("unknown opcode 0xaf"),
+            0x91 => { let addr = self.read_addr_dir_ind_idx_y(); self.sta(addr) },
+            0x92 => { let addr = self.read_addr_dir_ind(); self.sta(addr) },
+            0x93 => { let addr = self.read_addr_stk_rel_ind_idx_y(); self.sta(addr) },
+            0x94 => { let addr = self.read_addr_dir_idx_x(); self.sty(addr) },
+            0x95 => { let addr = self.read_addr_dir_idx_x(); self.sta(addr) },
+            0x96 => { let addr = self.read_addr_dir_idx_y(); self.stx(addr) },
+            0x97 => { let addr = self.read_addr_dir_ind_lng_idx_y(); self.sta(addr) },
+            0x98 => self.tya(),
+            0x99 => { let addr = self.read_addr_abs_idx_y(); self.sta(addr) },
+            0x9a => self.txs(),
+            0x9b => self.txy(),
+            0x9c => { let addr = self.read_addr_abs(); self.stz(addr) },
+            0x9d => { let addr = self.read_addr_abs_idx_x(); self.sta(addr) },
+            0x9e => { let addr = self.read_addr_abs_idx_x(); self.stz(addr) },
+            0x9f => { let addr = self.read_addr_abs_lng_idx_x(); self.sta(addr) },
+            0xa0 => { let rhs = self.read_imm_x(); self.ldy(rhs) },
+            0xa1 => { let addr = self.read_addr_dir_idx_x_ind(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xa2 => { let rhs = self.read_imm_x(); self.ldx(rhs) },
+            0xa3 => { let addr = self.read_addr_stk_rel(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xa4 => { let addr = self.read_addr_dir(); let rhs = self.read_x(addr); self.ldy(rhs) },
+            0xa5 => { let addr = self.read_addr_dir(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xa6 => { let addr = self.read_addr_dir(); let rhs = self.read_x(addr); self.ldx(rhs) },
+            0xa7 => { let addr = self.read_addr_dir_ind_lng(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xa8 => self.tay(),
+            0xa9 => { let rhs = self.read_imm_m(); self.lda(rhs) },
+            0xaa => self.tax(),
+            0xab => self.plb(),
+            0xac => { let addr = self.read_addr_abs(); let rhs = self.read_x(addr); self.ldy(rhs) },
+            0xad => { let addr = self.read_addr_abs(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xae => { let addr = self.read_addr_abs(); let rhs = self.read_x(addr); self.ldx(rhs) },
+            0xaf => { let addr = self.read_addr_abs_lng(); let rhs = self.read_m(addr); self.lda(rhs) },
             0xb0 => { let rhs = self.read_code(); self.bcs(rhs) },
-            0xb1 => panic!("unknown opcode 0xb1"),
-            0xb2 => panic!("unknown opcode 0xb2"),
-            0xb3 => panic!("unknown opcode 0xb3"),
-            0xb4 => panic!("unknown opcode 0xb4"),
-            0xb5 => panic!("unknown opcode 0xb5"),
-            0xb6 => panic!("unknown opcode 0xb6"),
-            0xb7 => panic!("unknown opcode 0xb7"),
+            0xb1 => { let addr = self.read_addr_dir_ind_idx_y(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xb2 => { let addr = self.read_addr_dir_ind(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xb3 => { let addr = self.read_addr_stk_rel_ind_idx_y(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xb4 => { let addr = self.read_addr_dir_idx_x(); let rhs = self.read_x(addr); self.ldy(rhs) },
+            0xb5 => { let addr = self.read_addr_dir_idx_x(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xb6 => { let addr = self.read_addr_dir_idx_y(); let rhs = self.read_x(addr); self.ldx(rhs) },
+            0xb7 => { let addr = self.read_addr_dir_ind_lng_idx_y(); let rhs = self.read_m(addr); self.lda(rhs) },
             0xb8 => self.clv(),
-            0xb9 => panic!("unknown opcode 0xb9"),
-            0xba => panic!("unknown opcode 0xba"),
-            0xbb => panic!("unknown opcode 0xbb"),
-            0xbc => panic!("unknown opcode 0xbc"),
-            0xbd => panic!("unknown opcode 0xbd"),
-            0xbe => panic!("unknown opcode 0xbe"),
-            0xbf => panic!("unknown opcode 0xbf"),
-            0xc0 => panic!("unknown opcode 0xc0"),
-            0xc1 => panic!("unknown opcode 0xc1"),
+            0xb9 => { let addr = self.read_addr_abs_idx_y(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xba => self.tsx(),
+            0xbb => self.tyx(),
+            0xbc => { let addr = self.read_addr_abs_idx_x(); let rhs = self.read_x(addr); self.ldy(rhs) },
+            0xbd => { let addr = self.read_addr_abs_idx_x(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xbe => { let addr = self.read_addr_abs_idx_y(); let rhs = self.read_x(addr); self.ldx(rhs) },
+            0xbf => { let addr = self.read_addr_abs_lng_idx_x(); let rhs = self.read_m(addr); self.lda(rhs) },
+            0xc0 => { let rhs = self.read_imm_x(); self.cpy(rhs) },
+            0xc1 => { let addr = self.read_addr_dir_idx_x_ind(); let rhs = self.read_m(addr); self.cmp(rhs) },
             0xc2 => { let rhs = self.read_code(); self.rep(rhs) },
-            0xc3 => panic!("unknown opcode 0xc3"),
-            0xc4 => panic!("unknown opcode 0xc4"),
-            0xc5 => panic!("unknown opcode 0xc5"),
-            0xc6 => panic!("unknown opcode 0xc6"),
-            0xc7 => panic!("unknown opcode 0xc7"),
-            0xc8 => panic!("unknown opcode 0xc8"),
-            0xc9 => panic!("unknown opcode 0xc9"),
-            0xca => panic!("unknown opcode 0xca"),
-            0xcb => panic!("unknown opcode 0xcb"),
-            0xcc => panic!("unknown opcode 0xcc"),
-            0xcd => panic!("unknown opcode 0xcd"),
-            0xce => panic!("unknown opcode 0xce"),
-            0xcf => panic!("unknown opcode 0xcf"),
+            0xc3 => { let addr = self.read_addr_stk_rel(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xc4 => { let addr = self.read_addr_dir(); let rhs = self.read_x(addr); self.cpy(rhs) },
+            0xc5 => { let addr = self.read_addr_dir(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xc6 => { let addr = self.read_addr_dir(); self.modify_m(addr, Self::dec) },
+            0xc7 => { let addr = self.read_addr_dir_ind_lng(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xc8 => self.iny(),
+            0xc9 => { let rhs = self.read_imm_m(); self.cmp(rhs) },
+            0xca => self.dex(),
+            0xcb => self.wai(),
+            0xcc => { let addr = self.read_addr_abs(); let rhs = self.read_x(addr); self.cpy(rhs) },
+            0xcd => { let addr = self.read_addr_abs(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xce => { let addr = self.read_addr_abs(); self.modify_m(addr, Self::dec) },
+            0xcf => { let addr = self.read_addr_abs_lng(); let rhs = self.read_m(addr); self.cmp(rhs) },
             0xd0 => { let rhs = self.read_code(); self.bne(rhs) },
-            0xd1 => panic!("unknown opcode 0xd1"),
-            0xd2 => panic!("unknown opcode 0xd2"),
-            0xd3 => panic!("unknown opcode 0xd3"),
-            0xd4 => panic!("unknown opcode 0xd4"),
-            0xd5 => panic!("unknown opcode 0xd5"),
-            0xd6 => panic!("unknown opcode 0xd6"),
-            0xd7 => panic!("unknown opcode 0xd7"),
+            0xd1 => { let addr = self.read_addr_dir_ind_idx_y(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xd2 => { let addr = self.read_addr_dir_ind(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xd3 => { let addr = self.read_addr_stk_rel_ind_idx_y(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xd4 => { let addr = self.read_code() as u16; self.idle_direct(); self.pei(addr) },
+            0xd5 => { let addr = self.read_addr_dir_idx_x(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xd6 => { let addr = self.read_addr_dir_idx_x(); self.modify_m(addr, Self::dec) },
+            0xd7 => { let addr = self.read_addr_dir_ind_lng_idx_y(); let rhs = self.read_m(addr); self.cmp(rhs) },
             0xd8 => self.cld(),
-            0xd9 => panic!("unknown opcode 0xd9"),
-            0xda => panic!("unknown opcode 0xda"),
-            0xdb => panic!("unknown opcode 0xdb"),
-            0xdc => panic!("unknown opcode 0xdc"),
-            0xdd => panic!("unknown opcode 0xdd"),
-            0xde => panic!("unknown opcode 0xde"),
-            0xdf => panic!("unknown opcode 0xdf"),
-            0xe0 => panic!("unknown opcode 0xe0"),
-            0xe1 => panic!("unknown opcode 0xe1"),
+            0xd9 => { let addr = self.read_addr_abs_idx_y(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xda => self.phx(),
+            0xdb => self.stp(),
+            0xdc => { let rhs = self.read_addr_abs_ind_lng(); self.jml(rhs) },
+            0xdd => { let addr = self.read_addr_abs_idx_x(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xde => { let addr = self.read_addr_abs_idx_x(); self.modify_m(addr, Self::dec) },
+            0xdf => { let addr = self.read_addr_abs_lng_idx_x(); let rhs = self.read_m(addr); self.cmp(rhs) },
+            0xe0 => { let rhs = self.read_imm_x(); self.cpx(rhs) },
+            0xe1 => { let addr = self.read_addr_dir_idx_x_ind(); let rhs = self.read_m(addr); self.sbc(rhs) },
             0xe2 => { let rhs = self.read_code(); self.sep(rhs) },
-            0xe3 => panic!("unknown opcode 0xe3"),
-            0xe4 => panic!("unknown opcode 0xe4"),
-            0xe5 => panic!("unknown opcode 0xe5"),
-            0xe6 => panic!("unknown opcode 0xe6"),
-            0xe7 => panic!("unknown opcode 0xe7"),
-            0xe8 => panic!("unknown opcode 0xe8"),
-            0xe9 => panic!("unknown opcode 0xe9"),
-            0xea => panic!("unknown opcode 0xea"),
-            0xeb => panic!("unknown opcode 0xeb"),
-            0xec => panic!("unknown opcode 0xec"),
-            0xed => panic!("unknown opcode 0xed"),
-            0xee => panic!("unknown opcode 0xee"),
-            0xef => panic!("unknown opcode 0xef"),
+            0xe3 => { let addr = self.read_addr_stk_rel(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xe4 => { let addr = self.read_addr_dir(); let rhs = self.read_x(addr); self.cpx(rhs) },
+            0xe5 => { let addr = self.read_addr_dir(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xe6 => { let addr = self.read_addr_dir(); self.modify_m(addr, Self::inc) },
+            0xe7 => { let addr = self.read_addr_dir_ind_lng(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xe8 => self.inx(),
+            0xe9 => { let rhs = self.read_imm_m(); self.sbc(rhs) },
+            0xea => self.nop(),
+            0xeb => self.xba(),
+            0xec => { let addr = self.read_addr_abs(); let rhs = self.read_x(addr); self.cpx(rhs) },
+            0xed => { let addr = self.read_addr_abs(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xee => { let addr = self.read_addr_abs(); self.modify_m(addr, Self::inc) },
+            0xef => { let addr = self.read_addr_abs_lng(); let rhs = self.read_m(addr); self.sbc(rhs) },
             0xf0 => { let rhs = self.read_code(); self.beq(rhs) },
-            0xf1 => panic!("unknown opcode 0xf1"),
-            0xf2 => panic!("unknown opcode 0xf2"),
-            0xf3 => panic!("unknown opcode 0xf3"),
-            0xf4 => panic!("unknown opcode 0xf4"),
-            0xf5 => panic!("unknown opcode 0xf5"),
-            0xf6 => panic!("unknown opcode 0xf6"),
-            0xf7 => panic!("unknown opcode 0xf7"),
+            0xf1 => { let addr = self.read_addr_dir_ind_idx_y(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xf2 => { let addr = self.read_addr_dir_ind(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xf3 => { let addr = self.read_addr_stk_rel_ind_idx_y(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xf4 => { let rhs = self.read_code_word(); self.pea(rhs) },
+            0xf5 => { let addr = self.read_addr_dir_idx_x(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xf6 => { let addr = self.read_addr_dir_idx_x(); self.modify_m(addr, Self::inc) },
+            0xf7 => { let addr = self.read_addr_dir_ind_lng_idx_y(); let rhs = self.read_m(addr); self.sbc(rhs) },
             0xf8 => self.sed(),
-            0xf9 => panic!("unknown opcode 0xf9"),
-            0xfa => panic!("unknown opcode 0xfa"),
+            0xf9 => { let addr = self.read_addr_abs_idx_y(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xfa => self.plx(),
             0xfb => self.xce(),
-            0xfc => panic!("unknown opcode 0xfc"),
-            0xfd => panic!("unknown opcode 0xfd"),
-            0xfe => panic!("unknown opcode 0xfe"),
-            0xff => panic!("unknown opcode 0xff"),
+            0xfc => { let rhs = self.read_addr_abs_idx_x_ind(); self.jsr(rhs as u16) },
+            0xfd => { let addr = self.read_addr_abs_idx_x(); let rhs = self.read_m(addr); self.sbc(rhs) },
+            0xfe => { let addr = self.read_addr_abs_idx_x(); self.modify_m(addr, Self::inc) },
+            0xff => { let addr = self.read_addr_abs_lng_idx_x(); let rhs = self.read_m(addr); self.sbc(rhs) },
         }
     }
 
@@ -422,8 +553,59 @@ impl Cpu {
         self.set_negative((value as i16) < 0);
     }
 
+    #[inline]
+    const fn set_ps(&mut self, ps: u8) {
+        self.ps = ps;
+        if self.emulation_mode {
+            self.ps |= Self::INDEX_MODE_8 | Self::MEMORY_MODE_8;
+        }
+        if self.index_mode_8() {
+            self.x &= 0xff;
+            self.y &= 0xff;
+        }
+    }
+
+    #[inline]
+    const fn set_a(&mut self, value: u16) {
+        if self.memory_mode_8() {
+            self.a = (self.a & 0xff00) | (value & 0xff)
+        } else {
+            self.a = value
+        }
+    }
+
     // memory
     #[inline]
+    fn read(&mut self, addr: u32) -> u8 {
+        let addr = addr & 0xffffff;
+        self.mem.tick(self.mem.speed(addr));
+        self.mem.read(addr)
+    }
+    #[inline]
+    fn write(&mut self, addr: u32, value: u8) {
+        let addr = addr & 0xffffff;
+        self.mem.tick(self.mem.speed(addr));
+        self.mem.write(addr, value)
+    }
+    #[inline]
+    fn idle(&mut self) {
+        self.mem.tick(Self::IO_CYCLES)
+    }
+    #[inline]
+    fn idle_direct(&mut self) {
+        // direct page accesses take an extra cycle when d isn't page aligned
+        if (self.d & 0xff) != 0 {
+            self.idle()
+        }
+    }
+    #[inline]
+    fn idle_index(&mut self, base: u32, addr: u32) {
+        // indexing takes an extra cycle with 16-bit index registers or when crossing a page
+        if !self.index_mode_8() || ((base ^ addr) & 0xff00) != 0 {
+            self.idle()
+        }
+    }
+    #[inline]
     const fn as_code_addr(&self, addr: u16) -> u32 {
         ((self.k as u32) << 16) | addr as u32
     }
@@ -434,14 +616,14 @@ impl Cpu {
     #[inline]
     const fn as_direct_addr(&self, addr: u16) -> u32 {
         if self.emulation_mode && (self.d & 0xff) == 0 {
-            ((self.d & 0xff00) | (addr & 0xff)) as u32
+            Self::BANK_0 | ((self.d & 0xff00) | (addr & 0xff)) as u32
         } else {
-            self.d.wrapping_add(addr) as u32
+            Self::BANK_0 | self.d.wrapping_add(addr) as u32
         }
     }
     #[inline]
     fn read_code(&mut self) -> u8 {
-        let result = self.mem.read(((self.k as u32) << 16) | self.pc as u32);
+        let result = self.read(((self.k as u32) << 16) | self.pc as u32);
         self.pc = self.pc.wrapping_add(1);
         result
     }
@@ -459,12 +641,86 @@ impl Cpu {
         ((b3 as u32) << 16) | ((b2 as u32) << 8) | b1 as u32
     }
     #[inline]
+    fn read_imm_m(&mut self) -> u16 {
+        if self.memory_mode_8() {
+            self.read_code() as u16
+        } else {
+            self.read_code_word()
+        }
+    }
+    #[inline]
+    fn read_imm_x(&mut self) -> u16 {
+        if self.index_mode_8() {
+            self.read_code() as u16
+        } else {
+            self.read_code_word()
+        }
+    }
+    #[inline]
+    const fn next_addr(addr: u32) -> u32 {
+        if (addr & Self::BANK_0) != 0 {
+            (addr as u16).wrapping_add(1) as u32
+        } else {
+            addr.wrapping_add(1) & 0xffffff
+        }
+    }
+    #[inline]
+    fn read_word(&mut self, addr: u32) -> u16 {
+        let lsb = self.read(addr);
+        let msb = self.read(Self::next_addr(addr));
+        ((msb as u16) << 8) | lsb as u16
+    }
+    #[inline]
+    fn write_word(&mut self, addr: u32, value: u16) {
+        self.write(addr, value as u8);
+        self.write(Self::next_addr(addr), (value >> 8) as u8);
+    }
+    #[inline]
+    fn read_m(&mut self, addr: u32) -> u16 {
+        if self.memory_mode_8() {
+            self.read(addr) as u16
+        } else {
+            self.read_word(addr)
+        }
+    }
+    #[inline]
+    fn write_m(&mut self, addr: u32, value: u16) {
+        if self.memory_mode_8() {
+            self.write(addr, value as u8)
+        } else {
+            self.write_word(addr, value)
+        }
+    }
+    #[inline]
+    fn read_x(&mut self, addr: u32) -> u16 {
+        if self.index_mode_8() {
+            self.read(addr) as u16
+        } else {
+            self.read_word(addr)
+        }
+    }
+    #[inline]
+    fn write_x(&mut self, addr: u32, value: u16) {
+        if self.index_mode_8() {
+            self.write(addr, value as u8)
+        } else {
+            self.write_word(addr, value)
+        }
+    }
+    #[inline]
+    fn modify_m(&mut self, addr: u32, f: fn(&mut Self, u16) -> u16) {
+        let value = self.read_m(addr);
+        self.idle();
+        let result = f(self, value);
+        self.write_m(addr, result);
+    }
+    #[inline]
     const fn restrict_sp(&mut self) {
         self.sp = 0x100 | (self.sp & 0xff)
     }
     #[inline]
     fn push_unchecked(&mut self, rhs: u8) {
-        self.mem.write(self.sp as u32, rhs);
+        self.write(self.sp as u32, rhs);
         self.sp = self.sp.wrapping_sub(1);
     }
     #[inline]
@@ -477,15 +733,16 @@ impl Cpu {
     #[inline]
     fn pop_unchecked(&mut self) -> u8 {
         self.sp = self.sp.wrapping_add(1);
-        self.mem.read(self.sp as u32)
+        self.read(self.sp as u32)
     }
     #[inline]
     fn pop(&mut self) -> u8 {
-        let value = self.pop_unchecked();
+        // in emulation mode the read itself wraps within page 1
+        self.sp = self.sp.wrapping_add(1);
         if self.emulation_mode {
             self.restrict_sp()
         }
-        value
+        self.read(self.sp as u32)
     }
     #[inline]
     fn push_word(&mut self, rhs: u16) {
@@ -498,6 +755,15 @@ impl Cpu {
         let hi = self.pop();
         ((hi as u16) << 8) | lo as u16
     }
+    // pea, pei and per don't wrap within page 1 in emulation mode either
+    #[inline]
+    fn push_word_unchecked(&mut self, rhs: u16) {
+        self.push_unchecked((rhs >> 8) as u8);
+        self.push_unchecked(rhs as u8);
+        if self.emulation_mode {
+            self.restrict_sp()
+        }
+    }
     #[inline]
     fn push_long(&mut self, rhs: u32) {
         self.push_unchecked((rhs >> 16) as u8);
@@ -527,12 +793,18 @@ impl Cpu {
     #[inline]
     fn read_addr_abs_idx_x(&mut self) -> u32 {
         let addr = self.read_code_word();
-        self.as_data_addr(addr).wrapping_add(self.x as u32) & 0xffffff
+        let base = self.as_data_addr(addr);
+        let addr = base.wrapping_add(self.x as u32) & 0xffffff;
+        self.idle_index(base, addr);
+        addr
     }
     #[inline]
     fn read_addr_abs_idx_y(&mut self) -> u32 {
         let addr = self.read_code_word();
-        self.as_data_addr(addr).wrapping_add(self.y as u32) & 0xffffff
+        let base = self.as_data_addr(addr);
+        let addr = base.wrapping_add(self.y as u32) & 0xffffff;
+        self.idle_index(base, addr);
+        addr
     }
     #[inline]
     fn read_addr_abs_lng(&mut self) -> u32 {
@@ -544,21 +816,114 @@ impl Cpu {
     }
     #[inline]
     fn read_addr_abs_ind(&mut self) -> u32 {
+        // the pointer is always read from bank 0, but the target is in the program bank
         let addr = self.read_code_word();
-        let lsb = self.mem.read(addr as u32);
-        let msb = self.mem.read(addr.wrapping_add(1) as u32);
-        ((msb as u32) << 8) | lsb as u32
+        let lsb = self.read(addr as u32);
+        let msb = self.read(addr.wrapping_add(1) as u32);
+        self.as_code_addr(((msb as u16) << 8) | lsb as u16)
     }
     #[inline]
     fn read_addr_abs_ind_lng(&mut self) -> u32 {
         let addr = self.read_code_word();
 
-        let b1 = self.mem.read(addr as u32);
-        let b2 = self.mem.read(addr.wrapping_add(1) as u32);
-        let b3 = self.mem.read(addr.wrapping_add(2) as u32);
+        let b1 = self.read(addr as u32);
+        let b2 = self.read(addr.wrapping_add(1) as u32);
+        let b3 = self.read(addr.wrapping_add(2) as u32);
 
         ((b3 as u32) << 16) | ((b2 as u32) << 8) | b1 as u32
     }
+    #[inline]
+    fn read_addr_abs_idx_x_ind(&mut self) -> u32 {
+        // unlike (abs), the pointer is read from the program bank
+        let addr = self.read_code_word().wrapping_add(self.x);
+        self.idle();
+        let lsb = self.read(self.as_code_addr(addr));
+        let msb = self.read(self.as_code_addr(addr.wrapping_add(1)));
+        self.as_code_addr(((msb as u16) << 8) | lsb as u16)
+    }
+    #[inline]
+    fn read_addr_dir(&mut self) -> u32 {
+        let addr = self.read_code() as u16;
+        self.idle_direct();
+        self.as_direct_addr(addr)
+    }
+    #[inline]
+    fn read_addr_dir_idx_x(&mut self) -> u32 {
+        let addr = (self.read_code() as u16).wrapping_add(self.x);
+        self.idle_direct();
+        self.idle();
+        self.as_direct_addr(addr)
+    }
+    #[inline]
+    fn read_addr_dir_idx_y(&mut self) -> u32 {
+        let addr = (self.read_code() as u16).wrapping_add(self.y);
+        self.idle_direct();
+        self.idle();
+        self.as_direct_addr(addr)
+    }
+    #[inline]
+    fn read_addr_dir_ind(&mut self) -> u32 {
+        let addr = self.read_code() as u16;
+        self.idle_direct();
+        let addr = self.read_direct_ptr(addr);
+        self.as_data_addr(addr)
+    }
+    #[inline]
+    fn read_addr_dir_ind_idx_y(&mut self) -> u32 {
+        let base = self.read_addr_dir_ind();
+        let addr = base.wrapping_add(self.y as u32) & 0xffffff;
+        self.idle_index(base, addr);
+        addr
+    }
+    #[inline]
+    fn read_addr_dir_idx_x_ind(&mut self) -> u32 {
+        let addr = (self.read_code() as u16).wrapping_add(self.x);
+        self.idle_direct();
+        self.idle();
+        let addr = self.read_direct_ptr(addr);
+        self.as_data_addr(addr)
+    }
+    #[inline]
+    fn read_addr_dir_ind_lng(&mut self) -> u32 {
+        // long pointers are a native feature, so they never wrap at the page boundary
+        let addr = self.read_code() as u16;
+        self.idle_direct();
+
+        let b1 = self.read(self.d.wrapping_add(addr) as u32);
+        let b2 = self.read(self.d.wrapping_add(addr).wrapping_add(1) as u32);
+        let b3 = self.read(self.d.wrapping_add(addr).wrapping_add(2) as u32);
+
+        ((b3 as u32) << 16) | ((b2 as u32) << 8) | b1 as u32
+    }
+    #[inline]
+    fn read_addr_dir_ind_lng_idx_y(&mut self) -> u32 {
+        self.read_addr_dir_ind_lng().wrapping_add(self.y as u32) & 0xffffff
+    }
+    #[inline]
+    fn read_addr_stk_rel(&mut self) -> u32 {
+        let addr = self.read_code() as u16;
+        self.idle();
+        Self::BANK_0 | self.sp.wrapping_add(addr) as u32
+    }
+    #[inline]
+    fn read_addr_stk_rel_ind_idx_y(&mut self) -> u32 {
+        let addr = self.read_code() as u16;
+        self.idle();
+
+        let lsb = self.read(self.sp.wrapping_add(addr) as u32);
+        let msb = self.read(self.sp.wrapping_add(addr).wrapping_add(1) as u32);
+        self.idle();
+
+        self.as_data_addr(((msb as u16) << 8) | lsb as u16).wrapping_add(self.y as u32) & 0xffffff
+    }
+    #[inline]
+    fn read_direct_ptr(&mut self, addr: u16) -> u16 {
+        // both bytes go through the direct page mapping, so the pointer wraps
+        // within the page in emulation mode and within bank 0 otherwise
+        let lsb = self.read(self.as_direct_addr(addr));
+        let msb = self.read(self.as_direct_addr(addr.wrapping_add(1)));
+        ((msb as u16) << 8) | lsb as u16
+    }
 
     // instructions
     #[inline]
@@ -570,7 +935,7 @@ impl Cpu {
             if result > 0x09 { result += 0x06 }
             result = (self.a & 0xf0) + (rhs as u16 & 0xf0) + if result > 0x0f { 0x10 } else { 0 } + (result & 0x0f);
         } else {
-            result = self.a + rhs as u16 + self.carry() as u16;
+            result = (self.a & 0xff) + rhs as u16 + self.carry() as u16;
         }
 
         self.set_overflow((!(self.a as u8 ^ rhs) & (self.a as u8 ^ result as u8) & 0x80) != 0);
@@ -591,13 +956,13 @@ impl Cpu {
             result = (self.a as u32 & 0x0f) + (rhs as u32 & 0x0f) + self.carry() as u32;
 
             if result > 0x09 { result += 0x06 }
-            result = (self.a as u32 & 0xf0) + (rhs as u32 & 0xf0) + if result > 0x0f { 0x10 } else { 0 } + (result as u32 & 0x0f);
+            result = (self.a as u32 & 0xf0) + (rhs as u32 & 0xf0) + if result > 0x0f { 0x10 } else { 0 } + (result & 0x0f);
 
             if result > 0x9f { result += 0x60 }
-            result = (self.a as u32 & 0xf00) + (rhs as u32 & 0xf00) + if result > 0xff { 0x100 } else { 0 } + (result as u32 & 0xff);
+            result = (self.a as u32 & 0xf00) + (rhs as u32 & 0xf00) + if result > 0xff { 0x100 } else { 0 } + (result & 0xff);
 
             if result > 0x9ff { result += 0x600 }
-            result = (self.a as u32 & 0xf000) + (rhs as u32 & 0xf000) + if result > 0xfff { 0x1000 } else { 0 } + (result as u32 & 0xfff);
+            result = (self.a as u32 & 0xf000) + (rhs as u32 & 0xf000) + if result > 0xfff { 0x1000 } else { 0 } + (result & 0xfff);
         } else {
             result = self.a as u32 + rhs as u32 + self.carry() as u32;
         }
@@ -618,61 +983,65 @@ impl Cpu {
         if self.memory_mode_8() {
             self.adc8(rhs as u8)
         } else {
-            self.adc16(rhs as u16)
+            self.adc16(rhs)
         }
     }
 
     #[inline]
     const fn sbc8(&mut self, rhs: u8) {
         let rhs = !rhs;
+        // signed, the digit adjustments can take a nibble below zero
+        let a = (self.a & 0xff) as i32;
         let mut result;
         if self.decimal() {
-            result = (self.a & 0x0f) + (rhs as u16 & 0x0f) + self.carry() as u16;
+            result = (a & 0x0f) + (rhs as i32 & 0x0f) + self.carry() as i32;
 
             if result <= 0x0f { result -= 0x06 }
-            result = (self.a & 0xf0) + (rhs as u16 & 0xf0) + if result > 0x0f { 0x10 } else { 0 } + (result & 0x0f);
+            result = (a & 0xf0) + (rhs as i32 & 0xf0) + if result > 0x0f { 0x10 } else { 0 } + (result & 0x0f);
         } else {
-            result = self.a + rhs as u16 + self.carry() as u16;
+            result = a + rhs as i32 + self.carry() as i32;
         }
 
-        self.set_overflow((!(self.a as u8 ^ rhs) & (self.a as u8 ^ result as u8) & 0x80) != 0);
+        self.set_overflow((!(a as u8 ^ rhs) & (a as u8 ^ result as u8) & 0x80) != 0);
+        self.set_carry(result > 0xff);
 
         if self.decimal() && result <= 0xff {
             result -= 0x60
         }
 
         self.set_zero_negative8(result as u8);
-        self.set_carry(result > 0xff);
 
-        self.a = (self.a & 0xff00) | (result & 0xff)
+        self.a = (self.a & 0xff00) | (result as u16 & 0xff)
     }
     #[inline]
     const fn sbc16(&mut self, rhs: u16) {
         let rhs = !rhs;
+        // signed, the digit adjustments can take a nibble below zero
+        let a = self.a as i32;
         let mut result;
         if self.decimal() {
-            result = (self.a as u32 & 0x0f) + (rhs as u32 & 0x0f) + self.carry() as u32;
+            result = (a & 0x0f) + (rhs as i32 & 0x0f) + self.carry() as i32;
 
             if result <= 0x0f { result -= 0x06 }
-            result = (self.a as u32 & 0xf0) + (rhs as u32 & 0xf0) + if result > 0x0f { 0x10 } else { 0 } + (result as u32 & 0x0f);
+            result = (a & 0xf0) + (rhs as i32 & 0xf0) + if result > 0x0f { 0x10 } else { 0 } + (result & 0x0f);
 
             if result <= 0xff { result -= 0x60 }
-            result = (self.a as u32 & 0xf00) + (rhs as u32 & 0xf00) + if result > 0xff { 0x100 } else { 0 } + (result as u32 & 0xff);
+            result = (a & 0xf00) + (rhs as i32 & 0xf00) + if result > 0xff { 0x100 } else { 0 } + (result & 0xff);
 
             if result <= 0xfff { result -= 0x600 }
-            result = (self.a as u32 & 0xf000) + (rhs as u32 & 0xf000) + if result > 0xfff { 0x1000 } else { 0 } + (result as u32 & 0xfff);
+            result = (a & 0xf000) + (rhs as i32 & 0xf000) + if result > 0xfff { 0x1000 } else { 0 } + (result & 0xfff);
         } else {
-            result = self.a as u32 + rhs as u32 + self.carry() as u32;
+            result = a + rhs as i32 + self.carry() as i32;
         }
 
         self.set_overflow((!(self.a ^ rhs) & (self.a ^ result as u16) & 0x8000) != 0);
+        self.set_carry(result > 0xffff);
 
         if self.decimal() && result <= 0xffff {
             result -= 0x6000
         }
 
         self.set_zero_negative16(result as u16);
-        self.set_carry(result > 0xffff);
 
         self.a = result as u16
     }
@@ -685,79 +1054,142 @@ impl Cpu {
         }
     }
 
+    // load/store instructions
+    #[inline]
+    const fn lda(&mut self, rhs: u16) {
+        if self.memory_mode_8() {
+            self.a = (self.a & 0xff00) | (rhs & 0xff);
+            self.set_zero_negative8(rhs as u8);
+        } else {
+            self.a = rhs;
+            self.set_zero_negative16(rhs);
+        }
+    }
+    #[inline]
+    const fn ldx(&mut self, rhs: u16) {
+        if self.index_mode_8() {
+            self.x = rhs & 0xff;
+            self.set_zero_negative8(rhs as u8);
+        } else {
+            self.x = rhs;
+            self.set_zero_negative16(rhs);
+        }
+    }
+    #[inline]
+    const fn ldy(&mut self, rhs: u16) {
+        if self.index_mode_8() {
+            self.y = rhs & 0xff;
+            self.set_zero_negative8(rhs as u8);
+        } else {
+            self.y = rhs;
+            self.set_zero_negative16(rhs);
+        }
+    }
+    #[inline]
+    fn sta(&mut self, addr: u32) {
+        self.write_m(addr, self.a)
+    }
+    #[inline]
+    fn stx(&mut self, addr: u32) {
+        self.write_x(addr, self.x)
+    }
+    #[inline]
+    fn sty(&mut self, addr: u32) {
+        self.write_x(addr, self.y)
+    }
+    #[inline]
+    fn stz(&mut self, addr: u32) {
+        self.write_m(addr, 0)
+    }
+
     // branch operations
     #[inline]
-    const fn bcc(&mut self, rhs: u8) {
+    fn bcc(&mut self, rhs: u8) {
         if !self.carry() { self.bra(rhs) }
     }
     #[inline]
-    const fn bcs(&mut self, rhs: u8) {
+    fn bcs(&mut self, rhs: u8) {
         if self.carry() { self.bra(rhs) }
     }
     #[inline]
-    const fn beq(&mut self, rhs: u8) {
+    fn beq(&mut self, rhs: u8) {
         if self.zero() { self.bra(rhs) }
     }
     #[inline]
-    const fn bmi(&mut self, rhs: u8) {
+    fn bmi(&mut self, rhs: u8) {
         if self.negative() { self.bra(rhs) }
     }
     #[inline]
-    const fn bne(&mut self, rhs: u8) {
+    fn bne(&mut self, rhs: u8) {
         if !self.zero() { self.bra(rhs) }
     }
     #[inline]
-    const fn bpl(&mut self, rhs: u8) {
+    fn bpl(&mut self, rhs: u8) {
         if !self.negative() { self.bra(rhs) }
     }
     #[inline]
-    const fn bra(&mut self, rhs: u8) {
-        self.pc = self.pc.wrapping_add(rhs as i8 as u16)
+    fn bra(&mut self, rhs: u8) {
+        let pc = self.pc.wrapping_add(rhs as i8 as u16);
+        self.idle();
+        // emulation mode takes another cycle when the branch crosses a page
+        if self.emulation_mode && ((self.pc ^ pc) & 0xff00) != 0 {
+            self.idle()
+        }
+        self.pc = pc
     }
     #[inline]
-    const fn brl(&mut self, rhs: u16) {
+    fn brl(&mut self, rhs: u16) {
+        self.idle();
         self.pc = self.pc.wrapping_add(rhs)
     }
     #[inline]
-    const fn bvc(&mut self, rhs: u8) {
+    fn bvc(&mut self, rhs: u8) {
         if !self.overflow() { self.bra(rhs) }
     }
     #[inline]
-    const fn bvs(&mut self, rhs: u8) {
+    fn bvs(&mut self, rhs: u8) {
         if self.overflow() { self.bra(rhs) }
     }
 
     // set/clear flag instructions
     #[inline]
-    const fn clc(&mut self) {
+    fn clc(&mut self) {
+        self.idle();
         self.set_carry(false)
     }
     #[inline]
-    const fn cld(&mut self) {
+    fn cld(&mut self) {
+        self.idle();
         self.set_decimal(false)
     }
     #[inline]
-    const fn cli(&mut self) {
+    fn cli(&mut self) {
+        self.idle();
         self.set_irq_disable(false)
     }
     #[inline]
-    const fn clv(&mut self) {
+    fn clv(&mut self) {
+        self.idle();
         self.set_overflow(false)
     }
     #[inline]
-    const fn sec(&mut self) {
+    fn sec(&mut self) {
+        self.idle();
         self.set_carry(true)
     }
     #[inline]
-    const fn sed(&mut self) {
+    fn sed(&mut self) {
+        self.idle();
         self.set_decimal(true)
     }
     #[inline]
-    const fn sei(&mut self) {
+    fn sei(&mut self) {
+        self.idle();
         self.set_irq_disable(true)
     }
     #[inline]
-    const fn rep(&mut self, rhs: u8) {
+    fn rep(&mut self, rhs: u8) {
+        self.idle();
         self.ps &= !rhs;
         if self.emulation_mode {
             self.set_memory_mode_8(true);
@@ -765,7 +1197,8 @@ impl Cpu {
         }
     }
     #[inline]
-    const fn sep(&mut self, rhs: u8) {
+    fn sep(&mut self, rhs: u8) {
+        self.idle();
         self.ps |= rhs;
         if self.index_mode_8() {
             self.x &= 0xff;
@@ -799,7 +1232,24 @@ impl Cpu {
         result
     }
     #[inline]
-    const fn dex(&mut self) {
+    const fn dec(&mut self, rhs: u16) -> u16 {
+        if self.memory_mode_8() {
+            self.dec8(rhs as u8) as u16
+        } else {
+            self.dec16(rhs)
+        }
+    }
+    #[inline]
+    const fn inc(&mut self, rhs: u16) -> u16 {
+        if self.memory_mode_8() {
+            self.inc8(rhs as u8) as u16
+        } else {
+            self.inc16(rhs)
+        }
+    }
+    #[inline]
+    fn dex(&mut self) {
+        self.idle();
         if self.index_mode_8() {
             self.x = (self.x & 0xff00) | self.dec8(self.x as u8) as u16
         } else {
@@ -807,7 +1257,8 @@ impl Cpu {
         }
     }
     #[inline]
-    const fn dey(&mut self) {
+    fn dey(&mut self) {
+        self.idle();
         if self.index_mode_8() {
             self.y = (self.y & 0xff00) | self.dec8(self.y as u8) as u16
         } else {
@@ -815,7 +1266,8 @@ impl Cpu {
         }
     }
     #[inline]
-    const fn inx(&mut self) {
+    fn inx(&mut self) {
+        self.idle();
         if self.index_mode_8() {
             self.x = (self.x & 0xff00) | self.inc8(self.x as u8) as u16
         } else {
@@ -823,7 +1275,8 @@ impl Cpu {
         }
     }
     #[inline]
-    const fn iny(&mut self) {
+    fn iny(&mut self) {
+        self.idle();
         if self.index_mode_8() {
             self.y = (self.y & 0xff00) | self.inc8(self.y as u8) as u16
         } else {
@@ -831,7 +1284,8 @@ impl Cpu {
         }
     }
     #[inline]
-    const fn dec_a(&mut self) {
+    fn dec_a(&mut self) {
+        self.idle();
         if self.memory_mode_8() {
             self.a = (self.a & 0xff00) | self.dec8(self.a as u8) as u16
         } else {
@@ -839,7 +1293,8 @@ impl Cpu {
         }
     }
     #[inline]
-    const fn inc_a(&mut self) {
+    fn inc_a(&mut self) {
+        self.idle();
         if self.memory_mode_8() {
             self.a = (self.a & 0xff00) | self.inc8(self.a as u8) as u16
         } else {
@@ -879,6 +1334,353 @@ impl Cpu {
         }
     }
 
+    // bit test instructions
+    #[inline]
+    const fn bit(&mut self, rhs: u16) {
+        if self.memory_mode_8() {
+            self.set_zero((self.a as u8 & rhs as u8) == 0);
+            self.set_overflow((rhs & 0x40) != 0);
+            self.set_negative((rhs & 0x80) != 0);
+        } else {
+            self.set_zero((self.a & rhs) == 0);
+            self.set_overflow((rhs & 0x4000) != 0);
+            self.set_negative((rhs & 0x8000) != 0);
+        }
+    }
+    #[inline]
+    const fn bit_imm(&mut self, rhs: u16) {
+        if self.memory_mode_8() {
+            self.set_zero((self.a as u8 & rhs as u8) == 0);
+        } else {
+            self.set_zero((self.a & rhs) == 0);
+        }
+    }
+    #[inline]
+    const fn trb(&mut self, rhs: u16) -> u16 {
+        self.bit_imm(rhs);
+        rhs & !self.a
+    }
+    #[inline]
+    const fn tsb(&mut self, rhs: u16) -> u16 {
+        self.bit_imm(rhs);
+        rhs | self.a
+    }
+
+    // shift instructions
+    #[inline]
+    const fn asl(&mut self, rhs: u16) -> u16 {
+        if self.memory_mode_8() {
+            let result = (rhs as u8) << 1;
+            self.set_carry((rhs & 0x80) != 0);
+            self.set_zero_negative8(result);
+            result as u16
+        } else {
+            let result = rhs << 1;
+            self.set_carry((rhs & 0x8000) != 0);
+            self.set_zero_negative16(result);
+            result
+        }
+    }
+    #[inline]
+    const fn lsr(&mut self, rhs: u16) -> u16 {
+        if self.memory_mode_8() {
+            let result = (rhs as u8) >> 1;
+            self.set_carry((rhs & 0x01) != 0);
+            self.set_zero_negative8(result);
+            result as u16
+        } else {
+            let result = rhs >> 1;
+            self.set_carry((rhs & 0x01) != 0);
+            self.set_zero_negative16(result);
+            result
+        }
+    }
+    #[inline]
+    const fn rol(&mut self, rhs: u16) -> u16 {
+        if self.memory_mode_8() {
+            let result = ((rhs as u8) << 1) | self.carry() as u8;
+            self.set_carry((rhs & 0x80) != 0);
+            self.set_zero_negative8(result);
+            result as u16
+        } else {
+            let result = (rhs << 1) | self.carry() as u16;
+            self.set_carry((rhs & 0x8000) != 0);
+            self.set_zero_negative16(result);
+            result
+        }
+    }
+    #[inline]
+    fn ror(&mut self, rhs: u16) -> u16 {
+        if self.memory_mode_8() {
+            let result = ((rhs as u8) >> 1) | ((self.carry() as u8) << 7);
+            self.set_carry((rhs & 0x01) != 0);
+            self.set_zero_negative8(result);
+            result as u16
+        } else {
+            let result = (rhs >> 1) | ((self.carry() as u16) << 15);
+            self.set_carry((rhs & 0x01) != 0);
+            self.set_zero_negative16(result);
+            result
+        }
+    }
+    #[inline]
+    fn asl_a(&mut self) {
+        self.idle();
+        let result = self.asl(self.a);
+        self.set_a(result)
+    }
+    #[inline]
+    fn lsr_a(&mut self) {
+        self.idle();
+        let result = self.lsr(self.a);
+        self.set_a(result)
+    }
+    #[inline]
+    fn rol_a(&mut self) {
+        self.idle();
+        let result = self.rol(self.a);
+        self.set_a(result)
+    }
+    #[inline]
+    fn ror_a(&mut self) {
+        self.idle();
+        let result = self.ror(self.a);
+        self.set_a(result)
+    }
+
+    // transfer instructions
+    #[inline]
+    fn tax(&mut self) {
+        self.idle();
+        self.ldx(self.a)
+    }
+    #[inline]
+    fn tay(&mut self) {
+        self.idle();
+        self.ldy(self.a)
+    }
+    #[inline]
+    fn tsx(&mut self) {
+        self.idle();
+        self.ldx(self.sp)
+    }
+    #[inline]
+    fn txa(&mut self) {
+        self.idle();
+        self.lda(self.x)
+    }
+    #[inline]
+    fn txy(&mut self) {
+        self.idle();
+        self.ldy(self.x)
+    }
+    #[inline]
+    fn tya(&mut self) {
+        self.idle();
+        self.lda(self.y)
+    }
+    #[inline]
+    fn tyx(&mut self) {
+        self.idle();
+        self.ldx(self.y)
+    }
+    #[inline]
+    fn txs(&mut self) {
+        self.idle();
+        self.sp = self.x;
+        if self.emulation_mode {
+            self.restrict_sp()
+        }
+    }
+    #[inline]
+    fn tcs(&mut self) {
+        self.idle();
+        self.sp = self.a;
+        if self.emulation_mode {
+            self.restrict_sp()
+        }
+    }
+    #[inline]
+    fn tsc(&mut self) {
+        self.idle();
+        self.a = self.sp;
+        self.set_zero_negative16(self.a);
+    }
+    #[inline]
+    fn tcd(&mut self) {
+        self.idle();
+        self.d = self.a;
+        self.set_zero_negative16(self.d);
+    }
+    #[inline]
+    fn tdc(&mut self) {
+        self.idle();
+        self.a = self.d;
+        self.set_zero_negative16(self.a);
+    }
+    #[inline]
+    fn xba(&mut self) {
+        self.idle();
+        self.idle();
+        self.a = self.a.swap_bytes();
+        self.set_zero_negative8(self.a as u8);
+    }
+
+    // stack instructions
+    #[inline]
+    fn pha(&mut self) {
+        self.idle();
+        if self.memory_mode_8() {
+            self.push(self.a as u8)
+        } else {
+            self.push_word(self.a)
+        }
+    }
+    #[inline]
+    fn phx(&mut self) {
+        self.idle();
+        if self.index_mode_8() {
+            self.push(self.x as u8)
+        } else {
+            self.push_word(self.x)
+        }
+    }
+    #[inline]
+    fn phy(&mut self) {
+        self.idle();
+        if self.index_mode_8() {
+            self.push(self.y as u8)
+        } else {
+            self.push_word(self.y)
+        }
+    }
+    #[inline]
+    fn php(&mut self) {
+        self.idle();
+        self.push(self.ps)
+    }
+    #[inline]
+    fn phb(&mut self) {
+        self.idle();
+        self.push(self.dbr)
+    }
+    #[inline]
+    fn phk(&mut self) {
+        self.idle();
+        self.push(self.k)
+    }
+    #[inline]
+    fn phd(&mut self) {
+        self.idle();
+        self.push_word(self.d)
+    }
+    #[inline]
+    fn pla(&mut self) {
+        self.idle();
+        self.idle();
+        let value = if self.memory_mode_8() {
+            self.pop() as u16
+        } else {
+            self.pop_word()
+        };
+        self.lda(value)
+    }
+    #[inline]
+    fn plx(&mut self) {
+        self.idle();
+        self.idle();
+        let value = if self.index_mode_8() {
+            self.pop() as u16
+        } else {
+            self.pop_word()
+        };
+        self.ldx(value)
+    }
+    #[inline]
+    fn ply(&mut self) {
+        self.idle();
+        self.idle();
+        let value = if self.index_mode_8() {
+            self.pop() as u16
+        } else {
+            self.pop_word()
+        };
+        self.ldy(value)
+    }
+    #[inline]
+    fn plp(&mut self) {
+        self.idle();
+        self.idle();
+        let value = self.pop();
+        self.set_ps(value)
+    }
+    #[inline]
+    fn plb(&mut self) {
+        self.idle();
+        self.idle();
+        self.dbr = self.pop();
+        self.set_zero_negative8(self.dbr);
+    }
+    #[inline]
+    fn pld(&mut self) {
+        self.idle();
+        self.idle();
+        self.d = self.pop_word();
+        self.set_zero_negative16(self.d);
+    }
+    #[inline]
+    fn pea(&mut self, rhs: u16) {
+        self.push_word_unchecked(rhs)
+    }
+    #[inline]
+    fn pei(&mut self, addr: u16) {
+        let value = self.read_direct_ptr(addr);
+        self.push_word_unchecked(value)
+    }
+    #[inline]
+    fn per(&mut self, rhs: u16) {
+        self.idle();
+        self.push_word_unchecked(self.pc.wrapping_add(rhs))
+    }
+
+    // block move instructions
+    #[inline]
+    fn mvn(&mut self, dst: u8, src: u8) {
+        self.move_byte(dst, src);
+        if self.index_mode_8() {
+            self.x = (self.x + 1) & 0xff;
+            self.y = (self.y + 1) & 0xff;
+        } else {
+            self.x = self.x.wrapping_add(1);
+            self.y = self.y.wrapping_add(1);
+        }
+    }
+    #[inline]
+    fn mvp(&mut self, dst: u8, src: u8) {
+        self.move_byte(dst, src);
+        if self.index_mode_8() {
+            self.x = self.x.wrapping_sub(1) & 0xff;
+            self.y = self.y.wrapping_sub(1) & 0xff;
+        } else {
+            self.x = self.x.wrapping_sub(1);
+            self.y = self.y.wrapping_sub(1);
+        }
+    }
+    #[inline]
+    fn move_byte(&mut self, dst: u8, src: u8) {
+        self.dbr = dst;
+        let value = self.read(((src as u32) << 16) | self.x as u32);
+        self.write(((dst as u32) << 16) | self.y as u32, value);
+        self.idle();
+        self.idle();
+
+        // repeat the instruction until the count underflows
+        self.a = self.a.wrapping_sub(1);
+        if self.a != 0xffff {
+            self.pc = self.pc.wrapping_sub(3);
+        }
+    }
+
     // jump instructions
     #[inline]
     const fn jml(&mut self, rhs: u32) {
@@ -891,23 +1693,30 @@ impl Cpu {
     }
     #[inline]
     fn jsl(&mut self, rhs: u32) {
+        self.idle();
         self.push_long(((self.k as u32) << 16) | self.pc.wrapping_sub(1) as u32);
         self.k = (rhs >> 16) as u8;
         self.pc = rhs as u16;
     }
     #[inline]
     fn jsr(&mut self, rhs: u16) {
+        self.idle();
         self.push_word(self.pc.wrapping_sub(1));
         self.pc = rhs;
     }
     #[inline]
     fn rtl(&mut self) {
+        self.idle();
+        self.idle();
         let addr = self.pop_long();
         self.k = (addr >> 16) as u8;
         self.pc = addr as u16;
     }
     #[inline]
     fn rts(&mut self) {
+        self.idle();
+        self.idle();
+        self.idle();
         self.pc = self.pop_word().wrapping_add(1);
     }
 
@@ -915,7 +1724,7 @@ impl Cpu {
     #[inline]
     const fn and(&mut self, rhs: u16) {
         if self.memory_mode_8() {
-            self.a &= rhs as u8 as u16;
+            self.a &= 0xff00 | rhs as u8 as u16;
             self.set_zero_negative8(self.a as u8);
         } else {
             self.a &= rhs;
@@ -923,7 +1732,7 @@ impl Cpu {
         }
     }
     #[inline]
-    const fn eor(&mut self, rhs: u16) {
+    fn eor(&mut self, rhs: u16) {
         if self.memory_mode_8() {
             self.a ^= rhs as u8 as u16;
             self.set_zero_negative8(self.a as u8);
@@ -933,7 +1742,7 @@ impl Cpu {
         }
     }
     #[inline]
-    const fn ora(&mut self, rhs: u16) {
+    fn ora(&mut self, rhs: u16) {
         if self.memory_mode_8() {
             self.a |= rhs as u8 as u16;
             self.set_zero_negative8(self.a as u8);
@@ -943,15 +1752,270 @@ impl Cpu {
         }
     }
 
+    // interrupt instructions
+    #[inline]
+    fn brk(&mut self, _rhs: u8) {
+        self.interrupt(Interrupt::Brk)
+    }
+    #[inline]
+    fn cop(&mut self, _rhs: u8) {
+        self.interrupt(Interrupt::Cop)
+    }
+    #[inline]
+    fn interrupt(&mut self, interrupt: Interrupt) {
+        if !interrupt.is_software() {
+            self.idle();
+            self.idle();
+        }
+        if self.emulation_mode {
+            self.push_word(self.pc);
+            // the break flag shares a bit with the index mode flag, and is
+            // only set on the stack for software interrupts
+            if interrupt.is_software() {
+                self.push(self.ps | Self::INDEX_MODE_8)
+            } else {
+                self.push(self.ps & !Self::INDEX_MODE_8)
+            }
+        } else {
+            self.push(self.k);
+            self.push_word(self.pc);
+            self.push(self.ps);
+        }
+        self.set_irq_disable(true);
+        self.set_decimal(false);
+        self.k = 0;
+        self.pc = self.read_word(interrupt.vector(self.emulation_mode));
+    }
+    #[inline]
+    fn rti(&mut self) {
+        self.idle();
+        self.idle();
+        let ps = self.pop();
+        self.set_ps(ps);
+        self.pc = self.pop_word();
+        if !self.emulation_mode {
+            self.k = self.pop();
+        }
+    }
+    #[inline]
+    fn wai(&mut self) {
+        self.idle();
+        self.idle();
+        self.waiting = true
+    }
+    #[inline]
+    fn stp(&mut self) {
+        self.idle();
+        self.idle();
+        self.stopped = true
+    }
+
+    // misc instructions
+    #[inline]
+    fn nop(&mut self) {
+        self.idle()
+    }
+    #[inline]
+    const fn wdm(&mut self, _rhs: u8) {}
+
     #[inline]
-    const fn xce(&mut self) {
+    fn xce(&mut self) {
+        self.idle();
         let carry = self.carry();
         self.set_carry(self.emulation_mode);
         self.emulation_mode = carry;
 
         if self.emulation_mode {
-            self.sep(Self::INDEX_MODE_8 | Self::MEMORY_MODE_8);
+            self.set_ps(self.ps);
             self.restrict_sp();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+
+    // runs code from $8000 in emulation mode until it hits stp, which also
+    // fills the rest of the rom, with a handler at $8100 for the given vector
+    fn run_with_handler(code: &[u8], handler: &[u8], vector: usize) -> Cpu {
+        let mut rom = vec![0xdb; 0x8000];
+        rom[..code.len()].copy_from_slice(code);
+        rom[0x100..0x100 + handler.len()].copy_from_slice(handler);
+        rom[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        rom[vector & 0x7fff..(vector & 0x7fff) + 2].copy_from_slice(&[0x00, 0x81]);
+        let mut cpu = Cpu::new(Mem::new(Rom::unchecked(&rom)));
+        for _ in 0..10 {
+            if cpu.stopped {
+                break
+            }
+            cpu.run()
+        }
+        assert!(cpu.stopped, "still running at {:02x}:{:04x}", cpu.k, cpu.pc);
+        cpu
+    }
+
+    fn run(code: &[u8]) -> Cpu {
+        run_with_handler(code, &[], 0xfffe)
+    }
+
+    // same, but switches to native mode first
+    fn run_native(code: &[u8]) -> Cpu {
+        run(&[&[0x18, 0xfb], code].concat())
+    }
+
+    #[test]
+    fn loads_stores_and_branches() {
+        // rep #$30; lda #$1234; sta $0010; ldx #$0010; dex; bne -3; sep #$20; lda #$ff; xba
+        let mut cpu = run_native(&[0xc2, 0x30, 0xa9, 0x34, 0x12, 0x8d, 0x10, 0x00, 0xa2, 0x10, 0x00, 0xca, 0xd0, 0xfd, 0xe2, 0x20, 0xa9, 0xff, 0xeb]);
+        assert_eq!(cpu.x, 0);
+        assert_eq!(cpu.a, 0xff12);
+        assert_eq!(cpu.mem.read16(0x10), 0x1234);
+    }
+
+    #[test]
+    fn subroutines_and_stack() {
+        // rep #$30; lda #$beef; jsr $8012; stp; ... $8012: pha; pla; inc; rts
+        let mut code = vec![0xc2, 0x30, 0xa9, 0xef, 0xbe, 0x20, 0x12, 0x80, 0xdb];
+        code.resize(0x10, 0xdb);
+        code.extend_from_slice(&[0x48, 0x68, 0x1a, 0x60]);
+        let cpu = run_native(&code);
+        assert_eq!(cpu.a, 0xbef0);
+        assert_eq!(cpu.sp, 0x01ff);
+    }
+
+    #[test]
+    fn binary_adc_sbc() {
+        // rep #$20; lda #$ff10; sep #$20; clc; adc #$05
+        let cpu = run_native(&[0xc2, 0x20, 0xa9, 0x10, 0xff, 0xe2, 0x20, 0x18, 0x69, 0x05]);
+        assert_eq!(cpu.a, 0xff15);
+        assert!(!cpu.carry() && !cpu.overflow());
+        // 8-bit carries stay out of b: rep #$20; lda #$ffff; sep #$20; clc; adc #$01
+        let cpu = run_native(&[0xc2, 0x20, 0xa9, 0xff, 0xff, 0xe2, 0x20, 0x18, 0x69, 0x01]);
+        assert_eq!(cpu.a, 0xff00);
+        assert!(cpu.carry() && cpu.zero());
+        // rep #$20; lda #$ff05; sep #$20; sec; sbc #$10
+        let cpu = run_native(&[0xc2, 0x20, 0xa9, 0x05, 0xff, 0xe2, 0x20, 0x38, 0xe9, 0x10]);
+        assert_eq!(cpu.a, 0xfff5);
+        assert!(!cpu.carry() && cpu.negative());
+        // signed overflow: lda #$7f; clc; adc #$01
+        let cpu = run(&[0xa9, 0x7f, 0x18, 0x69, 0x01]);
+        assert_eq!(cpu.a & 0xff, 0x80);
+        assert!(cpu.overflow());
+    }
+
+    #[test]
+    fn decimal_adc_sbc() {
+        // sed; lda #$99; clc; adc #$01
+        let cpu = run(&[0xf8, 0xa9, 0x99, 0x18, 0x69, 0x01]);
+        assert_eq!(cpu.a & 0xff, 0x00);
+        assert!(cpu.carry());
+        // sed; lda #$42; sec; sbc #$13
+        let cpu = run(&[0xf8, 0xa9, 0x42, 0x38, 0xe9, 0x13]);
+        assert_eq!(cpu.a & 0xff, 0x29);
+        assert!(cpu.carry());
+        // sed; lda #$00; sec; sbc #$01
+        let cpu = run(&[0xf8, 0xa9, 0x00, 0x38, 0xe9, 0x01]);
+        assert_eq!(cpu.a & 0xff, 0x99);
+        assert!(!cpu.carry());
+        // sed; rep #$20; lda #$1234; clc; adc #$4768
+        let cpu = run_native(&[0xf8, 0xc2, 0x20, 0xa9, 0x34, 0x12, 0x18, 0x69, 0x68, 0x47]);
+        assert_eq!(cpu.a, 0x6002);
+        // sed; rep #$20; lda #$0000; sec; sbc #$0001
+        let cpu = run_native(&[0xf8, 0xc2, 0x20, 0xa9, 0x00, 0x00, 0x38, 0xe9, 0x01, 0x00]);
+        assert_eq!(cpu.a, 0x9999);
+        assert!(!cpu.carry());
+        // sed; rep #$20; lda #$1000; sec; sbc #$0001
+        let cpu = run_native(&[0xf8, 0xc2, 0x20, 0xa9, 0x00, 0x10, 0x38, 0xe9, 0x01, 0x00]);
+        assert_eq!(cpu.a, 0x0999);
+        assert!(cpu.carry());
+    }
+
+    #[test]
+    fn emulation_direct_page_wraps() {
+        // lda #$00; sta $ff; lda #$02; sta $00; lda #$aa; sta $0200; lda #$bb; sta $0300;
+        // lda #$11; sta $0008; lda #$22; sta $0108
+        let setup = [0xa9, 0x00, 0x85, 0xff, 0xa9, 0x02, 0x85, 0x00, 0xa9, 0xaa, 0x8d, 0x00, 0x02, 0xa9, 0xbb, 0x8d, 0x00, 0x03,
+            0xa9, 0x11, 0x8d, 0x08, 0x00, 0xa9, 0x22, 0x8d, 0x08, 0x01];
+        // the ($ff) pointer's high byte comes from $0000
+        let cpu = run(&[&setup[..], &[0xb2, 0xff]].concat());
+        assert_eq!(cpu.a & 0xff, 0xaa);
+        // ldx #$10; lda $f8,x stays in the direct page
+        let cpu = run(&[&setup[..], &[0xa2, 0x10, 0xb5, 0xf8]].concat());
+        assert_eq!(cpu.a & 0xff, 0x11);
+        // but not in native mode
+        let cpu = run(&[&setup[..], &[0x18, 0xfb, 0xa2, 0x10, 0xb5, 0xf8]].concat());
+        assert_eq!(cpu.a & 0xff, 0x22);
+        // pla at the top of page 1 wraps to $0100: ldx #$ff; txs; lda #$5a; sta $0100; pla
+        let cpu = run(&[0xa2, 0xff, 0x9a, 0xa9, 0x5a, 0x8d, 0x00, 0x01, 0x68]);
+        assert_eq!(cpu.a & 0xff, 0x5a);
+        assert_eq!(cpu.sp, 0x0100);
+    }
+
+    #[test]
+    fn direct_and_stack_words_wrap_in_bank_0() {
+        let mut cpu = run_native(&[]);
+        cpu.d = 0xff00;
+        let addr = cpu.as_direct_addr(0xff);
+        assert_eq!(addr & 0xffffff, 0x00ffff);
+        assert_eq!(Cpu::next_addr(addr), 0x000000);
+        cpu.sp = 0xfffe;
+        // the operand of lda $01,s, fetched from wram
+        (cpu.k, cpu.pc) = (0x7e, 0x8000);
+        cpu.mem.write(0x7e8000, 0x01);
+        let addr = cpu.read_addr_stk_rel();
+        assert_eq!(Cpu::next_addr(addr), 0x000000);
+        // absolute addresses still carry into the next bank
+        assert_eq!(Cpu::next_addr(0x00ffff), 0x010000);
+    }
+
+    #[test]
+    fn native_nmi_and_rti() {
+        // clc; xce; cli; lda #$80; sta $4200; wai; bra -3
+        let code = [0x18, 0xfb, 0x58, 0xa9, 0x80, 0x8d, 0x00, 0x42, 0xcb, 0x80, 0xfd];
+        // inc $10; lda $10; cmp #$03; bne +1; stp; rti
+        let handler = [0xe6, 0x10, 0xa5, 0x10, 0xc9, 0x03, 0xd0, 0x01, 0xdb, 0x40];
+        let mut cpu = run_with_handler(&code, &handler, 0xffea);
+        // two nmis returned to the loop, the third stopped in the handler
+        assert_eq!(cpu.mem.read(0x10), 3);
+        assert_eq!(cpu.sp, 0x01fb);
+        // the bank, the address after wai, then the flags
+        assert_eq!(cpu.mem.read(0x01ff), 0x00);
+        assert_eq!(cpu.mem.read16(0x01fd), 0x8009);
+        assert_eq!(cpu.mem.read(0x01fc) & Cpu::IRQ_DISABLE, 0);
+        assert!(cpu.irq_disable());
+    }
+
+    #[test]
+    fn emulation_nmi_and_brk() {
+        // lda #$80; sta $4200; wai
+        let code = [0xa9, 0x80, 0x8d, 0x00, 0x42, 0xcb];
+        let mut cpu = run_with_handler(&code, &[0xdb], 0xfffa);
+        assert_eq!(cpu.sp, 0x01fc);
+        assert_eq!(cpu.mem.read16(0x01fe), 0x8006);
+        // hardware interrupts push the break flag clear
+        assert_eq!(cpu.mem.read(0x01fd) & Cpu::INDEX_MODE_8, 0);
+
+        // brk shares the irq vector, skips its signature byte and sets the break flag
+        let mut cpu = run_with_handler(&[0xea, 0x00, 0x00], &[0xdb], 0xfffe);
+        assert_eq!(cpu.mem.read16(0x01fe), 0x8003);
+        assert_ne!(cpu.mem.read(0x01fd) & Cpu::INDEX_MODE_8, 0);
+        assert!(!cpu.decimal());
+    }
+
+    #[test]
+    fn irq_entry_and_masked_wai() {
+        // a v-irq on line 10: lda #$0a; sta $4209; stz $420a; lda #$20; sta $4200
+        let setup = [0xa9, 0x0a, 0x8d, 0x09, 0x42, 0x9c, 0x0a, 0x42, 0xa9, 0x20, 0x8d, 0x00, 0x42];
+        // with irqs masked, wai still wakes up but carries on to stp
+        let mut cpu = run_with_handler(&[&setup[..], &[0xcb]].concat(), &[0xe6, 0x10, 0xdb], 0xfffe);
+        assert_eq!(cpu.mem.read(0x10), 0);
+        assert!(!cpu.waiting);
+        // cli; wai takes the vector, and the handler acks and returns: lda $4211; inc $10; rti
+        let mut cpu = run_with_handler(&[&setup[..], &[0x58, 0xcb]].concat(), &[0xad, 0x11, 0x42, 0xe6, 0x10, 0x40], 0xfffe);
+        assert_eq!(cpu.mem.read(0x10), 1);
+        assert!(!cpu.irq_disable());
+        assert_eq!(cpu.sp, 0x01ff);
+    }
+}
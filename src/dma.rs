@@ -0,0 +1,288 @@
+// dma channel registers ($4300-$437f)
+
+use crate::state::{Serializer, Value};
+
+#[derive(Clone, Copy)]
+pub struct Channel {
+    // dmapx
+    params: u8,
+    // bbadx
+    b_addr: u8,
+    // a1tx/a1bx
+    a_addr: u16,
+    a_bank: u8,
+    // dasx/dasbx
+    count: u16,
+    indirect_bank: u8,
+    // a2ax
+    table_addr: u16,
+    // ntrlx
+    line_counter: u8,
+    // unused byte at $43xb/$43xf
+    unused: u8,
+
+    // internal hdma state
+    hdma_done: bool,
+    hdma_do_transfer: bool,
+}
+
+impl Channel {
+    const B_TO_A: u8 = 0x80;
+    const INDIRECT: u8 = 0x40;
+    const DECREMENT: u8 = 0x10;
+    const FIXED: u8 = 0x08;
+
+    // b-bus offsets written by each transfer mode
+    const PATTERNS: [&[u8]; 8] = [
+        &[0],
+        &[0, 1],
+        &[0, 0],
+        &[0, 0, 1, 1],
+        &[0, 1, 2, 3],
+        &[0, 1, 0, 1],
+        &[0, 0],
+        &[0, 0, 1, 1],
+    ];
+
+    const fn new() -> Self {
+        Self {
+            params: 0xff,
+            b_addr: 0xff,
+            a_addr: 0xffff,
+            a_bank: 0xff,
+            count: 0xffff,
+            indirect_bank: 0xff,
+            table_addr: 0xffff,
+            line_counter: 0xff,
+            unused: 0xff,
+            hdma_done: false,
+            hdma_do_transfer: false,
+        }
+    }
+
+    #[inline]
+    pub const fn b_to_a(&self) -> bool {
+        (self.params & Self::B_TO_A) != 0
+    }
+
+    #[inline]
+    pub const fn pattern(&self) -> &'static [u8] {
+        Self::PATTERNS[(self.params & 7) as usize]
+    }
+
+    // b-bus address for the nth byte of a transfer unit
+    #[inline]
+    pub const fn b_addr(&self, index: usize) -> u8 {
+        let pattern = self.pattern();
+        self.b_addr.wrapping_add(pattern[index % pattern.len()])
+    }
+
+    #[inline]
+    pub const fn a_addr(&self) -> u32 {
+        ((self.a_bank as u32) << 16) | self.a_addr as u32
+    }
+
+    // steps the a-bus address within its bank
+    #[inline]
+    pub const fn step_a_addr(&mut self) {
+        if (self.params & Self::FIXED) == 0 {
+            if (self.params & Self::DECREMENT) != 0 {
+                self.a_addr = self.a_addr.wrapping_sub(1)
+            } else {
+                self.a_addr = self.a_addr.wrapping_add(1)
+            }
+        }
+    }
+
+    // counts down one byte, returning true once the transfer is done
+    #[inline]
+    pub const fn step_count(&mut self) -> bool {
+        self.count = self.count.wrapping_sub(1);
+        self.count == 0
+    }
+
+    #[inline]
+    pub const fn indirect(&self) -> bool {
+        (self.params & Self::INDIRECT) != 0
+    }
+
+    #[inline]
+    pub const fn hdma_done(&self) -> bool {
+        self.hdma_done
+    }
+
+    #[inline]
+    pub const fn hdma_do_transfer(&self) -> bool {
+        self.hdma_do_transfer
+    }
+
+    // clears the hdma state at the start of a frame
+    #[inline]
+    pub const fn reset_hdma(&mut self) {
+        self.hdma_done = false;
+        self.hdma_do_transfer = false;
+    }
+
+    // points the table back at the start address at the start of a frame
+    #[inline]
+    pub const fn start_hdma(&mut self) {
+        self.table_addr = self.a_addr
+    }
+
+    // address of the next table byte
+    #[inline]
+    pub const fn table_addr(&self) -> u32 {
+        ((self.a_bank as u32) << 16) | self.table_addr as u32
+    }
+
+    #[inline]
+    pub const fn step_table_addr(&mut self) {
+        self.table_addr = self.table_addr.wrapping_add(1)
+    }
+
+    // address of the next data byte, which lives in the table in direct mode
+    #[inline]
+    pub const fn hdma_addr(&self) -> u32 {
+        if self.indirect() {
+            ((self.indirect_bank as u32) << 16) | self.count as u32
+        } else {
+            self.table_addr()
+        }
+    }
+
+    #[inline]
+    pub const fn step_hdma_addr(&mut self) {
+        if self.indirect() {
+            self.count = self.count.wrapping_add(1)
+        } else {
+            self.step_table_addr()
+        }
+    }
+
+    #[inline]
+    pub const fn set_indirect_addr(&mut self, addr: u16) {
+        self.count = addr
+    }
+
+    // loads a line counter from the table, a zero entry ends the channel for this frame
+    #[inline]
+    pub const fn set_line_counter(&mut self, line_counter: u8) {
+        self.line_counter = line_counter;
+        self.hdma_do_transfer = true;
+        self.hdma_done = line_counter == 0;
+    }
+
+    // counts down one scanline, returning true when the next table entry is due
+    #[inline]
+    pub const fn step_line_counter(&mut self) -> bool {
+        self.line_counter = self.line_counter.wrapping_sub(1);
+        // with the repeat bit set, data is transferred on every line of the entry
+        self.hdma_do_transfer = (self.line_counter & 0x80) != 0;
+        (self.line_counter & 0x7f) == 0
+    }
+
+    fn read(&self, reg: u8, mdr: u8) -> u8 {
+        match reg {
+            0x0 => self.params,
+            0x1 => self.b_addr,
+            0x2 => self.a_addr as u8,
+            0x3 => (self.a_addr >> 8) as u8,
+            0x4 => self.a_bank,
+            0x5 => self.count as u8,
+            0x6 => (self.count >> 8) as u8,
+            0x7 => self.indirect_bank,
+            0x8 => self.table_addr as u8,
+            0x9 => (self.table_addr >> 8) as u8,
+            0xa => self.line_counter,
+            0xb | 0xf => self.unused,
+            _ => mdr,
+        }
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        match reg {
+            0x0 => self.params = value,
+            0x1 => self.b_addr = value,
+            0x2 => self.a_addr = (self.a_addr & 0xff00) | value as u16,
+            0x3 => self.a_addr = (self.a_addr & 0x00ff) | ((value as u16) << 8),
+            0x4 => self.a_bank = value,
+            0x5 => self.count = (self.count & 0xff00) | value as u16,
+            0x6 => self.count = (self.count & 0x00ff) | ((value as u16) << 8),
+            0x7 => self.indirect_bank = value,
+            0x8 => self.table_addr = (self.table_addr & 0xff00) | value as u16,
+            0x9 => self.table_addr = (self.table_addr & 0x00ff) | ((value as u16) << 8),
+            0xa => self.line_counter = value,
+            0xb | 0xf => self.unused = value,
+            _ => {},
+        }
+    }
+}
+
+impl Value for Channel {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.value(&mut self.params);
+        s.value(&mut self.b_addr);
+        s.value(&mut self.a_addr);
+        s.value(&mut self.a_bank);
+        s.value(&mut self.count);
+        s.value(&mut self.indirect_bank);
+        s.value(&mut self.table_addr);
+        s.value(&mut self.line_counter);
+        s.value(&mut self.unused);
+        s.value(&mut self.hdma_done);
+        s.value(&mut self.hdma_do_transfer);
+    }
+}
+
+pub struct Dma {
+    channels: [Channel; 8],
+    // hdmaen
+    hdma_enable: u8,
+}
+
+impl Dma {
+    pub const fn new() -> Self {
+        Self {
+            channels: [Channel::new(); 8],
+            hdma_enable: 0,
+        }
+    }
+
+    pub fn state(&mut self, s: &mut Serializer) {
+        s.section(*b"DMA ", |s| {
+            s.value(&mut self.channels);
+            s.value(&mut self.hdma_enable);
+        });
+    }
+
+    #[inline]
+    pub const fn hdma_enable(&self) -> u8 {
+        self.hdma_enable
+    }
+
+    #[inline]
+    pub const fn set_hdma_enable(&mut self, hdma_enable: u8) {
+        self.hdma_enable = hdma_enable
+    }
+
+    #[inline]
+    pub const fn channel(&self, index: usize) -> &Channel {
+        &self.channels[index]
+    }
+
+    // channels are updated in place, hdma can run on one in the middle of a dma
+    #[inline]
+    pub const fn channel_mut(&mut self, index: usize) -> &mut Channel {
+        &mut self.channels[index]
+    }
+
+    pub fn read(&self, addr: u16, mdr: u8) -> u8 {
+        let index = ((addr >> 4) & 7) as usize;
+        self.channels[index].read((addr & 0xf) as u8, mdr)
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        let index = ((addr >> 4) & 7) as usize;
+        self.channels[index].write((addr & 0xf) as u8, value)
+    }
+}
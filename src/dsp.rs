@@ -0,0 +1,626 @@
+// s-dsp: 8 brr voices with envelopes, mixed with the echo into 32khz stereo
+
+use crate::state::{Serializer, Value};
+
+// per-voice registers, at voice << 4
+const VOLL: usize = 0x00;
+const VOLR: usize = 0x01;
+const PITCHL: usize = 0x02;
+const PITCHH: usize = 0x03;
+const SRCN: usize = 0x04;
+const ADSR1: usize = 0x05;
+const ADSR2: usize = 0x06;
+const GAIN: usize = 0x07;
+const ENVX: usize = 0x08;
+const OUTX: usize = 0x09;
+
+// global registers
+const MVOLL: usize = 0x0c;
+const MVOLR: usize = 0x1c;
+const EVOLL: usize = 0x2c;
+const EVOLR: usize = 0x3c;
+const KON: usize = 0x4c;
+const KOFF: usize = 0x5c;
+const FLG: usize = 0x6c;
+const ENDX: usize = 0x7c;
+const EFB: usize = 0x0d;
+const PMON: usize = 0x2d;
+const NON: usize = 0x3d;
+const EON: usize = 0x4d;
+const DIR: usize = 0x5d;
+const ESA: usize = 0x6d;
+const EDL: usize = 0x7d;
+// fir coefficients, at tap << 4
+const FIR: usize = 0x0f;
+
+// flg bits
+const FLG_RESET: u8 = 0x80;
+const FLG_MUTE: u8 = 0x40;
+const FLG_ECHO_DISABLE: u8 = 0x20;
+
+// the envelope and noise rates all divide a shared counter
+const COUNTER_RANGE: u32 = 2048 * 5 * 3;
+const COUNTER_RATES: [u32; 32] = [
+    COUNTER_RANGE + 1, // never fires
+    2048, 1536, 1280, 1024, 768, 640, 512, 384, 320, 256, 192, 160, 128, 96, 80,
+    64, 48, 40, 32, 24, 20, 16, 12, 10, 8, 6, 5, 4, 3, 2, 1,
+];
+const COUNTER_OFFSETS: [u32; 32] = [
+    1, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536,
+    0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 536, 0, 1040, 0, 0,
+];
+
+// 4-tap gaussian interpolation kernel
+const GAUSS: [i32; 512] = [
+       0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,    0,
+       1,    1,    1,    1,    1,    1,    1,    1,    1,    1,    1,    2,    2,    2,    2,    2,
+       2,    2,    3,    3,    3,    3,    3,    4,    4,    4,    4,    4,    5,    5,    5,    5,
+       6,    6,    6,    6,    7,    7,    7,    8,    8,    8,    9,    9,    9,   10,   10,   10,
+      11,   11,   11,   12,   12,   13,   13,   14,   14,   15,   15,   15,   16,   16,   17,   17,
+      18,   19,   19,   20,   20,   21,   21,   22,   23,   23,   24,   24,   25,   26,   27,   27,
+      28,   29,   29,   30,   31,   32,   32,   33,   34,   35,   36,   36,   37,   38,   39,   40,
+      41,   42,   43,   44,   45,   46,   47,   48,   49,   50,   51,   52,   53,   54,   55,   56,
+      58,   59,   60,   61,   62,   64,   65,   66,   67,   69,   70,   71,   73,   74,   76,   77,
+      78,   80,   81,   83,   84,   86,   87,   89,   90,   92,   94,   95,   97,   99,  100,  102,
+     104,  106,  107,  109,  111,  113,  115,  117,  118,  120,  122,  124,  126,  128,  130,  132,
+     134,  137,  139,  141,  143,  145,  147,  150,  152,  154,  156,  159,  161,  163,  166,  168,
+     171,  173,  175,  178,  180,  183,  186,  188,  191,  193,  196,  199,  201,  204,  207,  210,
+     212,  215,  218,  221,  224,  227,  230,  233,  236,  239,  242,  245,  248,  251,  254,  257,
+     260,  263,  267,  270,  273,  276,  280,  283,  286,  290,  293,  297,  300,  304,  307,  311,
+     314,  318,  321,  325,  328,  332,  336,  339,  343,  347,  351,  354,  358,  362,  366,  370,
+     374,  378,  381,  385,  389,  393,  397,  401,  405,  410,  414,  418,  422,  426,  430,  434,
+     439,  443,  447,  451,  456,  460,  464,  469,  473,  477,  482,  486,  491,  495,  499,  504,
+     508,  513,  517,  522,  527,  531,  536,  540,  545,  550,  554,  559,  563,  568,  573,  577,
+     582,  587,  592,  596,  601,  606,  611,  615,  620,  625,  630,  635,  640,  644,  649,  654,
+     659,  664,  669,  674,  678,  683,  688,  693,  698,  703,  708,  713,  718,  723,  728,  732,
+     737,  742,  747,  752,  757,  762,  767,  772,  777,  782,  787,  792,  797,  802,  806,  811,
+     816,  821,  826,  831,  836,  841,  846,  851,  855,  860,  865,  870,  875,  880,  884,  889,
+     894,  899,  904,  908,  913,  918,  923,  927,  932,  937,  941,  946,  951,  955,  960,  965,
+     969,  974,  978,  983,  988,  992,  997, 1001, 1005, 1010, 1014, 1019, 1023, 1027, 1032, 1036,
+    1040, 1045, 1049, 1053, 1057, 1061, 1066, 1070, 1074, 1078, 1082, 1086, 1090, 1094, 1098, 1102,
+    1106, 1109, 1113, 1117, 1121, 1125, 1128, 1132, 1136, 1139, 1143, 1146, 1150, 1153, 1157, 1160,
+    1164, 1167, 1170, 1174, 1177, 1180, 1183, 1186, 1190, 1193, 1196, 1199, 1202, 1205, 1207, 1210,
+    1213, 1216, 1219, 1221, 1224, 1227, 1229, 1232, 1234, 1237, 1239, 1241, 1244, 1246, 1248, 1251,
+    1253, 1255, 1257, 1259, 1261, 1263, 1265, 1267, 1269, 1270, 1272, 1274, 1275, 1277, 1279, 1280,
+    1282, 1283, 1284, 1286, 1287, 1288, 1290, 1291, 1292, 1293, 1294, 1295, 1296, 1297, 1297, 1298,
+    1299, 1300, 1300, 1301, 1302, 1302, 1303, 1303, 1303, 1304, 1304, 1304, 1304, 1304, 1305, 1305,
+];
+
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+enum Envelope {
+    #[default]
+    Release,
+    Attack,
+    Decay,
+    Sustain,
+}
+
+impl Value for Envelope {
+    fn serialize(&mut self, s: &mut Serializer) {
+        let mut value = *self as u8;
+        s.value(&mut value);
+        *self = match value {
+            1 => Self::Attack,
+            2 => Self::Decay,
+            3 => Self::Sustain,
+            _ => Self::Release,
+        };
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Voice {
+    // the last 12 decoded samples
+    buf: [i32; 12],
+    buf_pos: usize,
+    interp_pos: i32,
+
+    brr_addr: u16,
+    brr_offset: u16,
+
+    // samples left before a keyed on voice starts playing
+    kon_delay: u8,
+
+    env_mode: Envelope,
+    env: i32,
+    hidden_env: i32,
+}
+
+impl Voice {
+    fn interpolate(&self) -> i32 {
+        let offset = ((self.interp_pos >> 4) & 0xff) as usize;
+        let pos = self.buf_pos + (self.interp_pos >> 12) as usize;
+        let sample = |i: usize| self.buf[(pos + i) % 12];
+
+        // the first three taps wrap at 16 bits before the last one is added
+        let mut out = (GAUSS[255 - offset] * sample(0)) >> 11;
+        out += (GAUSS[511 - offset] * sample(1)) >> 11;
+        out += (GAUSS[256 + offset] * sample(2)) >> 11;
+        out = out as i16 as i32;
+        out += (GAUSS[offset] * sample(3)) >> 11;
+        clamp16(out) & !1
+    }
+
+    // decodes the next 4 samples of the current brr block
+    fn decode_brr(&mut self, aram: &[u8; 0x10000]) {
+        let header = aram[self.brr_addr as usize];
+        let shift = header >> 4;
+        let filter = (header >> 2) & 3;
+        let data = [
+            aram[self.brr_addr.wrapping_add(self.brr_offset) as usize],
+            aram[self.brr_addr.wrapping_add(self.brr_offset + 1) as usize],
+        ];
+
+        for i in 0..4 {
+            let nibble = (data[i >> 1] >> (if (i & 1) == 0 { 4 } else { 0 })) & 0xf;
+            let mut s = ((nibble << 4) as i8 >> 4) as i32;
+            s = (s << shift) >> 1;
+            if shift >= 0xd {
+                // invalid shifts keep only the sign
+                s = if s < 0 { -2048 } else { 0 };
+            }
+
+            let p1 = self.buf[(self.buf_pos + 11) % 12];
+            let p2 = self.buf[(self.buf_pos + 10) % 12] >> 1;
+            match filter {
+                1 => {
+                    s += p1 >> 1;
+                    s += (-p1) >> 5;
+                },
+                2 => {
+                    s += p1;
+                    s -= p2;
+                    s += p2 >> 4;
+                    s += (p1 * -3) >> 6;
+                },
+                3 => {
+                    s += p1;
+                    s -= p2;
+                    s += (p1 * -13) >> 7;
+                    s += (p2 * 3) >> 4;
+                },
+                _ => {},
+            }
+
+            // samples are clamped to 16 bits, then doubled and wrapped
+            self.buf[self.buf_pos] = (clamp16(s) * 2) as i16 as i32;
+            self.buf_pos = (self.buf_pos + 1) % 12;
+        }
+    }
+}
+
+impl Value for Voice {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.value(&mut self.buf);
+        s.value(&mut self.buf_pos);
+        s.value(&mut self.interp_pos);
+        s.value(&mut self.brr_addr);
+        s.value(&mut self.brr_offset);
+        s.value(&mut self.kon_delay);
+        s.value(&mut self.env_mode);
+        s.value(&mut self.env);
+        s.value(&mut self.hidden_env);
+        s.check(self.buf.iter().all(|&sample| sample == sample as i16 as i32) && self.buf_pos < self.buf.len());
+        s.check((0..=0x7fff).contains(&self.interp_pos) && self.brr_offset < 9 && (0..=0x7ff).contains(&self.env));
+    }
+}
+
+pub struct Dsp {
+    regs: [u8; 0x80],
+    voices: [Voice; 8],
+
+    // kon writes are latched until the next poll
+    new_kon: u8,
+    every_other: bool,
+
+    counter: u32,
+    noise: i32,
+
+    echo_offset: u16,
+    echo_length: u16,
+    echo_hist: [[i32; 2]; 8],
+    echo_pos: usize,
+
+    // interleaved stereo output since the last clear
+    samples: Vec<i16>,
+}
+
+impl Dsp {
+    pub fn new() -> Self {
+        let mut regs = [0; 0x80];
+        regs[FLG] = FLG_RESET | FLG_MUTE | FLG_ECHO_DISABLE;
+        Self {
+            regs,
+            voices: [Voice::default(); _],
+            new_kon: 0,
+            every_other: false,
+            counter: 0,
+            noise: 0x4000,
+            echo_offset: 0,
+            echo_length: 0,
+            echo_hist: [[0; 2]; 8],
+            echo_pos: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    // buffered samples aren't saved, they're sent out every frame
+    pub fn state(&mut self, s: &mut Serializer) {
+        s.section(*b"DSP ", |s| {
+            s.value(&mut self.regs);
+            s.value(&mut self.voices);
+            s.value(&mut self.new_kon);
+            s.value(&mut self.every_other);
+            s.value(&mut self.counter);
+            s.value(&mut self.noise);
+            s.value(&mut self.echo_offset);
+            s.value(&mut self.echo_length);
+            s.value(&mut self.echo_hist);
+            s.value(&mut self.echo_pos);
+            s.check(self.counter < COUNTER_RANGE && (0..=0x7fff).contains(&self.noise));
+            s.check(self.echo_length <= 0x7800 && self.echo_offset <= self.echo_length && self.echo_pos < self.echo_hist.len());
+            s.check(self.echo_hist.iter().flatten().all(|&sample| sample == sample as i16 as i32));
+        });
+    }
+
+    #[inline]
+    pub const fn read(&self, addr: u8) -> u8 {
+        self.regs[(addr & 0x7f) as usize]
+    }
+
+    pub fn write(&mut self, addr: u8, value: u8) {
+        let addr = addr as usize;
+        self.regs[addr] = value;
+        match addr {
+            KON => self.new_kon = value,
+            // any write to endx clears it
+            ENDX => self.regs[ENDX] = 0,
+            _ => {},
+        }
+    }
+
+    #[inline]
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    #[inline]
+    pub fn clear_samples(&mut self) {
+        self.samples.clear()
+    }
+
+    // whether an event at the given rate happens on this sample
+    #[inline]
+    const fn poll_counter(&self, rate: u8) -> bool {
+        (self.counter + COUNTER_OFFSETS[rate as usize]).is_multiple_of(COUNTER_RATES[rate as usize])
+    }
+
+    // produces one stereo sample, every 32 spc cycles
+    pub fn run(&mut self, aram: &mut [u8; 0x10000]) {
+        let flg = self.regs[FLG];
+
+        // key on and off are only polled every other sample
+        self.every_other = !self.every_other;
+        if self.every_other {
+            let kon = self.new_kon;
+            self.new_kon = 0;
+            let koff = self.regs[KOFF];
+            for (index, voice) in self.voices.iter_mut().enumerate() {
+                let bit = 1 << index;
+                if (kon & bit) != 0 {
+                    voice.kon_delay = 5;
+                    voice.env_mode = Envelope::Attack;
+                    self.regs[ENDX] &= !bit;
+                } else if (koff & bit) != 0 {
+                    voice.env_mode = Envelope::Release;
+                }
+            }
+        }
+        if (flg & FLG_RESET) != 0 {
+            for voice in &mut self.voices {
+                voice.env_mode = Envelope::Release;
+                voice.env = 0;
+            }
+        }
+
+        self.counter = if self.counter == 0 { COUNTER_RANGE - 1 } else { self.counter - 1 };
+        if self.poll_counter(flg & 0x1f) {
+            let feedback = (self.noise << 13) ^ (self.noise << 14);
+            self.noise = (feedback & 0x4000) ^ (self.noise >> 1);
+        }
+
+        let mut main = [0; 2];
+        let mut echo_in = [0; 2];
+        let mut prev = 0;
+        for index in 0..8 {
+            let out = self.run_voice(aram, index, prev);
+            prev = out;
+            let base = index << 4;
+            for (ch, vol) in [VOLL, VOLR].into_iter().enumerate() {
+                let amp = (out * self.regs[base | vol] as i8 as i32) >> 7;
+                main[ch] = clamp16(main[ch] + amp);
+                if (self.regs[EON] & (1 << index)) != 0 {
+                    echo_in[ch] = clamp16(echo_in[ch] + amp);
+                }
+            }
+        }
+
+        let echo = self.run_echo(aram, echo_in);
+        for (ch, (mvol, evol)) in [(MVOLL, EVOLL), (MVOLR, EVOLR)].into_iter().enumerate() {
+            let main = (main[ch] * self.regs[mvol] as i8 as i32) >> 7;
+            let echo = (echo[ch] * self.regs[evol] as i8 as i32) >> 7;
+            let out = if (flg & FLG_MUTE) != 0 { 0 } else { clamp16(main + echo) };
+            self.samples.push(out as i16);
+        }
+    }
+
+    // returns the voice's output before volume
+    fn run_voice(&mut self, aram: &mut [u8; 0x10000], index: usize, prev: i32) -> i32 {
+        let base = index << 4;
+        let bit = 1 << index;
+        let mut pitch = self.regs[base | PITCHL] as i32 | (((self.regs[base | PITCHH] & 0x3f) as i32) << 8);
+        if index > 0 && (self.regs[PMON] & bit) != 0 {
+            // pitch modulation by the previous voice's output
+            pitch += ((prev >> 5) * pitch) >> 10;
+        }
+        let dir = ((self.regs[DIR] as u16) << 8).wrapping_add((self.regs[base | SRCN] as u16) << 2);
+
+        let voice = &mut self.voices[index];
+        if voice.kon_delay > 0 {
+            if voice.kon_delay == 5 {
+                voice.brr_addr = read16(aram, dir);
+                voice.brr_offset = 1;
+                voice.buf_pos = 0;
+            }
+            // the first 12 samples are decoded while the voice is still silent
+            voice.env = 0;
+            voice.hidden_env = 0;
+            voice.kon_delay -= 1;
+            voice.interp_pos = if (voice.kon_delay & 3) != 0 { 0x4000 } else { 0 };
+            pitch = 0;
+        }
+
+        let sample = if (self.regs[NON] & bit) != 0 {
+            (self.noise << 1) as i16 as i32
+        } else {
+            voice.interpolate()
+        };
+        let out = ((sample * voice.env) >> 11) & !1;
+        self.regs[base | ENVX] = (voice.env >> 4) as u8;
+        self.regs[base | OUTX] = (out >> 8) as u8;
+
+        if voice.kon_delay == 0 {
+            let adsr = [self.regs[base | ADSR1], self.regs[base | ADSR2]];
+            let gain = self.regs[base | GAIN];
+            let env = self.run_envelope(index, adsr, gain);
+            self.voices[index].env = env;
+        }
+
+        let voice = &mut self.voices[index];
+        if voice.interp_pos >= 0x4000 {
+            voice.decode_brr(aram);
+            voice.brr_offset += 2;
+            if voice.brr_offset >= 9 {
+                let header = aram[voice.brr_addr as usize];
+                voice.brr_addr = voice.brr_addr.wrapping_add(9);
+                if (header & 1) != 0 {
+                    // end of the sample, jump to its loop point or stop
+                    voice.brr_addr = read16(aram, dir.wrapping_add(2));
+                    self.regs[ENDX] |= bit;
+                    if (header & 2) == 0 {
+                        voice.env_mode = Envelope::Release;
+                        voice.env = 0;
+                    }
+                }
+                voice.brr_offset = 1;
+            }
+        }
+        voice.interp_pos = ((voice.interp_pos & 0x3fff) + pitch).min(0x7fff);
+
+        out
+    }
+
+    // steps a voice's envelope, returning its new level
+    fn run_envelope(&mut self, index: usize, adsr: [u8; 2], gain: u8) -> i32 {
+        let voice = self.voices[index];
+        let mut env = voice.env;
+        if voice.env_mode == Envelope::Release {
+            return (env - 8).max(0)
+        }
+
+        let rate;
+        let mut sustain = adsr[1];
+        if (adsr[0] & 0x80) != 0 {
+            if voice.env_mode == Envelope::Attack {
+                rate = ((adsr[0] & 0x0f) << 1) + 1;
+                env += if rate < 31 { 0x20 } else { 0x400 };
+            } else {
+                env -= 1;
+                env -= env >> 8;
+                rate = if voice.env_mode == Envelope::Decay { ((adsr[0] >> 3) & 0x0e) + 0x10 } else { adsr[1] & 0x1f };
+            }
+        } else {
+            // the sustain level is compared against gain instead of adsr2 here
+            sustain = gain;
+            let mode = gain >> 5;
+            if mode < 4 {
+                env = (gain as i32) << 4;
+                rate = 31;
+            } else {
+                rate = gain & 0x1f;
+                match mode {
+                    // linear decrease
+                    4 => env -= 0x20,
+                    // exponential decrease
+                    5 => {
+                        env -= 1;
+                        env -= env >> 8;
+                    },
+                    // linear increase
+                    6 => env += 0x20,
+                    // bent increase
+                    _ => env += if voice.hidden_env >= 0x600 { 0x08 } else { 0x20 },
+                }
+            }
+        }
+
+        let fire = self.poll_counter(rate);
+        let voice = &mut self.voices[index];
+        if voice.env_mode == Envelope::Decay && (env >> 8) == (sustain >> 5) as i32 {
+            voice.env_mode = Envelope::Sustain;
+        }
+        voice.hidden_env = env;
+        if !(0..=0x7ff).contains(&env) {
+            env = if env < 0 { 0 } else { 0x7ff };
+            if voice.env_mode == Envelope::Attack {
+                voice.env_mode = Envelope::Decay;
+            }
+        }
+
+        if fire { env } else { voice.env }
+    }
+
+    // reads and writes the echo ring buffer, returning the filtered echo
+    fn run_echo(&mut self, aram: &mut [u8; 0x10000], echo_in: [i32; 2]) -> [i32; 2] {
+        let addr = ((self.regs[ESA] as u16) << 8).wrapping_add(self.echo_offset);
+        self.echo_pos = (self.echo_pos + 1) & 7;
+        self.echo_hist[self.echo_pos] = [
+            read16(aram, addr) as i16 as i32 >> 1,
+            read16(aram, addr.wrapping_add(2)) as i16 as i32 >> 1,
+        ];
+
+        let mut echo = [0; 2];
+        for (ch, echo) in echo.iter_mut().enumerate() {
+            // the oldest sample gets the first tap, the first 7 taps wrap at 16 bits
+            let mut sum = 0;
+            for tap in 0..8 {
+                if tap == 7 {
+                    sum = sum as i16 as i32;
+                }
+                let coef = self.regs[(tap << 4) | FIR] as i8 as i32;
+                sum += (self.echo_hist[(self.echo_pos + 1 + tap) & 7][ch] * coef) >> 6;
+            }
+            *echo = clamp16(sum) & !1;
+        }
+
+        if (self.regs[FLG] & FLG_ECHO_DISABLE) == 0 {
+            for (ch, echo) in echo.iter().enumerate() {
+                let feedback = (echo * self.regs[EFB] as i8 as i32) >> 7;
+                let value = (clamp16(echo_in[ch] + feedback) & !1) as u16;
+                write16(aram, addr.wrapping_add((ch as u16) << 1), value);
+            }
+        }
+
+        // the buffer length only changes when the offset wraps
+        if self.echo_offset == 0 {
+            self.echo_length = ((self.regs[EDL] & 0x0f) as u16) << 11;
+        }
+        self.echo_offset += 4;
+        if self.echo_offset >= self.echo_length {
+            self.echo_offset = 0;
+        }
+
+        echo
+    }
+}
+
+#[inline]
+const fn clamp16(value: i32) -> i32 {
+    if value < i16::MIN as i32 {
+        i16::MIN as i32
+    } else if value > i16::MAX as i32 {
+        i16::MAX as i32
+    } else {
+        value
+    }
+}
+
+#[inline]
+const fn read16(aram: &[u8; 0x10000], addr: u16) -> u16 {
+    (aram[addr as usize] as u16) | ((aram[addr.wrapping_add(1) as usize] as u16) << 8)
+}
+
+#[inline]
+const fn write16(aram: &mut [u8; 0x10000], addr: u16, value: u16) {
+    aram[addr as usize] = value as u8;
+    aram[addr.wrapping_add(1) as usize] = (value >> 8) as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sets up voice 0 to play a square wave brr block at $0300 from a directory at $0200
+    fn voice(header: u8) -> (Dsp, Box<[u8; 0x10000]>) {
+        let mut aram = Box::new([0; 0x10000]);
+        aram[0x200..0x204].copy_from_slice(&[0x00, 0x03, 0x00, 0x03]);
+        aram[0x300] = header;
+        aram[0x301..0x305].fill(0x77);
+        aram[0x305..0x309].fill(0x99);
+
+        let mut dsp = Dsp::new();
+        for (reg, value) in [
+            (FLG, FLG_ECHO_DISABLE),
+            (MVOLL, 0x7f), (MVOLR, 0x7f),
+            (VOLL, 0x7f), (VOLR, 0x7f),
+            (PITCHH, 0x10),
+            (DIR, 0x02),
+            (ADSR1, 0x8f), (ADSR2, 0xe0),
+            (KON, 0x01),
+        ] {
+            dsp.write(reg as u8, value);
+        }
+        (dsp, aram)
+    }
+
+    fn run(dsp: &mut Dsp, aram: &mut [u8; 0x10000], samples: usize) {
+        for _ in 0..samples {
+            dsp.run(aram);
+        }
+    }
+
+    #[test]
+    fn looping_voice_plays_until_key_off() {
+        // shift 12, loop, end
+        let (mut dsp, mut aram) = voice(0xc3);
+        run(&mut dsp, &mut aram, 200);
+        let peak = dsp.samples().iter().map(|sample| sample.unsigned_abs()).max().unwrap();
+        assert!(peak > 4000, "peak {peak}");
+        // the fastest attack reaches full volume right away
+        assert_eq!(dsp.read(ENVX as u8), 0x7f);
+        assert_eq!(dsp.read(ENDX as u8), 0x01);
+        dsp.write(ENDX as u8, 0xff);
+        assert_eq!(dsp.read(ENDX as u8), 0x00);
+
+        // release takes 8 off the envelope every sample
+        dsp.write(KOFF as u8, 0x01);
+        run(&mut dsp, &mut aram, 300);
+        assert_eq!(dsp.read(ENVX as u8), 0);
+        dsp.clear_samples();
+        run(&mut dsp, &mut aram, 16);
+        assert!(dsp.samples().iter().all(|&sample| sample == 0));
+    }
+
+    #[test]
+    fn sample_end_without_loop_silences_the_voice() {
+        // shift 12, end
+        let (mut dsp, mut aram) = voice(0xc1);
+        run(&mut dsp, &mut aram, 200);
+        assert_eq!(dsp.read(ENDX as u8), 0x01);
+        assert_eq!(dsp.read(ENVX as u8), 0);
+    }
+
+    #[test]
+    fn mute_and_reset_flags() {
+        let (mut dsp, mut aram) = voice(0xc3);
+        dsp.write(FLG as u8, FLG_MUTE | FLG_ECHO_DISABLE);
+        run(&mut dsp, &mut aram, 200);
+        assert!(dsp.samples().iter().all(|&sample| sample == 0));
+        assert_eq!(dsp.read(ENVX as u8), 0x7f);
+
+        // soft reset keys every voice off at once
+        dsp.write(FLG as u8, FLG_RESET | FLG_MUTE | FLG_ECHO_DISABLE);
+        run(&mut dsp, &mut aram, 1);
+        assert_eq!(dsp.read(ENVX as u8), 0);
+    }
+}
@@ -1,10 +1,11 @@
 // libretro exports
 
-use std::{ffi::{c_char, c_uint, c_void}, mem::{self, MaybeUninit}, ptr, slice};
+use std::{ffi::{c_char, c_uint, c_void, CStr}, mem::{self, MaybeUninit}, ptr, slice};
 
-use crate::{retro, App};
+use crate::{joypad::DeviceType, remap::{self, Remap}, retro, App};
 
 static mut APP: MaybeUninit<App> = MaybeUninit::uninit();
+static mut GAME_LOADED: bool = false;
 
 static mut ENVIRON_CB: Option<retro::environment_t> = None;
 static mut VIDEO_CB: Option<retro::video_refresh_t> = None;
@@ -13,6 +14,41 @@ static mut AUDIO_BATCH_CB: Option<retro::audio_sample_batch_t> = None;
 static mut INPUT_POLL_CB: Option<retro::input_poll_t> = None;
 static mut INPUT_STATE_CB: Option<retro::input_state_t> = None;
 
+const DEVICE_MULTITAP: c_uint = retro::device_subclass(retro::DEVICE_JOYPAD, 0);
+
+static mut CONTROLLER_TYPES: [retro::controller_description; 4] = [
+    retro::controller_description { desc: c"SNES Joypad".as_ptr(), id: retro::DEVICE_JOYPAD },
+    retro::controller_description { desc: c"SNES Mouse".as_ptr(), id: retro::DEVICE_MOUSE },
+    retro::controller_description { desc: c"Super Multitap".as_ptr(), id: DEVICE_MULTITAP },
+    retro::controller_description { desc: c"None".as_ptr(), id: retro::DEVICE_NONE },
+];
+
+// one entry per port, terminated by an empty one
+static mut CONTROLLER_INFO: [retro::controller_info; 3] = [
+    retro::controller_info { types: &raw const CONTROLLER_TYPES as _, num_types: 4 },
+    retro::controller_info { types: &raw const CONTROLLER_TYPES as _, num_types: 4 },
+    retro::controller_info { types: ptr::null(), num_types: 0 },
+];
+
+static mut VARIABLES: [retro::variable; 4] = [
+    retro::variable { key: c"smw_jump_buttons".as_ptr(), value: c"Jump buttons; B jumps, A spin jumps|A jumps, B spin jumps".as_ptr() },
+    retro::variable { key: c"smw_spin_on_r".as_ptr(), value: c"Spin Jump on R; disabled|enabled".as_ptr() },
+    retro::variable { key: c"smw_renderer".as_ptr(), value: c"Renderer; OpenGL|Software".as_ptr() },
+    retro::variable { key: ptr::null(), value: ptr::null() },
+];
+
+// two pads, or five with a multitap
+const PLAYERS: c_uint = 5;
+
+const PORTS: usize = 2;
+static mut PORT_DEVICES: [DeviceType; PORTS] = [DeviceType::Joypad; PORTS];
+
+// whether the renderer option asks for opengl, whether the frontend took the hw render request,
+// and whether there is a gl context to draw with
+static mut USE_GL: bool = true;
+static mut HW_RENDER_ENABLED: bool = false;
+static mut HW_CONTEXT: bool = false;
+
 static mut HW_RENDER: retro::hw_render_callback = retro::hw_render_callback {
     context_type: retro::HW_CONTEXT_OPENGL_CORE,
     context_reset: Some(context_reset),
@@ -32,9 +68,11 @@ extern "C" fn context_reset() {
     eprintln!("Context reset!");
 
     unsafe {
-        let get_proc_address = HW_RENDER.get_proc_address.unwrap_unchecked();
-        #[allow(static_mut_refs)]
-        APP.assume_init_mut().context_reset(|sym| mem::transmute(get_proc_address(sym.as_ptr())));
+        HW_CONTEXT = true;
+        if GAME_LOADED {
+            #[allow(static_mut_refs)]
+            select_renderer(APP.assume_init_mut());
+        }
     }
 }
 
@@ -42,8 +80,38 @@ extern "C" fn context_destroy() {
     eprintln!("Context destroy!");
 
     unsafe {
-        #[allow(static_mut_refs)]
-        APP.assume_init_mut().context_destroy();
+        // the context is still current, so the gl renderer can clean up as it goes
+        HW_CONTEXT = false;
+        if GAME_LOADED {
+            #[allow(static_mut_refs)]
+            select_renderer(APP.assume_init_mut());
+        }
+    }
+}
+
+// draws with opengl when there's a context and the option allows it, otherwise in software
+unsafe fn select_renderer(app: &mut App) {
+    unsafe {
+        if !(HW_RENDER_ENABLED && HW_CONTEXT && USE_GL) {
+            if app.uses_gl() {
+                eprintln!("Switching to software rendering.");
+                app.use_software();
+            }
+            return
+        }
+        if app.uses_gl() {
+            return
+        }
+        // a context without a way to load gl functions is left unused
+        let (Some(get_proc_address), Some(get_current_framebuffer)) = (HW_RENDER.get_proc_address, HW_RENDER.get_current_framebuffer) else {
+            eprintln!("No get_proc_address or get_current_framebuffer, staying on software rendering.");
+            return
+        };
+        let load = |sym: &CStr| mem::transmute(get_proc_address(sym.as_ptr()));
+        match app.use_gl(load, move || get_current_framebuffer()) {
+            Ok(()) => eprintln!("Switching to OpenGL rendering."),
+            Err(error) => eprintln!("OpenGL rendering failed, staying on software rendering: {error}"),
+        }
     }
 }
 
@@ -51,7 +119,10 @@ extern "C" fn context_destroy() {
 extern "C" fn retro_set_environment(cb: retro::environment_t) {
     unsafe {
         ENVIRON_CB = Some(cb);
-        // let environ_cb = ENVIRON_CB.unwrap_unchecked();
+        let environ_cb = ENVIRON_CB.unwrap_unchecked();
+
+        environ_cb(retro::ENVIRONMENT_SET_CONTROLLER_INFO, &raw mut CONTROLLER_INFO as _);
+        environ_cb(retro::ENVIRONMENT_SET_VARIABLES, &raw mut VARIABLES as _);
 
         // let no_rom = true;
         // environ_cb(retro::ENVIRONMENT_SET_SUPPORT_NO_GAME, &raw const no_rom as _);
@@ -117,17 +188,34 @@ extern "C" fn retro_get_system_av_info(info: *mut retro::system_av_info) {
         *info = retro::system_av_info::default()
             .geometry(retro::game_geometry::default()
                 .base_width(256)
-                .base_height(240)
-                .max_width(256)
-                .max_height(240)
-                .aspect_ratio(4.0 / 3.0))
+                .base_height(224)
+                .max_width(512)
+                .max_height(478)
+                // 8:7 pixels
+                .aspect_ratio(256.0 * 8.0 / 7.0 / 224.0))
             .timing(retro::system_timing::default()
-                .fps(60.0));
+                // 21.477 mhz master clock over 262 lines of 1364 cycles
+                .fps(60.0985)
+                // one dsp sample every 32 cycles of the 1.024 mhz spc
+                .sample_rate(32000.0));
     }
 }
 
 #[unsafe(no_mangle)]
-extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+extern "C" fn retro_set_controller_port_device(port: c_uint, device: c_uint) {
+    unsafe {
+        let port = port as usize;
+        if port >= PORTS {
+            return
+        }
+        PORT_DEVICES[port] = match device {
+            retro::DEVICE_JOYPAD => DeviceType::Joypad,
+            retro::DEVICE_MOUSE => DeviceType::Mouse,
+            DEVICE_MULTITAP => DeviceType::Multitap,
+            _ => DeviceType::None,
+        };
+    }
+}
 
 #[unsafe(no_mangle)]
 extern "C" fn retro_reset() {}
@@ -138,18 +226,133 @@ extern "C" fn retro_run() {
         #[allow(static_mut_refs)]
         let app = APP.assume_init_mut();
 
+        let mut updated = false;
+        if ENVIRON_CB.unwrap_unchecked()(retro::ENVIRONMENT_GET_VARIABLE_UPDATE, &raw mut updated as _) && updated {
+            update_options(app);
+        }
+
+        INPUT_POLL_CB.unwrap_unchecked()();
+        let input_state = INPUT_STATE_CB.unwrap_unchecked();
+        // each pad takes the next libretro port, so a multitap on port 2 covers players 2-5
+        let mut player = 0;
+        for (port, device_type) in PORT_DEVICES.into_iter().enumerate() {
+            app.connect(port, device_type);
+            match device_type {
+                DeviceType::None => {},
+                DeviceType::Joypad | DeviceType::Multitap => {
+                    let pads = if device_type == DeviceType::Multitap { 4 } else { 1 };
+                    for pad in 0..pads {
+                        app.set_buttons(port, pad, read_joypad(input_state, player));
+                        player += 1;
+                    }
+                },
+                DeviceType::Mouse => {
+                    let state = |id| input_state(player as _, retro::DEVICE_MOUSE, 0, id);
+                    app.set_mouse(
+                        port,
+                        state(retro::DEVICE_ID_MOUSE_X) as i32,
+                        state(retro::DEVICE_ID_MOUSE_Y) as i32,
+                        state(retro::DEVICE_ID_MOUSE_LEFT) != 0,
+                        state(retro::DEVICE_ID_MOUSE_RIGHT) != 0,
+                    );
+                    player += 1;
+                },
+            }
+        }
+
         app.run();
+
+        let video_cb = VIDEO_CB.unwrap_unchecked();
+        match app.frame() {
+            (Some(frame), width, height) => video_cb(frame.as_ptr() as _, width as _, height as _, App::FRAME_PITCH),
+            (None, width, height) => video_cb(retro::HW_FRAME_BUFFER_VALID, width as _, height as _, 0),
+        }
+
+        let samples = app.samples();
+        AUDIO_BATCH_CB.unwrap_unchecked()(samples.as_ptr(), samples.len() / 2);
+    }
+}
+
+// the buttons held on a libretro port, in snes report order
+unsafe fn read_joypad(input_state: retro::input_state_t, port: c_uint) -> u16 {
+    // libretro's joypad ids follow the snes report order, from b down to r
+    let mut buttons = 0;
+    for id in retro::DEVICE_ID_JOYPAD_B..=retro::DEVICE_ID_JOYPAD_R {
+        if unsafe { input_state(port, retro::DEVICE_JOYPAD, 0, id) } != 0 {
+            buttons |= 0x8000 >> id;
+        }
+    }
+    buttons
+}
+
+unsafe fn get_variable(key: &CStr) -> Option<&'static CStr> {
+    unsafe {
+        let mut variable = retro::variable { key: key.as_ptr(), value: ptr::null() };
+        if ENVIRON_CB.unwrap_unchecked()(retro::ENVIRONMENT_GET_VARIABLE, &raw mut variable as _) && !variable.value.is_null() {
+            Some(CStr::from_ptr(variable.value))
+        } else {
+            None
+        }
+    }
+}
+
+// reads the core options into the remap and renderer, and relabels the buttons to match
+unsafe fn update_options(app: &mut App) {
+    unsafe {
+        USE_GL = get_variable(c"smw_renderer") != Some(c"Software");
+        select_renderer(app);
+
+        let remap = Remap::default()
+            .swap_jump(get_variable(c"smw_jump_buttons") == Some(c"A jumps, B spin jumps"))
+            .spin_on_r(get_variable(c"smw_spin_on_r") == Some(c"enabled"));
+        app.set_remap(remap);
+
+        let mut descriptors = Vec::new();
+        for port in 0..PLAYERS {
+            for id in 0..remap::BUTTONS {
+                descriptors.push(retro::input_descriptor {
+                    port,
+                    device: retro::DEVICE_JOYPAD,
+                    index: 0,
+                    id: id as _,
+                    description: remap.label(id).as_ptr(),
+                });
+            }
+        }
+        descriptors.push(retro::input_descriptor { port: 0, device: 0, index: 0, id: 0, description: ptr::null() });
+        ENVIRON_CB.unwrap_unchecked()(retro::ENVIRONMENT_SET_INPUT_DESCRIPTORS, descriptors.as_mut_ptr() as _);
     }
 }
 
 #[unsafe(no_mangle)]
-extern "C" fn retro_serialize_size() -> usize { 0 }
+extern "C" fn retro_serialize_size() -> usize {
+    unsafe {
+        // every state has the same size, whatever is plugged into the ports, so this is the size of the current one
+        #[allow(static_mut_refs)]
+        APP.assume_init_mut().save_state().len()
+    }
+}
 
 #[unsafe(no_mangle)]
-extern "C" fn retro_serialize(_data: *mut c_void, _len: usize) -> bool { false }
+extern "C" fn retro_serialize(data: *mut c_void, len: usize) -> bool {
+    unsafe {
+        #[allow(static_mut_refs)]
+        let state = APP.assume_init_mut().save_state();
+        if state.len() > len {
+            return false
+        }
+        ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+        true
+    }
+}
 
 #[unsafe(no_mangle)]
-extern "C" fn retro_unserialize(_data: *const c_void, _len: usize) -> bool { false }
+extern "C" fn retro_unserialize(data: *const c_void, len: usize) -> bool {
+    unsafe {
+        #[allow(static_mut_refs)]
+        APP.assume_init_mut().load_state(slice::from_raw_parts(data as _, len))
+    }
+}
 
 #[unsafe(no_mangle)]
 extern "C" fn retro_cheat_reset() {}
@@ -168,16 +371,22 @@ extern "C" fn retro_load_game(game: *const retro::game_info) -> bool {
             return false;
         }
 
-        if !environ_cb(retro::ENVIRONMENT_SET_HW_RENDER, &raw mut HW_RENDER as _) {
-            return false;
-        }
-
         let game = &*game;
 
+        // frames are drawn in software until the context is reset, or for good if there is none
+        HW_RENDER_ENABLED = false;
+        HW_CONTEXT = false;
         #[allow(static_mut_refs)]
-        APP.write(App::load_game(
+        let app = APP.write(App::load_game(
             slice::from_raw_parts(game.data as _, game.size),
         ));
+        GAME_LOADED = true;
+        update_options(app);
+
+        HW_RENDER_ENABLED = environ_cb(retro::ENVIRONMENT_SET_HW_RENDER, &raw mut HW_RENDER as _);
+        if !HW_RENDER_ENABLED {
+            eprintln!("HW render is not supported, using software rendering.");
+        }
 
         eprintln!("Loaded game!");
         true
@@ -190,6 +399,8 @@ extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const retro::g
 #[unsafe(no_mangle)]
 extern "C" fn retro_unload_game() {
     unsafe {
+        // a context destroyed after this has nothing left to switch
+        GAME_LOADED = false;
         #[allow(static_mut_refs)]
         APP.assume_init_drop();
     }
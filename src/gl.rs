@@ -0,0 +1,118 @@
+// opengl 3.1 bindings, loaded through the frontend's get_proc_address
+
+use std::{ffi::{c_char, c_void, CStr}, mem};
+
+pub type GLenum = u32;
+pub type GLuint = u32;
+pub type GLint = i32;
+pub type GLsizei = i32;
+pub type GLbitfield = u32;
+
+pub const NO_ERROR: GLenum = 0;
+
+pub const TRIANGLES: GLenum = 0x0004;
+
+pub const BLEND: GLenum = 0x0be2;
+pub const DEPTH_TEST: GLenum = 0x0b71;
+pub const STENCIL_TEST: GLenum = 0x0b90;
+pub const SCISSOR_TEST: GLenum = 0x0c11;
+pub const CULL_FACE: GLenum = 0x0b44;
+
+pub const UNPACK_ALIGNMENT: GLenum = 0x0cf5;
+pub const UNPACK_ROW_LENGTH: GLenum = 0x0cf2;
+
+pub const TEXTURE_2D: GLenum = 0x0de1;
+pub const TEXTURE0: GLenum = 0x84c0;
+pub const TEXTURE_MAG_FILTER: GLenum = 0x2800;
+pub const TEXTURE_MIN_FILTER: GLenum = 0x2801;
+pub const NEAREST: GLint = 0x2600;
+
+pub const UNSIGNED_BYTE: GLenum = 0x1401;
+pub const UNSIGNED_SHORT: GLenum = 0x1403;
+pub const RGBA: GLenum = 0x1908;
+pub const RGBA8: GLint = 0x8058;
+pub const R8UI: GLint = 0x8232;
+pub const R16UI: GLint = 0x8234;
+pub const RED_INTEGER: GLenum = 0x8d94;
+
+pub const FRAMEBUFFER: GLenum = 0x8d40;
+pub const READ_FRAMEBUFFER: GLenum = 0x8ca8;
+pub const DRAW_FRAMEBUFFER: GLenum = 0x8ca9;
+pub const COLOR_ATTACHMENT0: GLenum = 0x8ce0;
+pub const FRAMEBUFFER_COMPLETE: GLenum = 0x8cd5;
+pub const COLOR_BUFFER_BIT: GLbitfield = 0x4000;
+
+pub const FRAGMENT_SHADER: GLenum = 0x8b30;
+pub const VERTEX_SHADER: GLenum = 0x8b31;
+pub const COMPILE_STATUS: GLenum = 0x8b81;
+pub const LINK_STATUS: GLenum = 0x8b82;
+pub const INFO_LOG_LENGTH: GLenum = 0x8b84;
+
+// declares the function table, each entry loaded from its gl name
+macro_rules! functions {
+    ($($field:ident = $name:literal: fn($($arg:ty),*) $(-> $ret:ty)?;)*) => {
+        pub struct Gl {
+            $(pub $field: unsafe extern "system" fn($($arg),*) $(-> $ret)?,)*
+        }
+
+        impl Gl {
+            // none if the context is missing any of the functions
+            pub fn load<F: FnMut(&CStr) -> *const c_void>(mut f: F) -> Option<Self> {
+                Some(Self {
+                    $($field: {
+                        let ptr = f($name);
+                        if ptr.is_null() {
+                            return None
+                        }
+                        unsafe { mem::transmute::<*const c_void, unsafe extern "system" fn($($arg),*) $(-> $ret)?>(ptr) }
+                    },)*
+                })
+            }
+        }
+    };
+}
+
+functions! {
+    get_error = c"glGetError": fn() -> GLenum;
+    disable = c"glDisable": fn(GLenum);
+    viewport = c"glViewport": fn(GLint, GLint, GLsizei, GLsizei);
+    pixel_store_i = c"glPixelStorei": fn(GLenum, GLint);
+    draw_arrays = c"glDrawArrays": fn(GLenum, GLint, GLsizei);
+
+    gen_textures = c"glGenTextures": fn(GLsizei, *mut GLuint);
+    delete_textures = c"glDeleteTextures": fn(GLsizei, *const GLuint);
+    bind_texture = c"glBindTexture": fn(GLenum, GLuint);
+    active_texture = c"glActiveTexture": fn(GLenum);
+    tex_parameter_i = c"glTexParameteri": fn(GLenum, GLenum, GLint);
+    tex_image_2d = c"glTexImage2D": fn(GLenum, GLint, GLint, GLsizei, GLsizei, GLint, GLenum, GLenum, *const c_void);
+    tex_sub_image_2d = c"glTexSubImage2D": fn(GLenum, GLint, GLint, GLint, GLsizei, GLsizei, GLenum, GLenum, *const c_void);
+
+    gen_framebuffers = c"glGenFramebuffers": fn(GLsizei, *mut GLuint);
+    delete_framebuffers = c"glDeleteFramebuffers": fn(GLsizei, *const GLuint);
+    bind_framebuffer = c"glBindFramebuffer": fn(GLenum, GLuint);
+    framebuffer_texture_2d = c"glFramebufferTexture2D": fn(GLenum, GLenum, GLenum, GLuint, GLint);
+    check_framebuffer_status = c"glCheckFramebufferStatus": fn(GLenum) -> GLenum;
+    blit_framebuffer = c"glBlitFramebuffer": fn(GLint, GLint, GLint, GLint, GLint, GLint, GLint, GLint, GLbitfield, GLenum);
+
+    gen_vertex_arrays = c"glGenVertexArrays": fn(GLsizei, *mut GLuint);
+    delete_vertex_arrays = c"glDeleteVertexArrays": fn(GLsizei, *const GLuint);
+    bind_vertex_array = c"glBindVertexArray": fn(GLuint);
+
+    create_shader = c"glCreateShader": fn(GLenum) -> GLuint;
+    delete_shader = c"glDeleteShader": fn(GLuint);
+    shader_source = c"glShaderSource": fn(GLuint, GLsizei, *const *const c_char, *const GLint);
+    compile_shader = c"glCompileShader": fn(GLuint);
+    get_shader_iv = c"glGetShaderiv": fn(GLuint, GLenum, *mut GLint);
+    get_shader_info_log = c"glGetShaderInfoLog": fn(GLuint, GLsizei, *mut GLsizei, *mut c_char);
+
+    create_program = c"glCreateProgram": fn() -> GLuint;
+    delete_program = c"glDeleteProgram": fn(GLuint);
+    attach_shader = c"glAttachShader": fn(GLuint, GLuint);
+    link_program = c"glLinkProgram": fn(GLuint);
+    get_program_iv = c"glGetProgramiv": fn(GLuint, GLenum, *mut GLint);
+    get_program_info_log = c"glGetProgramInfoLog": fn(GLuint, GLsizei, *mut GLsizei, *mut c_char);
+    use_program = c"glUseProgram": fn(GLuint);
+    get_uniform_location = c"glGetUniformLocation": fn(GLuint, *const c_char) -> GLint;
+    uniform_1i = c"glUniform1i": fn(GLint, GLint);
+    uniform_1iv = c"glUniform1iv": fn(GLint, GLsizei, *const GLint);
+}
@@ -0,0 +1,327 @@
+// s-cpu internal registers ($4200-$421f)
+
+use crate::state::Serializer;
+
+pub struct Io {
+    // nmitimen
+    nmi_enable: bool,
+    irq_mode: u8,
+    auto_joypad: bool,
+
+    // wrio
+    wrio: u8,
+
+    // multiplication/division unit
+    wrmpya: u8,
+    wrdiva: u16,
+    rddiv: u16,
+    rdmpy: u16,
+    alu_shift: u32,
+    mpy_steps: u8,
+    div_steps: u8,
+
+    // irq timers
+    htime: u16,
+    vtime: u16,
+
+    // memsel
+    fast_rom: bool,
+
+    // status
+    nmi_flag: bool,
+    irq_flag: bool,
+    nmi: bool,
+    vblank: bool,
+    hblank: bool,
+    joypad_busy: bool,
+
+    // auto-joypad results
+    joy: [u16; 4],
+}
+
+impl Io {
+    const IRQ_NONE: u8 = 0;
+    const IRQ_H: u8 = 1;
+    const IRQ_V: u8 = 2;
+    const IRQ_HV: u8 = 3;
+
+    // 5a22 revision reported in rdnmi
+    const CPU_VERSION: u8 = 2;
+
+    pub fn new() -> Self {
+        Self {
+            nmi_enable: false,
+            irq_mode: Self::IRQ_NONE,
+            auto_joypad: false,
+            wrio: 0xff,
+            wrmpya: 0xff,
+            wrdiva: 0xffff,
+            rddiv: 0,
+            rdmpy: 0,
+            alu_shift: 0,
+            mpy_steps: 0,
+            div_steps: 0,
+            htime: 0x1ff,
+            vtime: 0x1ff,
+            fast_rom: false,
+            nmi_flag: false,
+            irq_flag: false,
+            nmi: false,
+            vblank: false,
+            hblank: false,
+            joypad_busy: false,
+            joy: [0; 4],
+        }
+    }
+
+    pub fn state(&mut self, s: &mut Serializer) {
+        s.section(*b"IO  ", |s| {
+            s.value(&mut self.nmi_enable);
+            s.value(&mut self.irq_mode);
+            s.value(&mut self.auto_joypad);
+            s.value(&mut self.wrio);
+            s.value(&mut self.wrmpya);
+            s.value(&mut self.wrdiva);
+            s.value(&mut self.rddiv);
+            s.value(&mut self.rdmpy);
+            s.value(&mut self.alu_shift);
+            s.value(&mut self.mpy_steps);
+            s.value(&mut self.div_steps);
+            s.value(&mut self.htime);
+            s.value(&mut self.vtime);
+            s.value(&mut self.fast_rom);
+            s.value(&mut self.nmi_flag);
+            s.value(&mut self.irq_flag);
+            s.value(&mut self.nmi);
+            s.value(&mut self.vblank);
+            s.value(&mut self.hblank);
+            s.value(&mut self.joypad_busy);
+            s.value(&mut self.joy);
+        });
+    }
+
+    pub fn read(&mut self, addr: u16, mdr: u8) -> u8 {
+        match addr {
+            0x4210 => {
+                let value = ((self.nmi_flag as u8) << 7) | (mdr & 0x70) | Self::CPU_VERSION;
+                self.nmi_flag = false;
+                value
+            },
+            0x4211 => {
+                let value = ((self.irq_flag as u8) << 7) | (mdr & 0x7f);
+                self.irq_flag = false;
+                value
+            },
+            0x4212 => ((self.vblank as u8) << 7) | ((self.hblank as u8) << 6) | (mdr & 0x3e) | self.joypad_busy as u8,
+            0x4213 => self.wrio,
+            0x4214 => self.rddiv as u8,
+            0x4215 => (self.rddiv >> 8) as u8,
+            0x4216 => self.rdmpy as u8,
+            0x4217 => (self.rdmpy >> 8) as u8,
+            0x4218..=0x421f => {
+                let joy = self.joy[((addr - 0x4218) >> 1) as usize];
+                if (addr & 1) == 0 { joy as u8 } else { (joy >> 8) as u8 }
+            },
+            // write-only registers
+            _ => mdr,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4200 => {
+                let nmi_enable = (value & 0x80) != 0;
+                // enabling nmi during vblank before rdnmi is read fires it right away
+                if nmi_enable && !self.nmi_enable && self.nmi_flag {
+                    self.nmi = true;
+                }
+                self.nmi_enable = nmi_enable;
+                self.irq_mode = (value >> 4) & 3;
+                if self.irq_mode == Self::IRQ_NONE {
+                    self.irq_flag = false;
+                }
+                self.auto_joypad = (value & 1) != 0;
+            },
+            0x4201 => self.wrio = value,
+            0x4202 => self.wrmpya = value,
+            0x4203 => {
+                // the product is built one bit per cpu cycle
+                self.rdmpy = 0;
+                self.rddiv = ((value as u16) << 8) | self.wrmpya as u16;
+                self.alu_shift = value as u32;
+                self.mpy_steps = 8;
+                self.div_steps = 0;
+            },
+            0x4204 => self.wrdiva = (self.wrdiva & 0xff00) | value as u16,
+            0x4205 => self.wrdiva = (self.wrdiva & 0x00ff) | ((value as u16) << 8),
+            0x4206 => {
+                // restoring division, one quotient bit per cpu cycle
+                self.rdmpy = self.wrdiva;
+                self.alu_shift = (value as u32) << 16;
+                self.div_steps = 16;
+                self.mpy_steps = 0;
+            },
+            0x4207 => self.htime = (self.htime & 0x100) | value as u16,
+            0x4208 => self.htime = (self.htime & 0x0ff) | (((value & 1) as u16) << 8),
+            0x4209 => self.vtime = (self.vtime & 0x100) | value as u16,
+            0x420a => self.vtime = (self.vtime & 0x0ff) | (((value & 1) as u16) << 8),
+            0x420d => self.fast_rom = (value & 1) != 0,
+            // read-only registers
+            _ => {},
+        }
+    }
+
+    // runs the multiplication/division unit for one cpu cycle
+    pub fn step_alu(&mut self) {
+        if self.mpy_steps > 0 {
+            self.mpy_steps -= 1;
+            if (self.rddiv & 1) != 0 {
+                self.rdmpy = self.rdmpy.wrapping_add(self.alu_shift as u16);
+            }
+            self.rddiv >>= 1;
+            self.alu_shift <<= 1;
+        } else if self.div_steps > 0 {
+            self.div_steps -= 1;
+            self.rddiv <<= 1;
+            self.alu_shift >>= 1;
+            if self.rdmpy as u32 >= self.alu_shift {
+                self.rdmpy -= self.alu_shift as u16;
+                self.rddiv |= 1;
+            }
+        }
+    }
+
+    #[inline]
+    pub const fn wrio(&self) -> u8 {
+        self.wrio
+    }
+
+    #[inline]
+    pub const fn fast_rom(&self) -> bool {
+        self.fast_rom
+    }
+
+    // position of the h-irq on the scanline, in master cycles
+    #[inline]
+    pub const fn h_irq_pos(&self) -> Option<u32> {
+        match self.irq_mode {
+            Self::IRQ_H | Self::IRQ_HV if self.htime < 340 => Some(self.htime as u32 * 4 + 14),
+            _ => None,
+        }
+    }
+
+    // called when the h counter reaches h_irq_pos
+    pub const fn h_irq(&mut self, v: u32) {
+        if self.irq_mode == Self::IRQ_H || v == self.vtime as u32 {
+            self.irq_flag = true;
+        }
+    }
+
+    // called at the start of every scanline
+    pub const fn scanline(&mut self, v: u32) {
+        if self.irq_mode == Self::IRQ_V && v == self.vtime as u32 {
+            self.irq_flag = true;
+        }
+    }
+
+    pub const fn start_vblank(&mut self) {
+        self.vblank = true;
+        self.nmi_flag = true;
+        if self.nmi_enable {
+            self.nmi = true;
+        }
+        if self.auto_joypad {
+            self.joypad_busy = true;
+        }
+    }
+
+    pub const fn end_vblank(&mut self) {
+        self.vblank = false;
+        self.nmi_flag = false;
+    }
+
+    #[inline]
+    pub const fn set_hblank(&mut self, hblank: bool) {
+        self.hblank = hblank
+    }
+
+    #[inline]
+    pub const fn auto_joypad(&self) -> bool {
+        self.auto_joypad
+    }
+
+    // stores the results of an auto-joypad read in $4218-$421f
+    #[inline]
+    pub const fn set_joy(&mut self, joy: [u16; 4]) {
+        self.joy = joy
+    }
+
+    pub const fn end_joypad(&mut self) {
+        self.joypad_busy = false
+    }
+
+    // returns true once for each nmi edge
+    pub const fn poll_nmi(&mut self) -> bool {
+        let nmi = self.nmi;
+        self.nmi = false;
+        nmi
+    }
+
+    #[inline]
+    pub const fn irq(&self) -> bool {
+        self.irq_flag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(io: &mut Io, addr: u16) -> u16 {
+        io.read(addr, 0) as u16 | ((io.read(addr + 1, 0) as u16) << 8)
+    }
+
+    #[test]
+    fn multiply() {
+        let mut io = Io::new();
+        io.write(0x4202, 0xff);
+        io.write(0x4203, 0xff);
+        // the product is only partial until all eight steps have run
+        for _ in 0..4 {
+            io.step_alu();
+        }
+        assert_eq!(result(&mut io, 0x4216), 0xff * 0x0f);
+        for _ in 0..4 {
+            io.step_alu();
+        }
+        assert_eq!(result(&mut io, 0x4216), 0xfe01);
+
+        io.write(0x4202, 12);
+        io.write(0x4203, 13);
+        for _ in 0..8 {
+            io.step_alu();
+        }
+        assert_eq!(result(&mut io, 0x4216), 156);
+    }
+
+    #[test]
+    fn divide() {
+        let mut io = Io::new();
+        io.write(0x4204, 0xe8);
+        io.write(0x4205, 0x03);
+        io.write(0x4206, 7);
+        for _ in 0..16 {
+            io.step_alu();
+        }
+        assert_eq!(result(&mut io, 0x4214), 142);
+        assert_eq!(result(&mut io, 0x4216), 6);
+
+        // dividing by zero gives $ffff with the dividend as the remainder
+        io.write(0x4206, 0);
+        for _ in 0..16 {
+            io.step_alu();
+        }
+        assert_eq!(result(&mut io, 0x4214), 0xffff);
+        assert_eq!(result(&mut io, 0x4216), 1000);
+    }
+}
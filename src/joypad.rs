@@ -0,0 +1,345 @@
+// controllers on the serial joypad ports
+
+use crate::state::{Serializer, Value};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DeviceType {
+    None,
+    #[default]
+    Joypad,
+    Mouse,
+    Multitap,
+}
+
+#[derive(Clone, Copy)]
+pub enum Device {
+    None,
+    Joypad(Joypad),
+    Mouse(Mouse),
+    // four pads, read two at a time
+    Multitap([Joypad; 4]),
+}
+
+impl Device {
+    const RECORD_LEN: usize = 16;
+
+    pub fn new(device_type: DeviceType) -> Self {
+        match device_type {
+            DeviceType::None => Self::None,
+            DeviceType::Joypad => Self::Joypad(Joypad::default()),
+            DeviceType::Mouse => Self::Mouse(Mouse::default()),
+            DeviceType::Multitap => Self::Multitap([Joypad::default(); _]),
+        }
+    }
+
+    #[inline]
+    pub const fn device_type(&self) -> DeviceType {
+        match self {
+            Self::None => DeviceType::None,
+            Self::Joypad(_) => DeviceType::Joypad,
+            Self::Mouse(_) => DeviceType::Mouse,
+            Self::Multitap(_) => DeviceType::Multitap,
+        }
+    }
+
+    // sets the buttons on one of the device's pads, in report order
+    pub const fn set_buttons(&mut self, pad: usize, buttons: u16) {
+        match self {
+            Self::Joypad(joypad) if pad == 0 => joypad.set_buttons(buttons),
+            Self::Multitap(joypads) if pad < 4 => joypads[pad].set_buttons(buttons),
+            _ => {},
+        }
+    }
+
+    pub const fn set_mouse(&mut self, dx: i32, dy: i32, left: bool, right: bool) {
+        if let Self::Mouse(mouse) = self {
+            mouse.set_motion(dx, dy, left, right)
+        }
+    }
+
+    pub fn latch(&mut self) {
+        match self {
+            Self::None => {},
+            Self::Joypad(joypad) => joypad.latch(),
+            Self::Mouse(mouse) => mouse.latch(),
+            Self::Multitap(joypads) => {
+                for joypad in joypads {
+                    joypad.latch();
+                }
+            },
+        }
+    }
+
+    // clocks the device once, returning data line 1 in bit 0 and data line 2 in bit 1
+    pub fn read(&mut self, strobe: bool, io_bit: bool) -> u8 {
+        if strobe {
+            self.latch();
+        }
+        match self {
+            Self::None => 0,
+            Self::Joypad(joypad) => joypad.read(),
+            Self::Mouse(mouse) => {
+                // clocking the mouse while it's latched cycles its sensitivity
+                if strobe {
+                    mouse.cycle_speed();
+                }
+                mouse.read()
+            },
+            // with the latch held the second line reads 1, which is how games detect the multitap
+            Self::Multitap(joypads) if strobe => joypads[0].read() | 2,
+            Self::Multitap(joypads) => {
+                // the io bit from wrio picks which pair of pads drives the data lines
+                let (d1, d2) = if io_bit { (0, 1) } else { (2, 3) };
+                joypads[d1].read() | (joypads[d2].read() << 1)
+            },
+        }
+    }
+}
+
+impl Value for Device {
+    fn serialize(&mut self, s: &mut Serializer) {
+        // the type comes first, so loading can plug in the saved device before reading its state
+        let mut device_type = self.device_type() as u8;
+        s.value(&mut device_type);
+        let device_type = match device_type {
+            0 => DeviceType::None,
+            1 => DeviceType::Joypad,
+            2 => DeviceType::Mouse,
+            3 => DeviceType::Multitap,
+            _ => {
+                s.check(false);
+                return
+            },
+        };
+        if self.device_type() != device_type {
+            *self = Self::new(device_type);
+        }
+        // padded to the largest device, the multitap's four pads, so the state size is the same whatever is plugged in
+        s.fixed(Self::RECORD_LEN, |s| match self {
+            Self::None => {},
+            Self::Joypad(joypad) => s.value(joypad),
+            Self::Mouse(mouse) => s.value(mouse),
+            Self::Multitap(joypads) => s.value(joypads),
+        });
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Joypad {
+    // buttons in report order, b in the top bit
+    buttons: u16,
+    shift: u16,
+}
+
+impl Joypad {
+    #[inline]
+    pub const fn set_buttons(&mut self, buttons: u16) {
+        self.buttons = buttons
+    }
+
+    // the latch strobe reloads the shift register with the current buttons
+    #[inline]
+    const fn latch(&mut self) {
+        self.shift = self.buttons
+    }
+
+    // shifts out one bit per read, after the 16 report bits the pad returns 1
+    #[inline]
+    const fn read(&mut self) -> u8 {
+        let bit = (self.shift >> 15) as u8;
+        self.shift = (self.shift << 1) | 1;
+        bit
+    }
+}
+
+impl Value for Joypad {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.value(&mut self.buttons);
+        s.value(&mut self.shift);
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Mouse {
+    // motion since the last latch
+    dx: i32,
+    dy: i32,
+    left: bool,
+    right: bool,
+    speed: u8,
+    shift: u32,
+}
+
+impl Mouse {
+    const SPEEDS: u8 = 3;
+    const SIGNATURE: u32 = 0x1;
+
+    // motion piles up between latches, but only 127 of it can be reported
+    #[inline]
+    const fn set_motion(&mut self, dx: i32, dy: i32, left: bool, right: bool) {
+        self.dx = clamp_motion(self.dx.saturating_add(dx));
+        self.dy = clamp_motion(self.dy.saturating_add(dy));
+        self.left = left;
+        self.right = right;
+    }
+
+    #[inline]
+    const fn cycle_speed(&mut self) {
+        self.speed = (self.speed + 1) % Self::SPEEDS;
+    }
+
+    // the 32-bit report is a zero byte, buttons, speed and signature, then y and x as sign and magnitude
+    const fn latch(&mut self) {
+        self.shift = ((self.right as u32) << 23)
+            | ((self.left as u32) << 22)
+            | ((self.speed as u32) << 20)
+            | (Self::SIGNATURE << 16)
+            | (axis(self.dy) << 8)
+            | axis(self.dx);
+        self.dx = 0;
+        self.dy = 0;
+    }
+
+    #[inline]
+    const fn read(&mut self) -> u8 {
+        let bit = (self.shift >> 31) as u8;
+        self.shift = (self.shift << 1) | 1;
+        bit
+    }
+}
+
+impl Value for Mouse {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.value(&mut self.dx);
+        s.value(&mut self.dy);
+        s.value(&mut self.left);
+        s.value(&mut self.right);
+        s.value(&mut self.speed);
+        s.value(&mut self.shift);
+        s.check(self.speed < Self::SPEEDS && (-0x7f..=0x7f).contains(&self.dx) && (-0x7f..=0x7f).contains(&self.dy));
+    }
+}
+
+#[inline]
+const fn clamp_motion(delta: i32) -> i32 {
+    if delta < -0x7f {
+        -0x7f
+    } else if delta > 0x7f {
+        0x7f
+    } else {
+        delta
+    }
+}
+
+// up and left set the direction bit
+#[inline]
+const fn axis(delta: i32) -> u32 {
+    (((delta < 0) as u32) << 7) | delta.unsigned_abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // clocks bits out of data line 1, first bit on top
+    fn read_bits(device: &mut Device, bits: u32) -> u32 {
+        (0..bits).fold(0, |value, _| (value << 1) | (device.read(false, false) & 1) as u32)
+    }
+
+    #[test]
+    fn mouse_report() {
+        let mut mouse = Device::new(DeviceType::Mouse);
+        mouse.set_mouse(-3, 150, true, false);
+        mouse.set_mouse(0, 50, true, false);
+        mouse.set_mouse(0, i32::MAX, true, false);
+        mouse.latch();
+        // left button, speed 0, signature, then down 127 at most and left 3
+        assert_eq!(read_bits(&mut mouse, 32), 0x0041_7f83);
+        assert_eq!(read_bits(&mut mouse, 1), 1);
+
+        // latching reset the motion
+        mouse.latch();
+        assert_eq!(read_bits(&mut mouse, 32), 0x0041_0000);
+    }
+
+    #[test]
+    fn mouse_speed_cycles_while_latched() {
+        let mut mouse = Device::new(DeviceType::Mouse);
+        for speed in [1, 2, 0] {
+            mouse.read(true, false);
+            mouse.latch();
+            assert_eq!((read_bits(&mut mouse, 32) >> 20) & 3, speed);
+        }
+    }
+
+    #[test]
+    fn multitap_reads_pads_in_pairs() {
+        let mut multitap = Device::new(DeviceType::Multitap);
+        for (pad, buttons) in [0x8000, 0x4000, 0x2000, 0x1000].into_iter().enumerate() {
+            multitap.set_buttons(pad, buttons);
+        }
+        // the second line reads 1 while latched
+        assert_eq!(multitap.read(true, true), 0x03);
+        multitap.latch();
+        // io high gives pads 1 and 2, io low pads 3 and 4
+        assert_eq!(multitap.read(false, true), 0x01);
+        assert_eq!(multitap.read(false, true), 0x02);
+        multitap.latch();
+        assert_eq!(multitap.read(false, false), 0x00);
+        assert_eq!(multitap.read(false, false), 0x00);
+        assert_eq!(multitap.read(false, false), 0x01);
+        assert_eq!(multitap.read(false, false), 0x02);
+    }
+
+    fn save(device: &mut Device) -> Vec<u8> {
+        let mut s = Serializer::save();
+        s.section(*b"JOY ", |s| s.value(device));
+        s.finish()
+    }
+
+    fn load(device: &mut Device, data: &[u8]) -> bool {
+        let mut s = Serializer::load(data).unwrap();
+        s.section(*b"JOY ", |s| s.value(device));
+        s.valid()
+    }
+
+    #[test]
+    fn device_states_are_the_same_size() {
+        let sizes = [DeviceType::None, DeviceType::Joypad, DeviceType::Mouse, DeviceType::Multitap]
+            .map(|device_type| save(&mut Device::new(device_type)).len());
+        assert!(sizes.iter().all(|&size| size == sizes[0]), "{sizes:?}");
+    }
+
+    #[test]
+    fn device_state_replaces_the_plugged_in_device() {
+        let mut mouse = Device::new(DeviceType::Mouse);
+        mouse.set_mouse(5, -7, false, true);
+        let data = save(&mut mouse);
+
+        let mut device = Device::new(DeviceType::Multitap);
+        assert!(load(&mut device, &data));
+        assert_eq!(device.device_type(), DeviceType::Mouse);
+        device.latch();
+        assert_eq!(read_bits(&mut device, 32), 0x0081_8705);
+    }
+
+    #[test]
+    fn device_state_rejects_bad_values() {
+        let mut data = save(&mut Device::new(DeviceType::Mouse));
+        // past the section header, the type byte then dx
+        let mut device = Device::new(DeviceType::Joypad);
+        data[15] = 0x80;
+        assert!(!load(&mut device, &data));
+        data[15] = 0x00;
+        data[14] = 4;
+        assert!(!load(&mut device, &data));
+    }
+
+    #[test]
+    fn unplugged_port_reads_zero() {
+        let mut device = Device::new(DeviceType::None);
+        device.set_buttons(0, 0xffff);
+        device.latch();
+        assert_eq!(read_bits(&mut device, 16), 0);
+    }
+}
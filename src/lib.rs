@@ -1,37 +1,197 @@
 use std::ffi::{c_void, CStr};
 
-use gfx::Gfx;
-
-use crate::{cpu::Cpu, mem::Mem, rom::Rom};
+use crate::{
+    cpu::Cpu,
+    gl::Gl,
+    joypad::DeviceType,
+    mem::Mem,
+    ppu::{Gpu, Ppu, Software},
+    remap::Remap,
+    rom::Rom,
+    state::Serializer,
+};
 
+mod apu;
 mod cpu;
+mod dma;
+mod dsp;
 mod ffi;
+mod gl;
+mod io;
+mod joypad;
 mod mem;
+mod ppu;
+mod remap;
 mod retro;
 mod rom;
+mod spc;
+mod state;
 
 pub struct App {
-    gfx: Option<Gfx>,
     cpu: Cpu,
+    remap: Remap,
+    // whether frames are drawn with opengl rather than in software
+    gl: bool,
 }
 
 impl App {
+    // bytes between rows of the frame
+    pub const FRAME_PITCH: usize = Ppu::FRAME_WIDTH * 4;
+
     pub fn load_game(data: &[u8]) -> Self {
         Self {
-            gfx: None,
             cpu: Cpu::new(Mem::new(Rom::new(data))),
+            remap: Remap::default(),
+            gl: false,
         }
     }
 
     pub fn run(&mut self) {
+        self.cpu.mem_mut().spc_mut().clear_samples();
         self.cpu.run();
     }
 
-    pub fn context_reset<F: FnMut(&CStr) -> *const c_void>(&mut self, f: F) {
-        self.gfx = Some(Gfx::new(f));
+    // the last frame as xrgb8888, or none if it was drawn to the hw framebuffer, with its width and height
+    #[inline]
+    pub fn frame(&self) -> (Option<&[u32]>, usize, usize) {
+        self.cpu.mem().ppu().frame()
+    }
+
+    #[inline]
+    pub fn connect(&mut self, port: usize, device_type: DeviceType) {
+        self.cpu.mem_mut().connect(port, device_type)
+    }
+
+    #[inline]
+    pub fn set_remap(&mut self, remap: Remap) {
+        self.remap = remap
+    }
+
+    // buttons for a pad on a controller port, in snes report order with b in the top bit, before remapping
+    #[inline]
+    pub fn set_buttons(&mut self, port: usize, pad: usize, buttons: u16) {
+        let buttons = self.remap.apply(buttons);
+        self.cpu.mem_mut().set_buttons(port, pad, buttons)
+    }
+
+    #[inline]
+    pub fn set_mouse(&mut self, port: usize, dx: i32, dy: i32, left: bool, right: bool) {
+        self.cpu.mem_mut().set_mouse(port, dx, dy, left, right)
+    }
+
+    // the audio produced during the last frame, as interleaved stereo at 32khz
+    #[inline]
+    pub fn samples(&self) -> &[i16] {
+        self.cpu.mem().spc().samples()
+    }
+
+    pub fn save_state(&mut self) -> Vec<u8> {
+        let mut s = Serializer::save();
+        self.cpu.state(&mut s);
+        s.finish()
     }
 
-    pub fn context_destroy(&mut self) {
-        self.gfx = None;
+    // returns false if the state is from an incompatible version or is corrupt, keeping the current state
+    pub fn load_state(&mut self, data: &[u8]) -> bool {
+        let Some(mut s) = Serializer::load(data) else { return false };
+        // sections are loaded in place, so the current state is kept to go back to
+        let backup = self.save_state();
+        self.cpu.state(&mut s);
+        if !s.valid() {
+            if let Some(mut s) = Serializer::load(&backup) {
+                self.cpu.state(&mut s);
+            }
+            return false
+        }
+        true
+    }
+
+    #[inline]
+    pub const fn uses_gl(&self) -> bool {
+        self.gl
+    }
+
+    // draws with the current gl context from the next line on, copying frames to the framebuffer it returns
+    pub fn use_gl<F, T>(&mut self, f: F, framebuffer: T) -> Result<(), String>
+    where
+        F: FnMut(&CStr) -> *const c_void,
+        T: Fn() -> usize + 'static,
+    {
+        let gl = Gl::load(f).ok_or("missing OpenGL 3.1 functions")?;
+        let gpu = Gpu::new(gl, Box::new(framebuffer))?;
+        self.cpu.mem_mut().ppu_mut().set_renderer(Box::new(gpu));
+        self.gl = true;
+        Ok(())
+    }
+
+    // goes back to drawing in software, while the gl context is still current
+    pub fn use_software(&mut self) {
+        self.cpu.mem_mut().ppu_mut().set_renderer(Box::new(Software::new()));
+        self.gl = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an app running a loop that counts up $7e:0010
+    fn app() -> App {
+        let mut rom = vec![0; 0x8000];
+        // inc $10; bra -4
+        rom[..4].copy_from_slice(&[0xe6, 0x10, 0x80, 0xfc]);
+        // reset vector
+        rom[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        App { cpu: Cpu::new(Mem::new(Rom::unchecked(&rom))), remap: Remap::default(), gl: false }
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut app = app();
+        app.run();
+        let state = app.save_state();
+        app.run();
+        let later = app.save_state();
+        assert_ne!(state, later);
+
+        assert!(app.load_state(&state));
+        assert_eq!(app.save_state(), state);
+        // running on from the loaded state ends up in the same place
+        app.run();
+        assert_eq!(app.save_state(), later);
+    }
+
+    #[test]
+    fn state_size_is_the_same_for_every_device() {
+        let mut app = app();
+        let size = app.save_state().len();
+        for port in 0..2 {
+            for device_type in [DeviceType::None, DeviceType::Mouse, DeviceType::Multitap, DeviceType::Joypad] {
+                app.connect(port, device_type);
+                assert_eq!(app.save_state().len(), size, "{device_type:?} on port {port}");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_states_keep_the_current_state() {
+        let mut app = app();
+        app.connect(1, DeviceType::Mouse);
+        app.run();
+        let state = app.save_state();
+        app.run();
+        let current = app.save_state();
+
+        // an h counter past the end of the scanline, just past the time section's header
+        let mut bad = state.clone();
+        let h = bad.windows(4).position(|tag| tag == b"TIME").unwrap() + 8;
+        bad[h..h + 4].fill(0xff);
+        assert!(!app.load_state(&bad));
+        assert_eq!(app.save_state(), current);
+
+        assert!(!app.load_state(&state[..state.len() - 1]));
+        assert!(!app.load_state(b"SMWS\x02\x00"));
+        assert!(!app.load_state(&[]));
+        assert_eq!(app.save_state(), current);
     }
 }
@@ -1,19 +1,223 @@
-use crate::rom::Rom;
+use crate::{dma::Dma, io::Io, joypad::{Device, DeviceType}, ppu::Ppu, rom::Rom, spc::Spc, state::Serializer};
 
 pub struct Mem {
     ram: Box<[u8; 0x20000]>,
+    sram: Box<[u8; 0x800]>,
     rom: Rom,
+    io: Io,
+    dma: Dma,
+    ppu: Ppu,
+    spc: Spc,
+    ports: [Device; 2],
+
+    // joyser0 bit 0, held high the pads keep reloading
+    joypad_latch: bool,
+
+    // wmadd, the 17-bit address of the wram port
+    wram_addr: u32,
+
+    // last value on the data bus
+    mdr: u8,
+
+    // timing
+    h: u32,
+    v: u32,
+    refreshed: bool,
+    frame: bool,
 }
 
 impl Mem {
+    // ntsc timing, in master cycles
+    const SCANLINE_CYCLES: u32 = 1364;
+    const SCANLINES: u32 = 262;
+    const REFRESH_START: u32 = 538;
+    const REFRESH_CYCLES: u32 = 40;
+    const HBLANK_START: u32 = 1096;
+    const HBLANK_END: u32 = 4;
+    const VBLANK_START: u32 = 225;
+    const VBLANK_START_OVERSCAN: u32 = 240;
+    const JOYPAD_LINES: u32 = 3;
+
     pub fn new(rom: Rom) -> Self {
         Self {
             ram: Box::new([0; _]),
+            sram: Box::new([0; _]),
             rom,
+            io: Io::new(),
+            dma: Dma::new(),
+            ppu: Ppu::new(),
+            spc: Spc::new(),
+            ports: [Device::new(DeviceType::Joypad); _],
+            joypad_latch: false,
+            wram_addr: 0,
+            mdr: 0,
+            h: 0,
+            v: 0,
+            refreshed: false,
+            frame: false,
+        }
+    }
+
+    pub fn state(&mut self, s: &mut Serializer) {
+        s.section(*b"MEM ", |s| {
+            s.value(&mut self.ram);
+            s.value(&mut self.sram);
+            s.value(&mut self.wram_addr);
+            s.value(&mut self.mdr);
+            s.value(&mut self.joypad_latch);
+            s.check(self.wram_addr < 0x20000);
+        });
+        s.section(*b"JOY ", |s| {
+            s.value(&mut self.ports);
+        });
+        // the h/v counters drive every timed event
+        s.section(*b"TIME", |s| {
+            s.value(&mut self.h);
+            s.value(&mut self.v);
+            s.value(&mut self.refreshed);
+            s.value(&mut self.frame);
+            s.check(self.h < Self::SCANLINE_CYCLES && self.v < Self::SCANLINES);
+        });
+        self.io.state(s);
+        self.dma.state(s);
+        self.ppu.state(s);
+        self.spc.state(s);
+    }
+
+    // advances the master clock, stepping the h/v counters along with it
+    pub fn tick(&mut self, cycles: u32) {
+        // every tick is one cpu cycle as far as the alu is concerned
+        self.io.step_alu();
+
+        let mut cycles = cycles;
+        while cycles > 0 {
+            let next = self.next_event();
+            let step = cycles.min(next - self.h);
+            self.h += step;
+            cycles -= step;
+            // the spc runs in lockstep, catching up as the master clock advances
+            self.spc.run(step);
+            if self.h == next {
+                cycles += self.event();
+            }
+        }
+    }
+
+    // position of the next timed event on the scanline
+    fn next_event(&self) -> u32 {
+        let mut next = Self::SCANLINE_CYCLES;
+        let mut events = [Self::HBLANK_END, Self::REFRESH_START, Self::HBLANK_START].into_iter()
+            .chain(self.io.h_irq_pos());
+        for pos in &mut events {
+            if pos > self.h && pos < next {
+                next = pos;
+            }
+        }
+        next
+    }
+
+    // handles every event at the current position, returning any cycles stolen from the cpu
+    fn event(&mut self) -> u32 {
+        let mut stolen = 0;
+
+        if self.io.h_irq_pos() == Some(self.h) {
+            self.io.h_irq(self.v);
         }
+
+        match self.h {
+            Self::HBLANK_END => self.io.set_hblank(false),
+            Self::REFRESH_START if !self.refreshed => {
+                // the cpu is halted once per scanline while dram is refreshed
+                self.refreshed = true;
+                stolen += Self::REFRESH_CYCLES;
+            },
+            Self::HBLANK_START => {
+                self.io.set_hblank(true);
+                if self.v < self.vblank_start() {
+                    stolen += self.run_hdma();
+                }
+            },
+            Self::SCANLINE_CYCLES => {
+                self.h = 0;
+                self.refreshed = false;
+                self.v += 1;
+                if self.v == Self::SCANLINES {
+                    self.v = 0;
+                    self.frame = true;
+                }
+                stolen += self.scanline();
+            },
+            _ => {},
+        }
+
+        stolen
+    }
+
+    // handles the start of a scanline, returning any cycles stolen from the cpu
+    fn scanline(&mut self) -> u32 {
+        let mut stolen = 0;
+        let vblank_start = self.vblank_start();
+        if self.v == 0 {
+            self.io.end_vblank();
+            self.ppu.start_frame();
+            stolen += self.init_hdma();
+        } else if self.v == vblank_start {
+            self.io.start_vblank();
+            self.ppu.start_vblank();
+            if self.io.auto_joypad() {
+                self.read_auto_joypad();
+            }
+        } else if self.v == vblank_start + Self::JOYPAD_LINES {
+            self.io.end_joypad();
+        }
+        // line 0 is never displayed
+        if (1..vblank_start).contains(&self.v) {
+            self.ppu.render_line(self.v as usize - 1);
+        }
+        self.io.scanline(self.v);
+        stolen
+    }
+
+    #[inline]
+    fn vblank_start(&self) -> u32 {
+        if self.ppu.overscan() { Self::VBLANK_START_OVERSCAN } else { Self::VBLANK_START }
+    }
+
+    #[inline]
+    pub const fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+    #[inline]
+    pub const fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+    #[inline]
+    pub const fn spc(&self) -> &Spc {
+        &self.spc
+    }
+    #[inline]
+    pub const fn spc_mut(&mut self) -> &mut Spc {
+        &mut self.spc
+    }
+
+    // returns true once for each nmi edge
+    pub fn poll_nmi(&mut self) -> bool {
+        self.io.poll_nmi()
+    }
+
+    #[inline]
+    pub fn irq(&self) -> bool {
+        self.io.irq()
     }
 
-    pub fn read(&self, addr: u32) -> u8 {
+    // returns true once after each frame finishes
+    pub fn poll_frame(&mut self) -> bool {
+        let frame = self.frame;
+        self.frame = false;
+        frame
+    }
+
+    pub fn read(&mut self, addr: u32) -> u8 {
         // chipset: rom + ram + battery
         // rom size: 1<<9 kb (512KB)
         // ram size: 1<<1 kb (2KB)
@@ -24,39 +228,462 @@ impl Mem {
         // checksum: 0x8c80
         let bank = (addr >> 16) as u8;
         let addr = addr as u16;
-        match (addr, bank) {
+        let value = match (addr, bank) {
             // bus a
             (_,               0x7e..=0x7f)               => self.ram[(addr as usize) | (((bank as usize) & 1) << 16)],
-            (0x4000..=0x4fff, 0x00..=0x3f | 0x80..=0xbf) => panic!(),
+            (0x4200..=0x421f, 0x00..=0x3f | 0x80..=0xbf) => self.io.read(addr, self.mdr),
+            (0x4300..=0x437f, 0x00..=0x3f | 0x80..=0xbf) => self.dma.read(addr, self.mdr),
+            (0x4016,          0x00..=0x3f | 0x80..=0xbf) => (self.mdr & 0xfc) | self.read_joypad(0),
+            // joyser1 bits 2-4 are always set
+            (0x4017,          0x00..=0x3f | 0x80..=0xbf) => (self.mdr & 0xe0) | 0x1c | self.read_joypad(1),
+            // the rest of $4xxx isn't connected
+            (0x4000..=0x4fff, 0x00..=0x3f | 0x80..=0xbf) => self.mdr,
             (0x0000..=0x1fff, 0x00..=0x3f | 0x80..=0xbf) => self.ram[addr as usize],
-            (0x0000..=0x7fff, 0x70..=0x7d | 0xf0..=0xff) => panic!("save ram"),
+            (0x0000..=0x7fff, 0x70..=0x7d | 0xf0..=0xff) => self.sram[(addr as usize) & 0x7ff],
             (0x8000..=0xffff, 0x00..=0x7d | 0x80..=0xff) => self.rom[((addr as usize) & 0x7fff) | (((bank as usize) & 0xf) << 15)],
             // bus b
-            (0x2000..=0x2fff, 0x00..=0x3f | 0x80..=0xbf) => panic!("ppu read ({addr:x})"),
+            (0x2100..=0x21ff, 0x00..=0x3f | 0x80..=0xbf) => self.read_b(addr as u8),
             // open bus
-            _ => panic!("open bus read @ 0x{bank:2x}{addr:4x}"),
-        }
+            _ => self.mdr,
+        };
+        self.mdr = value;
+        value
     }
 
     pub fn write(&mut self, addr: u32, value: u8) {
+        self.mdr = value;
         let bank = (addr >> 16) as u8;
         let addr = addr as u16;
         match (addr, bank) {
             // bus a
             (_,               0x7e..=0x7f)               => self.ram[(addr as usize) | (((bank as usize) & 1) << 16)] = value,
-            (0x4000..=0x4fff, 0x00..=0x3f | 0x80..=0xbf) => panic!(),
+            (0x4201,          0x00..=0x3f | 0x80..=0xbf) => self.write_wrio(value),
+            (0x420b,          0x00..=0x3f | 0x80..=0xbf) => self.run_dma(value),
+            (0x420c,          0x00..=0x3f | 0x80..=0xbf) => self.dma.set_hdma_enable(value),
+            (0x4200..=0x421f, 0x00..=0x3f | 0x80..=0xbf) => self.io.write(addr, value),
+            (0x4300..=0x437f, 0x00..=0x3f | 0x80..=0xbf) => self.dma.write(addr, value),
+            (0x4016,          0x00..=0x3f | 0x80..=0xbf) => self.write_joypad_latch(value),
+            (0x4017,          0x00..=0x3f | 0x80..=0xbf) => {},
+            (0x4000..=0x4fff, 0x00..=0x3f | 0x80..=0xbf) => {},
             (0x0000..=0x1fff, 0x00..=0x3f | 0x80..=0xbf) => self.ram[addr as usize] = value,
-            (0x0000..=0x7fff, 0x70..=0x7d | 0xf0..=0xff) => panic!("save ram"),
+            (0x0000..=0x7fff, 0x70..=0x7d | 0xf0..=0xff) => self.sram[(addr as usize) & 0x7ff] = value,
             (0x8000..=0xffff, 0x00..=0x7d | 0x80..=0xff) => {}, // rom write
             // bus b
-            (0x2000..=0x2fff, 0x00..=0x3f | 0x80..=0xbf) => panic!("ppu write ({addr:x})"),
-            _ => panic!("unmapped write @ 0x{bank:2x}{addr:4x}"),
+            (0x2100..=0x21ff, 0x00..=0x3f | 0x80..=0xbf) => self.write_b(addr as u8, value),
+            // open bus
+            _ => {},
+        }
+    }
+
+    fn read_b(&mut self, addr: u8) -> u8 {
+        match addr {
+            0x37 => {
+                // reading slhv latches the counters, as long as wrio allows it
+                if (self.io.wrio() & 0x80) != 0 {
+                    self.latch_counters()
+                }
+                self.mdr
+            },
+            0x00..=0x3f => self.ppu.read(addr, self.mdr),
+            // the spc is already caught up with the access cycle by tick, the four ports repeat up to $217f
+            0x40..=0x7f => self.spc.read_port((addr & 3) as usize),
+            0x80 => {
+                let value = self.ram[self.wram_addr as usize];
+                self.wram_addr = (self.wram_addr + 1) & 0x1ffff;
+                value
+            },
+            // wmadd is write only, and nothing answers above $2183
+            _ => self.mdr,
+        }
+    }
+
+    fn write_b(&mut self, addr: u8, value: u8) {
+        match addr {
+            0x00..=0x3f => self.ppu.write(addr, value),
+            0x40..=0x7f => self.spc.write_port((addr & 3) as usize, value),
+            0x80 => {
+                self.ram[self.wram_addr as usize] = value;
+                self.wram_addr = (self.wram_addr + 1) & 0x1ffff;
+            },
+            0x81 => self.wram_addr = (self.wram_addr & 0x1ff00) | value as u32,
+            0x82 => self.wram_addr = (self.wram_addr & 0x100ff) | ((value as u32) << 8),
+            0x83 => self.wram_addr = (self.wram_addr & 0x0ffff) | (((value & 1) as u32) << 16),
+            _ => {},
+        }
+    }
+
+    // plugs a device into a port, unless it's already there
+    pub fn connect(&mut self, port: usize, device_type: DeviceType) {
+        if self.ports[port].device_type() != device_type {
+            self.ports[port] = Device::new(device_type);
+        }
+    }
+
+    // sets the buttons held on a pad, in report order
+    #[inline]
+    pub const fn set_buttons(&mut self, port: usize, pad: usize, buttons: u16) {
+        self.ports[port].set_buttons(pad, buttons)
+    }
+
+    #[inline]
+    pub const fn set_mouse(&mut self, port: usize, dx: i32, dy: i32, left: bool, right: bool) {
+        self.ports[port].set_mouse(dx, dy, left, right)
+    }
+
+    // wrio bits 6 and 7 drive the io pin on each port
+    #[inline]
+    const fn io_bit(&self, port: usize) -> bool {
+        (self.io.wrio() & (0x40 << port)) != 0
+    }
+
+    fn read_joypad(&mut self, port: usize) -> u8 {
+        let io_bit = self.io_bit(port);
+        self.ports[port].read(self.joypad_latch, io_bit)
+    }
+
+    fn write_joypad_latch(&mut self, value: u8) {
+        self.joypad_latch = (value & 1) != 0;
+        if self.joypad_latch {
+            for device in &mut self.ports {
+                device.latch();
+            }
+        }
+    }
+
+    // the auto-joypad read latches the ports and clocks 16 bits out of each while the busy flag is set,
+    // the second data line of each port goes to $421c-$421f
+    fn read_auto_joypad(&mut self) {
+        let mut joy = [0; 4];
+        for port in 0..2 {
+            let io_bit = self.io_bit(port);
+            let device = &mut self.ports[port];
+            device.latch();
+            for _ in 0..16 {
+                let bits = device.read(false, io_bit);
+                joy[port] = (joy[port] << 1) | (bits & 1) as u16;
+                joy[port + 2] = (joy[port + 2] << 1) | (bits >> 1) as u16;
+            }
         }
+        self.io.set_joy(joy);
     }
 
-    pub fn read16(&self, addr: u32) -> u16 {
+    fn write_wrio(&mut self, value: u8) {
+        // pulling bit 7 of wrio low also latches the counters
+        let latch = (self.io.wrio() & 0x80) != 0 && (value & 0x80) == 0;
+        self.io.write(0x4201, value);
+        if latch {
+            self.latch_counters()
+        }
+    }
+
+    fn latch_counters(&mut self) {
+        // the ppu counts dots of 4 master cycles
+        self.ppu.latch_counters((self.h / 4) as u16, self.v as u16);
+    }
+
+    // general purpose dma, the cpu is halted until every channel in mask is done
+    fn run_dma(&mut self, mask: u8) {
+        if mask == 0 {
+            return
+        }
+
+        // transfers are aligned to the 8 cycle dma clock
+        self.tick(8 - self.h % 8);
+        self.tick(8);
+
+        for index in 0..8 {
+            if (mask & (1 << index)) == 0 {
+                continue
+            }
+
+            self.tick(8);
+
+            let mut unit = 0;
+            loop {
+                let channel = self.dma.channel(index);
+                let (b_to_a, a_addr, b_addr) = (channel.b_to_a(), channel.a_addr(), channel.b_addr(unit));
+                self.tick(8);
+                self.transfer(b_to_a, a_addr, b_addr);
+                let channel = self.dma.channel_mut(index);
+                channel.step_a_addr();
+                unit += 1;
+                if channel.step_count() {
+                    break
+                }
+            }
+        }
+    }
+
+    // loads the hdma tables at the start of the frame, returning the cycles taken
+    fn init_hdma(&mut self) -> u32 {
+        let enable = self.dma.hdma_enable();
+        let mut cycles = 0;
+
+        for index in 0..8 {
+            self.dma.channel_mut(index).reset_hdma();
+            if (enable & (1 << index)) != 0 {
+                self.dma.channel_mut(index).start_hdma();
+                cycles += 8 + self.load_hdma_entry(index);
+            }
+        }
+
+        if enable != 0 {
+            cycles += 18;
+        }
+        cycles
+    }
+
+    // reads the next line counter (and indirect address) from the table
+    fn load_hdma_entry(&mut self, index: usize) -> u32 {
+        let line_counter = self.read_hdma_table(index);
+        self.dma.channel_mut(index).set_line_counter(line_counter);
+
+        if self.dma.channel(index).indirect() {
+            let lsb = self.read_hdma_table(index);
+            let msb = self.read_hdma_table(index);
+            self.dma.channel_mut(index).set_indirect_addr(((msb as u16) << 8) | lsb as u16);
+            16
+        } else {
+            0
+        }
+    }
+
+    // reads the next byte of a channel's hdma table
+    fn read_hdma_table(&mut self, index: usize) -> u8 {
+        let value = self.read_dma_a(self.dma.channel(index).table_addr());
+        self.dma.channel_mut(index).step_table_addr();
+        value
+    }
+
+    // runs one scanline of hdma during hblank, returning the cycles taken
+    fn run_hdma(&mut self) -> u32 {
+        let enable = self.dma.hdma_enable();
+        let mut cycles = 0;
+        let mut active = false;
+
+        for index in 0..8 {
+            let channel = self.dma.channel(index);
+            if (enable & (1 << index)) == 0 || channel.hdma_done() {
+                continue
+            }
+            active = true;
+            cycles += 8;
+
+            if channel.hdma_do_transfer() {
+                for unit in 0..channel.pattern().len() {
+                    let channel = self.dma.channel(index);
+                    let (b_to_a, a_addr, b_addr) = (channel.b_to_a(), channel.hdma_addr(), channel.b_addr(unit));
+                    self.transfer(b_to_a, a_addr, b_addr);
+                    self.dma.channel_mut(index).step_hdma_addr();
+                    cycles += 8;
+                }
+            }
+
+            if self.dma.channel_mut(index).step_line_counter() {
+                cycles += self.load_hdma_entry(index);
+            }
+        }
+
+        if active {
+            cycles += 18;
+        }
+        cycles
+    }
+
+    // the a-bus side of a dma can't reach the b-bus or the dma registers
+    const fn is_dma_a_addr(addr: u32) -> bool {
+        let bank = (addr >> 16) as u8;
+        let addr = addr as u16;
+        !matches!((addr, bank), (0x2100..=0x21ff | 0x4300..=0x437f | 0x420b..=0x420c, 0x00..=0x3f | 0x80..=0xbf))
+    }
+
+    // wram can't be both ends of a transfer through the wram port
+    const fn is_wram_addr(addr: u32) -> bool {
+        let bank = (addr >> 16) as u8;
+        let addr = addr as u16;
+        matches!((addr, bank), (_, 0x7e..=0x7f) | (0x0000..=0x1fff, 0x00..=0x3f | 0x80..=0xbf))
+    }
+
+    // moves one byte between the buses for a dma or hdma unit
+    fn transfer(&mut self, b_to_a: bool, a_addr: u32, b_addr: u8) {
+        // wram to wram transfers do nothing, the port isn't accessed and wram isn't written
+        let wram_to_wram = b_addr == 0x80 && Self::is_wram_addr(a_addr);
+        if b_to_a {
+            if !wram_to_wram {
+                let value = self.read_b(b_addr);
+                self.write_dma_a(a_addr, value);
+            }
+        } else {
+            let value = self.read_dma_a(a_addr);
+            if !wram_to_wram {
+                self.write_b(b_addr, value);
+            }
+        }
+    }
+
+    fn read_dma_a(&mut self, addr: u32) -> u8 {
+        if Self::is_dma_a_addr(addr) {
+            self.read(addr)
+        } else {
+            self.mdr
+        }
+    }
+
+    fn write_dma_a(&mut self, addr: u32, value: u8) {
+        if Self::is_dma_a_addr(addr) {
+            self.write(addr, value)
+        }
+    }
+
+    pub const fn speed(&self, addr: u32) -> u32 {
+        // master cycles per access
+        let bank = (addr >> 16) as u8;
+        let addr = addr as u16;
+        match (addr, bank) {
+            (0x0000..=0x1fff, 0x00..=0x3f | 0x80..=0xbf) => 8,  // wram
+            (0x2000..=0x3fff, 0x00..=0x3f | 0x80..=0xbf) => 6,  // bus b
+            (0x4000..=0x41ff, 0x00..=0x3f | 0x80..=0xbf) => 12, // joypad serial
+            (0x4200..=0x5fff, 0x00..=0x3f | 0x80..=0xbf) => 6,  // cpu registers
+            (0x8000..=0xffff, 0x80..=0xbf) | (_, 0xc0..=0xff) if self.io.fast_rom() => 6, // fastrom
+            _                                            => 8,  // slowrom, sram, wram
+        }
+    }
+
+    pub fn read16(&mut self, addr: u32) -> u16 {
         let lsb = self.read(addr);
         let msb = self.read((addr + 1) & 0xffffff);
         ((msb as u16) << 8) | (lsb as u16)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a mem with the given bytes at the start of the rom, so at $00:8000
+    fn mem_with(data: &[u8]) -> Mem {
+        Mem::new(Rom::unchecked(data))
+    }
+
+    // runs until the start of scanline v
+    fn run_to_line(mem: &mut Mem, v: u32) {
+        while mem.v != v {
+            mem.tick(8)
+        }
+    }
+
+    fn write_all(mem: &mut Mem, writes: &[(u32, u8)]) {
+        for &(addr, value) in writes {
+            mem.write(addr, value)
+        }
+    }
+
+    #[test]
+    fn dma_to_wram_port() {
+        let mut mem = mem_with(&[1, 2, 3, 4]);
+        // wmadd at $7f:0000, then channel 0: $00:8000 -> $2180, 4 bytes
+        // and channel 1 the same with a fixed a-bus address
+        write_all(&mut mem, &[
+            (0x2181, 0x00), (0x2182, 0x00), (0x2183, 0x01),
+            (0x4300, 0x00), (0x4301, 0x80), (0x4302, 0x00), (0x4303, 0x80), (0x4304, 0x00), (0x4305, 0x04), (0x4306, 0x00),
+            (0x4310, 0x08), (0x4311, 0x80), (0x4312, 0x01), (0x4313, 0x80), (0x4314, 0x00), (0x4315, 0x02), (0x4316, 0x00),
+        ]);
+        mem.write(0x420b, 0x03);
+        assert_eq!(mem.ram[0x10000..0x10006], [1, 2, 3, 4, 2, 2]);
+        assert_eq!(mem.wram_addr, 0x10006);
+        // the byte count runs out and the a-bus address is left after the last byte
+        assert_eq!(mem.read16(0x4305), 0);
+        assert_eq!(mem.read16(0x4302), 0x8004);
+        assert_eq!(mem.read16(0x4312), 0x8001);
+    }
+
+    #[test]
+    fn dma_wram_to_wram_is_ignored() {
+        let mut mem = mem_with(&[]);
+        mem.ram[0x100] = 0x55;
+        mem.ram[0x10000] = 0xaa;
+        // wmadd at $7f:0000, then channel 0: $2180 -> $00:0100, 1 byte
+        write_all(&mut mem, &[
+            (0x2181, 0x00), (0x2182, 0x00), (0x2183, 0x01),
+            (0x4300, 0x80), (0x4301, 0x80), (0x4302, 0x00), (0x4303, 0x01), (0x4304, 0x00), (0x4305, 0x01), (0x4306, 0x00),
+        ]);
+        mem.write(0x420b, 0x01);
+        assert_eq!(mem.ram[0x100], 0x55);
+        assert_eq!(mem.wram_addr, 0x10000);
+    }
+
+    #[test]
+    fn hdma_tables() {
+        let mut data = vec![0; 0x30];
+        // direct: 2 lines writing $aa once, then 2 lines writing $cc and $dd in repeat mode
+        data[0x00..0x06].copy_from_slice(&[0x02, 0xaa, 0x82, 0xcc, 0xdd, 0x00]);
+        // indirect: 1 line from $00:8020
+        data[0x10..0x14].copy_from_slice(&[0x01, 0x20, 0x80, 0x00]);
+        data[0x20] = 0xee;
+        let mut mem = mem_with(&data);
+        // hdma is enabled during vblank, so it starts with the next frame
+        run_to_line(&mut mem, 230);
+        write_all(&mut mem, &[
+            (0x2181, 0x00), (0x2182, 0x00), (0x2183, 0x01),
+            (0x4300, 0x00), (0x4301, 0x80), (0x4302, 0x00), (0x4303, 0x80), (0x4304, 0x00),
+            (0x4310, 0x40), (0x4311, 0x80), (0x4312, 0x10), (0x4313, 0x80), (0x4314, 0x00), (0x4317, 0x00),
+        ]);
+        mem.write(0x420c, 0x03);
+        run_to_line(&mut mem, 0);
+        run_to_line(&mut mem, 8);
+        // channel 0 runs before channel 1 on each line
+        assert_eq!(mem.ram[0x10000..0x10005], [0xaa, 0xee, 0xcc, 0xdd, 0x00]);
+        // the direct table ended, its address pointing past the terminator
+        assert_eq!(mem.read16(0x4308), 0x8006);
+    }
+
+    #[test]
+    fn dma_keeps_hdma_state() {
+        let mut mem = mem_with(&[0x02, 0xaa, 0x01, 0xbb, 0x00]);
+        // hdma on channel 0 from $00:8000 to $2181, enabled during vblank
+        write_all(&mut mem, &[(0x4300, 0x00), (0x4301, 0x81), (0x4302, 0x00), (0x4303, 0x80), (0x4304, 0x00)]);
+        run_to_line(&mut mem, 230);
+        mem.write(0x420c, 0x01);
+        run_to_line(&mut mem, 0);
+        run_to_line(&mut mem, 1);
+        assert_eq!(mem.read16(0x4308), 0x8002);
+        // a general purpose dma on the same channel mid-frame leaves the table where it was
+        write_all(&mut mem, &[(0x4305, 0x02), (0x4306, 0x00)]);
+        mem.write(0x420b, 0x01);
+        run_to_line(&mut mem, 3);
+        assert_eq!(mem.read16(0x4308), 0x8005);
+    }
+
+    #[test]
+    fn auto_joypad_read() {
+        let mut mem = mem_with(&[]);
+        mem.set_buttons(0, 0, 0x8c0f);
+        mem.set_buttons(1, 0, 0x4001);
+        mem.write(0x4200, 0x01);
+        run_to_line(&mut mem, 225);
+        assert_eq!(mem.read(0x4212) & 0x01, 0x01);
+        assert_eq!(mem.read16(0x4218), 0x8c0f);
+        assert_eq!(mem.read16(0x421a), 0x4001);
+        // plain pads leave the second data line low
+        assert_eq!(mem.read16(0x421c), 0);
+        run_to_line(&mut mem, 228);
+        assert_eq!(mem.read(0x4212) & 0x01, 0x00);
+        // the auto read clocked out all 16 bits, so the serial port is past the report
+        assert_eq!(mem.read(0x4016) & 0x01, 0x01);
+    }
+
+    #[test]
+    fn manual_joypad_read() {
+        let mut mem = mem_with(&[]);
+        mem.set_buttons(0, 0, 0xa001);
+        write_all(&mut mem, &[(0x4016, 0x01), (0x4016, 0x00)]);
+        let mut buttons = 0;
+        for _ in 0..16 {
+            buttons = (buttons << 1) | (mem.read(0x4016) & 0x01) as u16;
+        }
+        assert_eq!(buttons, 0xa001);
+        assert_eq!(mem.read(0x4016) & 0x01, 0x01);
+        // $4017 always reads bits 2-4 set
+        assert_eq!(mem.read(0x4017) & 0x1f, 0x1c);
+    }
+}
@@ -0,0 +1,700 @@
+// s-ppu registers ($2100-$213f) and video memory
+
+mod compose;
+mod gpu;
+mod mode7;
+mod obj;
+mod render;
+
+pub use self::{gpu::Gpu, render::Software};
+use crate::state::{Serializer, Value};
+
+#[derive(Clone, Copy, Default)]
+struct Bg {
+    // bgnsc
+    tilemap_base: u16,
+    tilemap_wide: bool,
+    tilemap_tall: bool,
+    // bgnnba
+    char_base: u16,
+    // bgnhofs/bgnvofs
+    hofs: u16,
+    vofs: u16,
+    // bgmode
+    big_tiles: bool,
+}
+
+// display registers, snapshotted for the renderer at the start of each line
+#[derive(Clone, Copy, Default)]
+pub struct LineState {
+    // inidisp
+    forced_blank: bool,
+    brightness: u8,
+
+    // obsel
+    obj_size: u8,
+    obj_name_base: u16,
+    obj_name_select: u16,
+
+    // sprites in range on this line, from oam evaluation
+    obj_range: [u8; 32],
+    obj_count: usize,
+
+    // backgrounds
+    bg_mode: u8,
+    bg3_priority: bool,
+    bg: [Bg; 4],
+
+    // mode 7
+    m7a: u16,
+    m7b: u16,
+    m7c: u16,
+    m7d: u16,
+    m7x: u16,
+    m7y: u16,
+    m7_hofs: u16,
+    m7_vofs: u16,
+    m7_over: u8,
+    m7_hflip: bool,
+    m7_vflip: bool,
+
+    // mosaic
+    mosaic_size: usize,
+    mosaic_enable: u8,
+
+    // setini
+    extbg: bool,
+    pseudo_hires: bool,
+    overscan: bool,
+    obj_interlace: bool,
+    interlace: bool,
+
+    // w12sel/w34sel/wobjsel and wbglog/wobjlog, for bg1-bg4, obj and the color window
+    window_select: [u8; 6],
+    window_logic: [u8; 6],
+    // wh0-wh3
+    window_left: [u8; 2],
+    window_right: [u8; 2],
+
+    // tm/ts/tmw/tsw
+    main_screen: u8,
+    sub_screen: u8,
+    main_window: u8,
+    sub_window: u8,
+
+    // cgwsel
+    clip_mode: u8,
+    prevent_mode: u8,
+    math_sub_screen: bool,
+    direct_color: bool,
+
+    // cgadsub
+    math_subtract: bool,
+    math_half: bool,
+    math_layers: u8,
+
+    // coldata
+    fixed_color: u16,
+
+    // stat78
+    field: bool,
+}
+
+impl Value for Bg {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.value(&mut self.tilemap_base);
+        s.value(&mut self.tilemap_wide);
+        s.value(&mut self.tilemap_tall);
+        s.value(&mut self.char_base);
+        s.value(&mut self.hofs);
+        s.value(&mut self.vofs);
+        s.value(&mut self.big_tiles);
+        s.check(self.tilemap_base <= 0xfc00 && self.hofs <= 0x3ff && self.vofs <= 0x3ff);
+    }
+}
+
+impl Value for LineState {
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.value(&mut self.forced_blank);
+        s.value(&mut self.brightness);
+        s.value(&mut self.obj_size);
+        s.value(&mut self.obj_name_base);
+        s.value(&mut self.obj_name_select);
+        s.value(&mut self.obj_range);
+        s.value(&mut self.obj_count);
+        s.value(&mut self.bg_mode);
+        s.value(&mut self.bg3_priority);
+        s.value(&mut self.bg);
+        s.value(&mut self.m7a);
+        s.value(&mut self.m7b);
+        s.value(&mut self.m7c);
+        s.value(&mut self.m7d);
+        s.value(&mut self.m7x);
+        s.value(&mut self.m7y);
+        s.value(&mut self.m7_hofs);
+        s.value(&mut self.m7_vofs);
+        s.value(&mut self.m7_over);
+        s.value(&mut self.m7_hflip);
+        s.value(&mut self.m7_vflip);
+        s.value(&mut self.mosaic_size);
+        s.value(&mut self.mosaic_enable);
+        s.value(&mut self.extbg);
+        s.value(&mut self.pseudo_hires);
+        s.value(&mut self.overscan);
+        s.value(&mut self.obj_interlace);
+        s.value(&mut self.interlace);
+        s.value(&mut self.window_select);
+        s.value(&mut self.window_logic);
+        s.value(&mut self.window_left);
+        s.value(&mut self.window_right);
+        s.value(&mut self.main_screen);
+        s.value(&mut self.sub_screen);
+        s.value(&mut self.main_window);
+        s.value(&mut self.sub_window);
+        s.value(&mut self.clip_mode);
+        s.value(&mut self.prevent_mode);
+        s.value(&mut self.math_sub_screen);
+        s.value(&mut self.direct_color);
+        s.value(&mut self.math_subtract);
+        s.value(&mut self.math_half);
+        s.value(&mut self.math_layers);
+        s.value(&mut self.fixed_color);
+        s.value(&mut self.field);
+        s.check(self.bg_mode < 8 && self.obj_size < 8 && (1..=16).contains(&self.mosaic_size));
+        s.check(self.obj_count <= self.obj_range.len() && self.obj_range[..self.obj_count].iter().all(|&index| index < 128));
+    }
+}
+
+// video memory as seen by a renderer
+pub struct VideoMem<'a> {
+    vram: &'a [u16; 0x8000],
+    cgram: &'a [u16; 0x100],
+    oam: &'a [u8; 0x220],
+    // whether vram and oam were written since the previous line, for renderers keeping a copy
+    vram_changed: bool,
+    oam_changed: bool,
+}
+
+// a backend drawing lines from the ppu state snapshot and video memory
+pub trait Renderer {
+    fn start_frame(&mut self, state: &LineState);
+    fn draw_line(&mut self, mem: &VideoMem, state: &LineState, y: usize);
+    fn end_frame(&mut self);
+    // the last frame as xrgb8888, or none if it went to the hw framebuffer, with its width and height
+    fn frame(&self) -> (Option<&[u32]>, usize, usize);
+}
+
+pub struct Ppu {
+    // video memory
+    vram: Box<[u16; 0x8000]>,
+    cgram: Box<[u16; 0x100]>,
+    oam: Box<[u8; 0x220]>,
+
+    state: LineState,
+    renderer: Box<dyn Renderer>,
+    vram_changed: bool,
+    oam_changed: bool,
+
+    // oam port
+    oam_addr: u16,
+    oam_reload: u16,
+    oam_priority: bool,
+    oam_latch: u8,
+
+    // scroll latches
+    bgofs_latch: u8,
+    bghofs_latch: u8,
+    m7_latch: u8,
+
+    // vram port
+    vram_addr: u16,
+    vram_latch: u16,
+    vram_increment_high: bool,
+    vram_remap: u8,
+    vram_step: u16,
+
+    // cgram port
+    cgram_addr: u8,
+    cgram_latch: u8,
+    cgram_high: bool,
+
+    // h/v counter latches
+    h_latch: u16,
+    v_latch: u16,
+    h_latch_high: bool,
+    v_latch_high: bool,
+    counters_latched: bool,
+
+    // stat77
+    range_over: bool,
+    time_over: bool,
+
+    // open bus for each ppu chip
+    ppu1_mdr: u8,
+    ppu2_mdr: u8,
+}
+
+impl Ppu {
+    // chip revisions reported in stat77/stat78
+    const PPU1_VERSION: u8 = 1;
+    const PPU2_VERSION: u8 = 3;
+
+    // room for hires and both interlaced fields of an overscanned frame
+    pub const FRAME_WIDTH: usize = 512;
+    pub const FRAME_HEIGHT: usize = 478;
+
+    pub fn new() -> Self {
+        Self {
+            vram: Box::new([0; _]),
+            cgram: Box::new([0; _]),
+            oam: Box::new([0; _]),
+            state: LineState {
+                forced_blank: true,
+                obj_name_select: 0x1000,
+                mosaic_size: 1,
+                ..Default::default()
+            },
+            renderer: Box::new(Software::new()),
+            vram_changed: true,
+            oam_changed: true,
+            oam_addr: 0,
+            oam_reload: 0,
+            oam_priority: false,
+            oam_latch: 0,
+            bgofs_latch: 0,
+            bghofs_latch: 0,
+            m7_latch: 0,
+            vram_addr: 0,
+            vram_latch: 0,
+            vram_increment_high: false,
+            vram_remap: 0,
+            vram_step: 1,
+            cgram_addr: 0,
+            cgram_latch: 0,
+            cgram_high: false,
+            h_latch: 0,
+            v_latch: 0,
+            h_latch_high: false,
+            v_latch_high: false,
+            counters_latched: false,
+            range_over: false,
+            time_over: false,
+            ppu1_mdr: 0,
+            ppu2_mdr: 0,
+        }
+    }
+
+    // the renderer's frame isn't saved, it's redrawn on the next frame
+    pub fn state(&mut self, s: &mut Serializer) {
+        s.section(*b"PPU ", |s| {
+            s.value(&mut self.vram);
+            s.value(&mut self.cgram);
+            s.value(&mut self.oam);
+            s.value(&mut self.state);
+            s.value(&mut self.oam_addr);
+            s.value(&mut self.oam_reload);
+            s.value(&mut self.oam_priority);
+            s.value(&mut self.oam_latch);
+            s.value(&mut self.bgofs_latch);
+            s.value(&mut self.bghofs_latch);
+            s.value(&mut self.m7_latch);
+            s.value(&mut self.vram_addr);
+            s.value(&mut self.vram_latch);
+            s.value(&mut self.vram_increment_high);
+            s.value(&mut self.vram_remap);
+            s.value(&mut self.vram_step);
+            s.value(&mut self.cgram_addr);
+            s.value(&mut self.cgram_latch);
+            s.value(&mut self.cgram_high);
+            s.value(&mut self.h_latch);
+            s.value(&mut self.v_latch);
+            s.value(&mut self.h_latch_high);
+            s.value(&mut self.v_latch_high);
+            s.value(&mut self.counters_latched);
+            s.value(&mut self.range_over);
+            s.value(&mut self.time_over);
+            s.value(&mut self.ppu1_mdr);
+            s.value(&mut self.ppu2_mdr);
+            s.check(self.oam_addr < 0x400 && self.oam_reload < 0x200);
+        });
+        self.vram_changed = true;
+        self.oam_changed = true;
+    }
+
+    pub fn read(&mut self, addr: u8, mdr: u8) -> u8 {
+        match addr {
+            0x34 => {
+                self.ppu1_mdr = self.m7_product() as u8;
+                self.ppu1_mdr
+            },
+            0x35 => {
+                self.ppu1_mdr = (self.m7_product() >> 8) as u8;
+                self.ppu1_mdr
+            },
+            0x36 => {
+                self.ppu1_mdr = (self.m7_product() >> 16) as u8;
+                self.ppu1_mdr
+            },
+            0x38 => {
+                self.ppu1_mdr = self.read_oam();
+                self.ppu1_mdr
+            },
+            0x39 => {
+                self.ppu1_mdr = self.vram_latch as u8;
+                if !self.vram_increment_high {
+                    self.step_vram_read();
+                }
+                self.ppu1_mdr
+            },
+            0x3a => {
+                self.ppu1_mdr = (self.vram_latch >> 8) as u8;
+                if self.vram_increment_high {
+                    self.step_vram_read();
+                }
+                self.ppu1_mdr
+            },
+            0x3b => {
+                let color = self.cgram[self.cgram_addr as usize];
+                if self.cgram_high {
+                    self.ppu2_mdr = (self.ppu2_mdr & 0x80) | (color >> 8) as u8;
+                    self.cgram_addr = self.cgram_addr.wrapping_add(1);
+                } else {
+                    self.ppu2_mdr = color as u8;
+                }
+                self.cgram_high = !self.cgram_high;
+                self.ppu2_mdr
+            },
+            0x3c => {
+                self.ppu2_mdr = Self::read_latch(self.h_latch, &mut self.h_latch_high, self.ppu2_mdr);
+                self.ppu2_mdr
+            },
+            0x3d => {
+                self.ppu2_mdr = Self::read_latch(self.v_latch, &mut self.v_latch_high, self.ppu2_mdr);
+                self.ppu2_mdr
+            },
+            0x3e => {
+                self.ppu1_mdr = ((self.time_over as u8) << 7)
+                    | ((self.range_over as u8) << 6)
+                    | (self.ppu1_mdr & 0x10)
+                    | Self::PPU1_VERSION;
+                self.ppu1_mdr
+            },
+            0x3f => {
+                self.ppu2_mdr = ((self.state.field as u8) << 7)
+                    | ((self.counters_latched as u8) << 6)
+                    | (self.ppu2_mdr & 0x20)
+                    | Self::PPU2_VERSION;
+                self.counters_latched = false;
+                self.h_latch_high = false;
+                self.v_latch_high = false;
+                self.ppu2_mdr
+            },
+            // write-only registers in the middle of each group of 4 float on the ppu1 bus
+            0x04..=0x06 | 0x08..=0x0a | 0x14..=0x16 | 0x18..=0x1a | 0x24..=0x26 | 0x28..=0x2a => self.ppu1_mdr,
+            _ => mdr,
+        }
+    }
+
+    pub fn write(&mut self, addr: u8, value: u8) {
+        match addr {
+            0x00 => {
+                self.state.forced_blank = (value & 0x80) != 0;
+                self.state.brightness = value & 0x0f;
+            },
+            0x01 => {
+                self.state.obj_size = value >> 5;
+                self.state.obj_name_base = ((value & 7) as u16) << 13;
+                self.state.obj_name_select = ((((value >> 3) & 3) + 1) as u16) << 12;
+            },
+            0x02 => {
+                self.oam_reload = (self.oam_reload & 0x100) | value as u16;
+                self.reload_oam_addr();
+            },
+            0x03 => {
+                self.oam_reload = (self.oam_reload & 0x0ff) | (((value & 1) as u16) << 8);
+                self.oam_priority = (value & 0x80) != 0;
+                self.reload_oam_addr();
+            },
+            0x04 => self.write_oam(value),
+            0x05 => {
+                self.state.bg_mode = value & 7;
+                self.state.bg3_priority = (value & 0x08) != 0;
+                for (index, bg) in self.state.bg.iter_mut().enumerate() {
+                    bg.big_tiles = (value & (0x10 << index)) != 0;
+                }
+            },
+            0x06 => {
+                self.state.mosaic_size = ((value >> 4) + 1) as usize;
+                self.state.mosaic_enable = value & 0x0f;
+            },
+            0x07..=0x0a => {
+                let bg = &mut self.state.bg[(addr - 0x07) as usize];
+                bg.tilemap_base = ((value & 0xfc) as u16) << 8;
+                bg.tilemap_wide = (value & 1) != 0;
+                bg.tilemap_tall = (value & 2) != 0;
+            },
+            0x0b => {
+                self.state.bg[0].char_base = ((value & 0x0f) as u16) << 12;
+                self.state.bg[1].char_base = ((value >> 4) as u16) << 12;
+            },
+            0x0c => {
+                self.state.bg[2].char_base = ((value & 0x0f) as u16) << 12;
+                self.state.bg[3].char_base = ((value >> 4) as u16) << 12;
+            },
+            0x0d | 0x0f | 0x11 | 0x13 => {
+                // the horizontal scroll mixes in the previous write to any scroll register
+                let bg = &mut self.state.bg[((addr - 0x0d) >> 1) as usize];
+                bg.hofs = (((value as u16) << 8) | (self.bgofs_latch & !7) as u16 | (self.bghofs_latch & 7) as u16) & 0x3ff;
+                self.bgofs_latch = value;
+                self.bghofs_latch = value;
+                // bg1's scroll registers double as the mode 7 scroll
+                if addr == 0x0d {
+                    self.state.m7_hofs = self.write_m7(value);
+                }
+            },
+            0x0e | 0x10 | 0x12 | 0x14 => {
+                let bg = &mut self.state.bg[((addr - 0x0e) >> 1) as usize];
+                bg.vofs = (((value as u16) << 8) | self.bgofs_latch as u16) & 0x3ff;
+                self.bgofs_latch = value;
+                if addr == 0x0e {
+                    self.state.m7_vofs = self.write_m7(value);
+                }
+            },
+            0x15 => {
+                self.vram_increment_high = (value & 0x80) != 0;
+                self.vram_remap = (value >> 2) & 3;
+                self.vram_step = match value & 3 {
+                    0 => 1,
+                    1 => 32,
+                    _ => 128,
+                };
+            },
+            0x16 => {
+                self.vram_addr = (self.vram_addr & 0xff00) | value as u16;
+                self.vram_latch = self.vram[self.vram_index()];
+            },
+            0x17 => {
+                self.vram_addr = (self.vram_addr & 0x00ff) | ((value as u16) << 8);
+                self.vram_latch = self.vram[self.vram_index()];
+            },
+            0x18 => {
+                let index = self.vram_index();
+                self.vram[index] = (self.vram[index] & 0xff00) | value as u16;
+                self.vram_changed = true;
+                if !self.vram_increment_high {
+                    self.vram_addr = self.vram_addr.wrapping_add(self.vram_step);
+                }
+            },
+            0x19 => {
+                let index = self.vram_index();
+                self.vram[index] = (self.vram[index] & 0x00ff) | ((value as u16) << 8);
+                self.vram_changed = true;
+                if self.vram_increment_high {
+                    self.vram_addr = self.vram_addr.wrapping_add(self.vram_step);
+                }
+            },
+            0x1a => {
+                self.state.m7_over = value >> 6;
+                self.state.m7_vflip = (value & 2) != 0;
+                self.state.m7_hflip = (value & 1) != 0;
+            },
+            0x1b => self.state.m7a = self.write_m7(value),
+            0x1c => self.state.m7b = self.write_m7(value),
+            0x1d => self.state.m7c = self.write_m7(value),
+            0x1e => self.state.m7d = self.write_m7(value),
+            0x1f => self.state.m7x = self.write_m7(value),
+            0x20 => self.state.m7y = self.write_m7(value),
+            0x21 => {
+                self.cgram_addr = value;
+                self.cgram_high = false;
+            },
+            0x22 => {
+                if self.cgram_high {
+                    self.cgram[self.cgram_addr as usize] = (((value & 0x7f) as u16) << 8) | self.cgram_latch as u16;
+                    self.cgram_addr = self.cgram_addr.wrapping_add(1);
+                } else {
+                    self.cgram_latch = value;
+                }
+                self.cgram_high = !self.cgram_high;
+            },
+            0x23..=0x25 => {
+                let layer = ((addr - 0x23) << 1) as usize;
+                self.state.window_select[layer] = value & 0x0f;
+                self.state.window_select[layer + 1] = value >> 4;
+            },
+            0x26 => self.state.window_left[0] = value,
+            0x27 => self.state.window_right[0] = value,
+            0x28 => self.state.window_left[1] = value,
+            0x29 => self.state.window_right[1] = value,
+            0x2a => {
+                for (layer, logic) in self.state.window_logic[..4].iter_mut().enumerate() {
+                    *logic = (value >> (layer << 1)) & 3;
+                }
+            },
+            0x2b => {
+                self.state.window_logic[4] = value & 3;
+                self.state.window_logic[5] = (value >> 2) & 3;
+            },
+            0x2c => self.state.main_screen = value & 0x1f,
+            0x2d => self.state.sub_screen = value & 0x1f,
+            0x2e => self.state.main_window = value & 0x1f,
+            0x2f => self.state.sub_window = value & 0x1f,
+            0x30 => {
+                self.state.clip_mode = value >> 6;
+                self.state.prevent_mode = (value >> 4) & 3;
+                self.state.math_sub_screen = (value & 2) != 0;
+                self.state.direct_color = (value & 1) != 0;
+            },
+            0x31 => {
+                self.state.math_subtract = (value & 0x80) != 0;
+                self.state.math_half = (value & 0x40) != 0;
+                self.state.math_layers = value & 0x3f;
+            },
+            0x32 => {
+                // each of the selected channels is set to the same intensity
+                let intensity = (value & 0x1f) as u16;
+                if (value & 0x20) != 0 {
+                    self.state.fixed_color = (self.state.fixed_color & !0x001f) | intensity;
+                }
+                if (value & 0x40) != 0 {
+                    self.state.fixed_color = (self.state.fixed_color & !0x03e0) | (intensity << 5);
+                }
+                if (value & 0x80) != 0 {
+                    self.state.fixed_color = (self.state.fixed_color & !0x7c00) | (intensity << 10);
+                }
+            },
+            0x33 => {
+                self.state.extbg = (value & 0x40) != 0;
+                self.state.pseudo_hires = (value & 0x08) != 0;
+                self.state.overscan = (value & 0x04) != 0;
+                self.state.obj_interlace = (value & 0x02) != 0;
+                self.state.interlace = (value & 0x01) != 0;
+            },
+            _ => {},
+        }
+    }
+
+    // the last frame as xrgb8888, FRAME_WIDTH pixels apart, or none if it went to the hw framebuffer,
+    // with its width and height
+    #[inline]
+    pub fn frame(&self) -> (Option<&[u32]>, usize, usize) {
+        self.renderer.frame()
+    }
+
+    // switches backends, the new one draws from the next line on with all of video memory
+    pub fn set_renderer(&mut self, renderer: Box<dyn Renderer>) {
+        self.renderer = renderer;
+        self.renderer.start_frame(&self.state);
+        self.vram_changed = true;
+        self.oam_changed = true;
+    }
+
+    pub fn render_line(&mut self, y: usize) {
+        // sprites are evaluated even when hidden, for the $213e flags
+        if !self.state.forced_blank {
+            self.evaluate_obj(y as u16);
+        }
+        let mem = VideoMem {
+            vram: &self.vram,
+            cgram: &self.cgram,
+            oam: &self.oam,
+            vram_changed: self.vram_changed,
+            oam_changed: self.oam_changed,
+        };
+        self.renderer.draw_line(&mem, &self.state, y);
+        self.vram_changed = false;
+        self.oam_changed = false;
+    }
+
+    // overscan moves the start of vblank down to line 240
+    #[inline]
+    pub const fn overscan(&self) -> bool {
+        self.state.overscan
+    }
+
+    // latches the h/v counters, from a read of $2137 or wrio
+    pub fn latch_counters(&mut self, h: u16, v: u16) {
+        self.h_latch = h;
+        self.v_latch = v;
+        self.counters_latched = true;
+    }
+
+    pub fn start_vblank(&mut self) {
+        self.renderer.end_frame();
+        // the oam address is reset to the last written value every frame
+        if !self.state.forced_blank {
+            self.reload_oam_addr();
+        }
+    }
+
+    pub fn start_frame(&mut self) {
+        self.state.field = !self.state.field;
+        self.renderer.start_frame(&self.state);
+        if !self.state.forced_blank {
+            self.range_over = false;
+            self.time_over = false;
+        }
+    }
+
+    // the 9-bit counters are read a byte at a time, the unused bits come from open bus
+    fn read_latch(latch: u16, high: &mut bool, mdr: u8) -> u8 {
+        let value = if *high {
+            (mdr & 0xfe) | ((latch >> 8) & 1) as u8
+        } else {
+            latch as u8
+        };
+        *high = !*high;
+        value
+    }
+
+    fn reload_oam_addr(&mut self) {
+        self.oam_addr = self.oam_reload << 1;
+    }
+
+    fn oam_index(&self) -> usize {
+        // the high table is mirrored across the top of the address space
+        if self.oam_addr < 0x200 {
+            self.oam_addr as usize
+        } else {
+            0x200 | (self.oam_addr as usize & 0x1f)
+        }
+    }
+
+    fn read_oam(&mut self) -> u8 {
+        let value = self.oam[self.oam_index()];
+        self.oam_addr = (self.oam_addr + 1) & 0x3ff;
+        value
+    }
+
+    fn write_oam(&mut self, value: u8) {
+        let index = self.oam_index();
+        if index >= 0x200 {
+            self.oam[index] = value;
+        } else if (index & 1) == 0 {
+            // the low table is written a word at a time
+            self.oam_latch = value;
+        } else {
+            self.oam[index - 1] = self.oam_latch;
+            self.oam[index] = value;
+        }
+        self.oam_addr = (self.oam_addr + 1) & 0x3ff;
+        self.oam_changed = true;
+    }
+
+    fn vram_index(&self) -> usize {
+        // vmain can rotate the low bits of the address for bitplane-friendly transfers
+        let addr = self.vram_addr;
+        let addr = match self.vram_remap {
+            0 => addr,
+            1 => (addr & 0xff00) | ((addr & 0x001f) << 3) | ((addr >> 5) & 7),
+            2 => (addr & 0xfe00) | ((addr & 0x003f) << 3) | ((addr >> 6) & 7),
+            _ => (addr & 0xfc00) | ((addr & 0x007f) << 3) | ((addr >> 7) & 7),
+        };
+        (addr & 0x7fff) as usize
+    }
+
+    fn step_vram_read(&mut self) {
+        self.vram_latch = self.vram[self.vram_index()];
+        self.vram_addr = self.vram_addr.wrapping_add(self.vram_step);
+    }
+}
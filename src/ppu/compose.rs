@@ -0,0 +1,125 @@
+// windows, main/sub screen compositing and color math
+
+use super::{LineState, Ppu, VideoMem};
+use super::render::{Layers, Pixel};
+
+// layer indices after bg1-bg4, the backdrop shares its index with the color window
+pub(super) const OBJ: usize = 4;
+const BACKDROP: usize = 5;
+const COLOR_WINDOW: usize = 5;
+
+// window logic from wbglog/wobjlog
+const LOGIC_OR: u8 = 0;
+const LOGIC_AND: u8 = 1;
+const LOGIC_XOR: u8 = 2;
+
+impl LineState {
+    pub(super) fn compose_line(&self, mem: &VideoMem, line: &mut [u32], layers: &Layers, sub_layers: &Layers, hires: bool) {
+        let backdrop = Pixel { color: mem.cgram[0], z: 0, math: true };
+        let fixed = Pixel { color: self.fixed_color, z: 0, math: true };
+        for x in 0..Ppu::LINE_WIDTH {
+            let (main_layer, main) = self.top_pixel(layers, x, self.main_screen, self.main_window, backdrop);
+            let (sub_layer, sub) = self.top_pixel(sub_layers, x, self.sub_screen, self.sub_window, fixed);
+            let color = self.color_math(x, main_layer, main, sub_layer, sub);
+            if hires {
+                // the even half-pixels show the sub screen, blended with the main screen
+                let even = self.color_math(x, sub_layer, sub, main_layer, main);
+                line[x << 1] = to_xrgb(even, self.brightness);
+                line[(x << 1) + 1] = to_xrgb(color, self.brightness);
+            } else {
+                line[x] = to_xrgb(color, self.brightness);
+            }
+        }
+    }
+
+    // front-most pixel of the layers on a screen, skipping layers masked by their window
+    fn top_pixel(&self, layers: &Layers, x: usize, screen: u8, window: u8, backdrop: Pixel) -> (usize, Pixel) {
+        let mut top = (BACKDROP, backdrop);
+        for (index, layer) in layers.iter().enumerate() {
+            if (screen & (1 << index)) == 0 || layer[x].z <= top.1.z {
+                continue
+            }
+            if (window & (1 << index)) != 0 && self.in_window(index, x) {
+                continue
+            }
+            top = (index, layer[x]);
+        }
+        top
+    }
+
+    fn in_window(&self, layer: usize, x: usize) -> bool {
+        // each layer has an enable and invert bit for both windows
+        let select = self.window_select[layer];
+        let x = x as u8;
+        let w1 = (self.window_left[0] <= x && x <= self.window_right[0]) != ((select & 1) != 0);
+        let w2 = (self.window_left[1] <= x && x <= self.window_right[1]) != ((select & 4) != 0);
+        match ((select & 2) != 0, (select & 8) != 0) {
+            (false, false) => false,
+            (true, false) => w1,
+            (false, true) => w2,
+            (true, true) => match self.window_logic[layer] {
+                LOGIC_OR => w1 || w2,
+                LOGIC_AND => w1 && w2,
+                LOGIC_XOR => w1 != w2,
+                _ => w1 == w2,
+            },
+        }
+    }
+
+    fn color_math(&self, x: usize, main_layer: usize, main: Pixel, sub_layer: usize, sub: Pixel) -> u16 {
+        let color_window = self.in_window(COLOR_WINDOW, x);
+        let black = window_region(self.clip_mode, color_window);
+        let color = if black { 0 } else { main.color };
+        if window_region(self.prevent_mode, color_window) || (self.math_layers & (1 << main_layer)) == 0 || !main.math {
+            return color
+        }
+
+        // the sub screen backdrop is the fixed color, which is never halved
+        let (rhs, halve) = if self.math_sub_screen {
+            (sub.color, self.math_half && sub_layer != BACKDROP)
+        } else {
+            (self.fixed_color, self.math_half)
+        };
+        blend(color, rhs, self.math_subtract, halve && !black)
+    }
+}
+
+// the color window regions used by cgwsel, never, outside, inside and always
+#[inline]
+const fn window_region(mode: u8, inside: bool) -> bool {
+    match mode {
+        0 => false,
+        1 => !inside,
+        2 => inside,
+        _ => true,
+    }
+}
+
+fn blend(lhs: u16, rhs: u16, subtract: bool, halve: bool) -> u16 {
+    let mut color = 0;
+    for shift in [0, 5, 10] {
+        let a = (lhs >> shift) & 0x1f;
+        let b = (rhs >> shift) & 0x1f;
+        let mut c = if subtract { a.saturating_sub(b) } else { a + b };
+        if halve {
+            c >>= 1;
+        }
+        color |= c.min(0x1f) << shift;
+    }
+    color
+}
+
+// converts bgr555 to xrgb8888, scaled by the inidisp brightness
+#[inline]
+const fn to_xrgb(color: u16, brightness: u8) -> u32 {
+    let r = scale_channel(color & 0x1f, brightness);
+    let g = scale_channel((color >> 5) & 0x1f, brightness);
+    let b = scale_channel((color >> 10) & 0x1f, brightness);
+    (r << 16) | (g << 8) | b
+}
+
+#[inline]
+const fn scale_channel(value: u16, brightness: u8) -> u32 {
+    let value = ((value << 3) | (value >> 2)) as u32;
+    value * brightness as u32 / 15
+}
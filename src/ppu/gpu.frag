@@ -0,0 +1,556 @@
+#version 140
+
+// one row of the frame, ported from the software renderer in render.rs,
+// mode7.rs, obj.rs and compose.rs, which stays the reference
+
+// video memory: vram is 256x128 words, cgram 256x1 words and oam 544x1 bytes
+uniform usampler2D vram;
+uniform usampler2D cgram;
+uniform usampler2D oam;
+
+// the line state, packed in this order by gpu.rs
+uniform int state[115];
+
+#define LINE 0
+#define BG_MODE 1
+#define BG3_PRIORITY 2
+#define BRIGHTNESS 3
+#define FORCED_BLANK 4
+#define MOSAIC_SIZE 5
+#define MOSAIC_ENABLE 6
+#define EXTBG 7
+#define PSEUDO_HIRES 8
+#define INTERLACE 9
+#define OBJ_INTERLACE 10
+#define FIELD 11
+#define DIRECT_COLOR 12
+#define MAIN_SCREEN 13
+#define SUB_SCREEN 14
+#define MAIN_WINDOW 15
+#define SUB_WINDOW 16
+#define CLIP_MODE 17
+#define PREVENT_MODE 18
+#define MATH_SUB_SCREEN 19
+#define MATH_SUBTRACT 20
+#define MATH_HALF 21
+#define MATH_LAYERS 22
+#define FIXED_COLOR 23
+#define WINDOW_LEFT 24
+#define WINDOW_RIGHT 26
+#define WINDOW_SELECT 28
+#define WINDOW_LOGIC 34
+#define M7A 40
+#define M7B 41
+#define M7C 42
+#define M7D 43
+#define M7X 44
+#define M7Y 45
+#define M7_HOFS 46
+#define M7_VOFS 47
+#define M7_OVER 48
+#define M7_HFLIP 49
+#define M7_VFLIP 50
+#define OBJ_SIZE 51
+#define OBJ_NAME_BASE 52
+#define OBJ_NAME_SELECT 53
+#define OBJ_COUNT 54
+#define OBJ_RANGE 55
+// 7 values per bg, from bg1 to bg4
+#define BG 87
+#define TILEMAP_BASE 0
+#define TILEMAP_WIDE 1
+#define TILEMAP_TALL 2
+#define CHAR_BASE 3
+#define HOFS 4
+#define VOFS 5
+#define BIG_TILES 6
+
+#define OBJ 4
+#define BACKDROP 5
+#define COLOR_WINDOW 5
+
+out vec4 frag_color;
+
+struct Pixel {
+    int color;
+    // depth in the current mode's priority order, 0 is transparent
+    int z;
+    bool math;
+};
+
+const Pixel TRANSPARENT = Pixel(0, 0, false);
+
+// render.rs tables, indexed by mode, then bg, then priority
+const int BG_BPP[32] = int[32](
+    2, 2, 2, 2,  4, 4, 2, 0,  4, 4, 0, 0,  8, 4, 0, 0,
+    8, 2, 0, 0,  4, 2, 0, 0,  4, 0, 0, 0,  8, 7, 0, 0);
+const int BG_Z[64] = int[64](
+    8, 11, 7, 10, 2, 5, 1, 4,
+    6, 9, 5, 8, 1, 3, 0, 0,
+    3, 7, 1, 5, 0, 0, 0, 0,
+    3, 7, 1, 5, 0, 0, 0, 0,
+    3, 7, 1, 5, 0, 0, 0, 0,
+    3, 7, 1, 5, 0, 0, 0, 0,
+    3, 7, 1, 5, 0, 0, 0, 0,
+    3, 3, 1, 5, 0, 0, 0, 0);
+const int OBJ_Z[32] = int[32](
+    3, 6, 9, 12,  2, 4, 7, 10,  2, 4, 6, 8,  2, 4, 6, 8,
+    2, 4, 6, 8,  2, 4, 6, 8,  2, 4, 6, 8,  2, 4, 6, 7);
+const int BG3_PRIORITY_Z = 11;
+
+// obj.rs small and large sprite sizes, as width and height
+const ivec2 OBJ_SIZES[16] = ivec2[16](
+    ivec2(8, 8), ivec2(16, 16), ivec2(8, 8), ivec2(32, 32),
+    ivec2(8, 8), ivec2(64, 64), ivec2(16, 16), ivec2(32, 32),
+    ivec2(16, 16), ivec2(64, 64), ivec2(32, 32), ivec2(64, 64),
+    ivec2(16, 32), ivec2(32, 64), ivec2(16, 32), ivec2(32, 32));
+const int OBJ_TILE_LIMIT = 34;
+
+int vram_word(int addr) {
+    addr &= 0x7fff;
+    return int(texelFetch(vram, ivec2(addr & 0xff, addr >> 8), 0).r);
+}
+
+int cgram_color(int index) {
+    return int(texelFetch(cgram, ivec2(index & 0xff, 0), 0).r);
+}
+
+int oam_byte(int index) {
+    return int(texelFetch(oam, ivec2(index, 0), 0).r);
+}
+
+bool flag(int index) {
+    return state[index] != 0;
+}
+
+int bg_state(int index, int field) {
+    return state[BG + index * 7 + field];
+}
+
+int direct_color(int pixel, int palette) {
+    int r = ((pixel & 7) << 2) | ((palette & 1) << 1);
+    int g = (((pixel >> 3) & 7) << 2) | (palette & 2);
+    int b = ((pixel >> 6) << 3) | (palette & 4);
+    return r | (g << 5) | (b << 10);
+}
+
+int tile_pixel(int char_base, int tile, int bpp, int px, int py) {
+    // each pair of bitplanes is interleaved into one word per row
+    int addr = char_base + tile * bpp * 4 + py;
+    int pixel = 0;
+    for (int plane = 0; plane < bpp / 2; plane++) {
+        int word = vram_word(addr + plane * 8);
+        int bits = ((word >> (7 - px)) & 1) | (((word >> (15 - px)) & 1) << 1);
+        pixel |= bits << (plane * 2);
+    }
+    return pixel;
+}
+
+int tilemap_entry(int index, int tx, int ty) {
+    // each screen is 32x32 tiles, bigger tilemaps lay screens out left to right then top to bottom
+    int addr = bg_state(index, TILEMAP_BASE) + ((ty & 0x1f) << 5) + (tx & 0x1f);
+    bool wide = bg_state(index, TILEMAP_WIDE) != 0;
+    if (wide && (tx & 0x20) != 0) {
+        addr += 0x400;
+    }
+    if (bg_state(index, TILEMAP_TALL) != 0 && (ty & 0x20) != 0) {
+        addr += wide ? 0x800 : 0x400;
+    }
+    return vram_word(addr);
+}
+
+// scroll offsets for a bg at column x, taking offset-per-tile into account
+ivec2 bg_offsets(int index, int x) {
+    int mode = state[BG_MODE];
+    int hofs = bg_state(index, HOFS);
+    int vofs = bg_state(index, VOFS);
+    if ((mode != 2 && mode != 4 && mode != 6) || index >= 2) {
+        return ivec2(hofs, vofs);
+    }
+
+    // the first column on screen is never affected
+    int column = (x + (hofs & 7)) >> 3;
+    if (column == 0) {
+        return ivec2(hofs, vofs);
+    }
+
+    // bg3's tilemap holds the replacement offsets, bit 13 applies them to bg1 and bit 14 to bg2
+    int tx = (column - 1) + (bg_state(2, HOFS) >> 3);
+    int ty = bg_state(2, VOFS) >> 3;
+    int enable = 0x2000 << index;
+    if (mode == 4) {
+        // mode 4 only has room for one offset per column, bit 15 picks which
+        int entry = tilemap_entry(2, tx, ty);
+        if ((entry & enable) != 0) {
+            if ((entry & 0x8000) != 0) {
+                vofs = entry & 0x3ff;
+            } else {
+                hofs = (entry & 0x3f8) | (hofs & 7);
+            }
+        }
+    } else {
+        int h_entry = tilemap_entry(2, tx, ty);
+        int v_entry = tilemap_entry(2, tx, ty + 1);
+        if ((h_entry & enable) != 0) {
+            hofs = (h_entry & 0x3f8) | (hofs & 7);
+        }
+        if ((v_entry & enable) != 0) {
+            vofs = v_entry & 0x3ff;
+        }
+    }
+    return ivec2(hofs, vofs);
+}
+
+// in hires modes, odd picks the even or odd half-pixel of the 512 pixel line
+Pixel bg_pixel(int index, int line, int odd, int x) {
+    int mode = state[BG_MODE];
+    int bpp = BG_BPP[mode * 4 + index];
+    int z_low = BG_Z[(mode * 4 + index) * 2];
+    int z_high = BG_Z[(mode * 4 + index) * 2 + 1];
+    if (mode == 1 && index == 2 && flag(BG3_PRIORITY)) {
+        z_high = BG3_PRIORITY_Z;
+    }
+
+    // modes 5 and 6 always use 16 pixel wide tiles, and render every line of both fields when interlaced
+    bool hires = mode >= 5;
+    bool big_tiles = bg_state(index, BIG_TILES) != 0;
+    int y = hires && flag(INTERLACE) ? (line << 1) | state[FIELD] : line;
+    int tile_width = big_tiles || hires ? 16 : 8;
+    int tile_height = big_tiles ? 16 : 8;
+    int palette_base = mode == 0 ? index * 32 : 0;
+
+    ivec2 offsets = bg_offsets(index, x);
+    int sx = hires ? ((x + offsets.x) << 1) | odd : x + offsets.x;
+    int sy = y + offsets.y;
+
+    int entry = tilemap_entry(index, sx / tile_width, sy / tile_height);
+    int px = sx % tile_width;
+    int py = sy % tile_height;
+    if ((entry & 0x4000) != 0) {
+        px = tile_width - 1 - px;
+    }
+    if ((entry & 0x8000) != 0) {
+        py = tile_height - 1 - py;
+    }
+
+    // big tiles are made of the neighbouring 8x8 tiles in vram
+    int tile = (entry & 0x3ff) + (px >> 3) + ((py >> 3) << 4);
+    int pixel = tile_pixel(bg_state(index, CHAR_BASE), tile, bpp, px & 7, py & 7);
+    if (pixel == 0) {
+        return TRANSPARENT;
+    }
+
+    int palette = (entry >> 10) & 7;
+    int color;
+    if (bpp == 2) {
+        color = cgram_color(palette_base + (palette << 2) + pixel);
+    } else if (bpp == 4) {
+        color = cgram_color((palette << 4) + pixel);
+    } else if (flag(DIRECT_COLOR)) {
+        color = direct_color(pixel, palette);
+    } else {
+        color = cgram_color(pixel);
+    }
+    return Pixel(color, (entry & 0x2000) != 0 ? z_high : z_low, true);
+}
+
+// sign extends the 13-bit center and scroll registers
+int sext13(int value) {
+    return (value << 19) >> 19;
+}
+
+int sext16(int value) {
+    return (value << 16) >> 16;
+}
+
+// offsets from the center are clipped to 10 bits, keeping the sign
+int clip(int value) {
+    return (value & 0x2000) != 0 ? value | ~0x3ff : value & 0x3ff;
+}
+
+Pixel mode7_pixel(int index, int line, int x) {
+    int a = sext16(state[M7A]);
+    int b = sext16(state[M7B]);
+    int c = sext16(state[M7C]);
+    int d = sext16(state[M7D]);
+    int hcenter = sext13(state[M7X]);
+    int vcenter = sext13(state[M7Y]);
+    int hoffset = clip(sext13(state[M7_HOFS]) - hcenter);
+    int voffset = clip(sext13(state[M7_VOFS]) - vcenter);
+
+    if (flag(M7_VFLIP)) {
+        line = 255 - line;
+    }
+
+    // the hardware drops the low 6 bits of each product
+    int start_x = ((a * hoffset) & ~63) + ((b * voffset) & ~63) + ((b * line) & ~63) + (hcenter << 8);
+    int start_y = ((c * hoffset) & ~63) + ((d * voffset) & ~63) + ((d * line) & ~63) + (vcenter << 8);
+
+    if (flag(M7_HFLIP)) {
+        x = 255 - x;
+    }
+    int px = (start_x + a * x) >> 8;
+    int py = (start_y + c * x) >> 8;
+
+    // the playfield is 1024x1024, outside of it either wraps, is transparent or repeats tile 0
+    bool outside = ((px | py) & ~0x3ff) != 0;
+    int tile;
+    if (outside && state[M7_OVER] > 1) {
+        if (state[M7_OVER] == 2) {
+            return TRANSPARENT;
+        }
+        tile = 0;
+    } else {
+        tile = vram_word((((py & 0x3ff) >> 3) << 7) | ((px & 0x3ff) >> 3)) & 0xff;
+    }
+    int pixel = vram_word((tile << 6) | ((py & 7) << 3) | (px & 7)) >> 8;
+
+    if (index == 0) {
+        if (pixel == 0) {
+            return TRANSPARENT;
+        }
+        int color = flag(DIRECT_COLOR) ? direct_color(pixel, 0) : cgram_color(pixel);
+        return Pixel(color, BG_Z[56], true);
+    }
+    // extbg reuses the same pixels as a 7-bit layer with a priority bit
+    if ((pixel & 0x7f) == 0) {
+        return TRANSPARENT;
+    }
+    return Pixel(cgram_color(pixel & 0x7f), BG_Z[58 + (pixel >> 7)], true);
+}
+
+struct Obj {
+    int x;
+    int y;
+    int tile;
+    int attr;
+    ivec2 size;
+};
+
+Obj obj(int index) {
+    int high = oam_byte(0x200 + (index >> 2)) >> ((index & 3) << 1);
+    // x is 9 bits, sign extended
+    int x = ((high & 1) << 8) | oam_byte(index << 2);
+    int attr = oam_byte((index << 2) + 3);
+    return Obj(
+        (x << 23) >> 23,
+        oam_byte((index << 2) + 1),
+        ((attr & 1) << 8) | oam_byte((index << 2) + 2),
+        attr,
+        OBJ_SIZES[state[OBJ_SIZE] * 2 + ((high >> 1) & 1)]);
+}
+
+// columns of a sprite partly or fully on screen
+int visible_columns(Obj o) {
+    int count = 0;
+    for (int column = 0; column < o.size.x >> 3; column++) {
+        int tx = o.x + (column << 3);
+        if (tx > -8 && tx < 256) {
+            count++;
+        }
+    }
+    return count;
+}
+
+Pixel obj_pixel(int y, int x) {
+    // tiles are fetched from the last sprite in range backwards, until the limit is reached
+    int count = state[OBJ_COUNT];
+    int fetched[32];
+    int tiles = 0;
+    for (int i = count - 1; i >= 0; i--) {
+        fetched[i] = min(visible_columns(obj(state[OBJ_RANGE + i])), OBJ_TILE_LIMIT - tiles);
+        tiles += fetched[i];
+    }
+
+    // earlier sprites are drawn over later ones
+    for (int i = 0; i < count; i++) {
+        Obj o = obj(state[OBJ_RANGE + i]);
+        int sx = x - o.x;
+        if (sx < 0 || sx >= o.size.x) {
+            continue;
+        }
+        // only the first fetched columns that are on screen are drawn
+        int column = sx >> 3;
+        int skipped = 0;
+        for (int c = 0; c < column; c++) {
+            int tx = o.x + (c << 3);
+            if (tx > -8 && tx < 256) {
+                skipped++;
+            }
+        }
+        if (skipped >= fetched[i]) {
+            continue;
+        }
+
+        int row = (y - o.y) & 0xff;
+        if (flag(OBJ_INTERLACE)) {
+            row = (row << 1) | state[FIELD];
+        }
+        if ((o.attr & 0x80) != 0) {
+            row = o.size.y - 1 - row;
+        }
+        int base = (o.tile & 0x100) != 0 ? state[OBJ_NAME_BASE] + state[OBJ_NAME_SELECT] : state[OBJ_NAME_BASE];
+
+        // large sprites wrap within a 16x16 grid of tiles
+        int columns = o.size.x >> 3;
+        int px = sx & 7;
+        if ((o.attr & 0x40) != 0) {
+            column = columns - 1 - column;
+            px = 7 - px;
+        }
+        int name = ((((o.tile >> 4) + (row >> 3)) & 0xf) << 4) | ((o.tile + column) & 0xf);
+        int pixel = tile_pixel(base, name, 4, px, row & 7);
+        if (pixel != 0) {
+            int palette = (o.attr >> 1) & 7;
+            int z = OBJ_Z[state[BG_MODE] * 4 + ((o.attr >> 4) & 3)];
+            return Pixel(cgram_color(0x80 + (palette << 4) + pixel), z, palette >= 4);
+        }
+    }
+    return TRANSPARENT;
+}
+
+// whether the current mode has a bg, mode 7 only has bg2 with extbg
+bool bg_enabled(int index) {
+    int mode = state[BG_MODE];
+    return BG_BPP[mode * 4 + index] != 0 && (mode != 7 || index == 0 || flag(EXTBG));
+}
+
+// one pixel of a layer, odd picking the half-pixel of bgs in true hires
+Pixel layer_pixel(int index, int x, int odd) {
+    int screens = state[MAIN_SCREEN] | state[SUB_SCREEN];
+    if ((screens & (1 << index)) == 0) {
+        return TRANSPARENT;
+    }
+    int y = state[LINE];
+    if (index == OBJ) {
+        return obj_pixel(y, x);
+    }
+    if (!bg_enabled(index)) {
+        return TRANSPARENT;
+    }
+
+    // mosaic blows up the first pixel of each block, the vertical part by rendering an earlier line
+    int size = state[MOSAIC_SIZE];
+    if ((state[MOSAIC_ENABLE] & (1 << index)) != 0) {
+        y -= y % size;
+        x -= x % size;
+    }
+    // bgs are fetched by the scanline counter, which is one ahead of the frame row
+    int line = y + 1;
+    if (state[BG_MODE] == 7) {
+        return mode7_pixel(index, line, x);
+    }
+    return bg_pixel(index, line, odd, x);
+}
+
+bool in_window(int layer, int x) {
+    // each layer has an enable and invert bit for both windows
+    int select = state[WINDOW_SELECT + layer];
+    bool w1 = (state[WINDOW_LEFT] <= x && x <= state[WINDOW_RIGHT]) != ((select & 1) != 0);
+    bool w2 = (state[WINDOW_LEFT + 1] <= x && x <= state[WINDOW_RIGHT + 1]) != ((select & 4) != 0);
+    bool enable1 = (select & 2) != 0;
+    bool enable2 = (select & 8) != 0;
+    if (!enable1 && !enable2) {
+        return false;
+    } else if (!enable2) {
+        return w1;
+    } else if (!enable1) {
+        return w2;
+    }
+    int logic = state[WINDOW_LOGIC + layer];
+    if (logic == 0) {
+        return w1 || w2;
+    } else if (logic == 1) {
+        return w1 && w2;
+    } else if (logic == 2) {
+        return w1 != w2;
+    }
+    return w1 == w2;
+}
+
+// the color window regions used by cgwsel, never, outside, inside and always
+bool window_region(int mode, bool inside) {
+    return mode == 3 || (mode == 1 && !inside) || (mode == 2 && inside);
+}
+
+int blend(int lhs, int rhs, bool subtract, bool halve) {
+    int result = 0;
+    for (int shift = 0; shift <= 10; shift += 5) {
+        int a = (lhs >> shift) & 0x1f;
+        int b = (rhs >> shift) & 0x1f;
+        int c = subtract ? max(a - b, 0) : a + b;
+        if (halve) {
+            c >>= 1;
+        }
+        result |= min(c, 0x1f) << shift;
+    }
+    return result;
+}
+
+int color_math(int x, int main_layer, Pixel main_pixel, int sub_layer, Pixel sub_pixel) {
+    bool color_window = in_window(COLOR_WINDOW, x);
+    bool black = window_region(state[CLIP_MODE], color_window);
+    int color = black ? 0 : main_pixel.color;
+    if (window_region(state[PREVENT_MODE], color_window) || (state[MATH_LAYERS] & (1 << main_layer)) == 0 || !main_pixel.math) {
+        return color;
+    }
+
+    // the sub screen backdrop is the fixed color, which is never halved
+    int rhs = state[FIXED_COLOR];
+    bool halve = flag(MATH_HALF);
+    if (flag(MATH_SUB_SCREEN)) {
+        rhs = sub_pixel.color;
+        halve = halve && sub_layer != BACKDROP;
+    }
+    return blend(color, rhs, flag(MATH_SUBTRACT), halve && !black);
+}
+
+// converts bgr555 to rgb, scaled by the inidisp brightness
+vec4 to_rgb(int color) {
+    ivec3 channels = ivec3(color & 0x1f, (color >> 5) & 0x1f, (color >> 10) & 0x1f);
+    ivec3 value = ((channels << 3) | (channels >> 2)) * state[BRIGHTNESS] / 15;
+    return vec4(vec3(value) / 255.0, 1.0);
+}
+
+void main() {
+    if (flag(FORCED_BLANK)) {
+        frag_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    int column = int(gl_FragCoord.x);
+    int x = column >> 1;
+    bool true_hires = state[BG_MODE] == 5 || state[BG_MODE] == 6;
+    bool hires = true_hires || flag(PSEUDO_HIRES);
+
+    // front-most pixel of the layers on each screen, skipping layers masked by their window,
+    // in true hires the main screen shows the odd half-pixels and the sub screen the even ones
+    int main_layer = BACKDROP;
+    int sub_layer = BACKDROP;
+    Pixel main_pixel = Pixel(cgram_color(0), 0, true);
+    Pixel sub_pixel = Pixel(state[FIXED_COLOR], 0, true);
+    for (int index = 0; index <= OBJ; index++) {
+        bool on_main = (state[MAIN_SCREEN] & (1 << index)) != 0;
+        bool on_sub = (state[SUB_SCREEN] & (1 << index)) != 0;
+        if (!on_main && !on_sub) {
+            continue;
+        }
+        Pixel pixel = layer_pixel(index, x, 1);
+        Pixel odd_pixel = true_hires && index != OBJ ? layer_pixel(index, x, 0) : pixel;
+        if (on_main && pixel.z > main_pixel.z && !((state[MAIN_WINDOW] & (1 << index)) != 0 && in_window(index, x))) {
+            main_layer = index;
+            main_pixel = pixel;
+        }
+        if (on_sub && odd_pixel.z > sub_pixel.z && !((state[SUB_WINDOW] & (1 << index)) != 0 && in_window(index, x))) {
+            sub_layer = index;
+            sub_pixel = odd_pixel;
+        }
+    }
+
+    // the even half-pixels show the sub screen, blended with the main screen
+    if (hires && (column & 1) == 0) {
+        frag_color = to_rgb(color_math(x, sub_layer, sub_pixel, main_layer, main_pixel));
+    } else {
+        frag_color = to_rgb(color_math(x, main_layer, main_pixel, sub_layer, sub_pixel));
+    }
+}
@@ -0,0 +1,342 @@
+// opengl renderer, running the software renderer's port in gpu.frag for each line
+
+use std::{ffi::{c_char, c_void, CStr}, ptr};
+
+use super::{LineState, Ppu, Renderer, VideoMem};
+use crate::gl::{self, Gl, GLenum, GLint, GLuint};
+
+// the frontend's framebuffer to copy finished frames to
+pub type Framebuffer = Box<dyn Fn() -> usize>;
+
+// values in the state uniform, in the order gpu.frag reads them
+const STATE_LEN: usize = 115;
+
+pub struct Gpu {
+    gl: Gl,
+    program: GLuint,
+    vertex_array: GLuint,
+    // vram, cgram and oam as integer textures, and the frame lines are drawn into
+    vram: GLuint,
+    cgram: GLuint,
+    oam: GLuint,
+    frame: GLuint,
+    framebuffer: GLuint,
+    state_location: GLint,
+    target: Framebuffer,
+    frame_lines: usize,
+    frame_hires: bool,
+    frame_interlace: bool,
+    // size of the last frame copied to the frontend
+    width: usize,
+    height: usize,
+}
+
+impl Gpu {
+    // needs a current opengl 3.1 context, returning the compile or link log if the shaders fail
+    pub fn new(gl: Gl, target: Framebuffer) -> Result<Self, String> {
+        unsafe {
+            let vertex = compile(&gl, gl::VERTEX_SHADER, include_str!("gpu.vert"))?;
+            let fragment = compile(&gl, gl::FRAGMENT_SHADER, include_str!("gpu.frag")).inspect_err(|_| {
+                (gl.delete_shader)(vertex);
+            })?;
+            let program = (gl.create_program)();
+            (gl.attach_shader)(program, vertex);
+            (gl.attach_shader)(program, fragment);
+            (gl.link_program)(program);
+            (gl.delete_shader)(vertex);
+            (gl.delete_shader)(fragment);
+            let mut status = 0;
+            (gl.get_program_iv)(program, gl::LINK_STATUS, &mut status);
+            if status == 0 {
+                let log = info_log(program, gl.get_program_iv, gl.get_program_info_log);
+                (gl.delete_program)(program);
+                return Err(log)
+            }
+
+            // the vertex shader makes its triangle from the vertex ids, but core contexts still need an array bound
+            let mut vertex_array = 0;
+            (gl.gen_vertex_arrays)(1, &mut vertex_array);
+
+            let mut textures = [0; 4];
+            (gl.gen_textures)(4, textures.as_mut_ptr());
+            let [vram, cgram, oam, frame] = textures;
+            let mut framebuffer = 0;
+            (gl.gen_framebuffers)(1, &mut framebuffer);
+
+            let gpu = Self {
+                state_location: (gl.get_uniform_location)(program, c"state".as_ptr()),
+                gl,
+                program,
+                vertex_array,
+                vram,
+                cgram,
+                oam,
+                frame,
+                framebuffer,
+                target,
+                frame_lines: 0,
+                frame_hires: false,
+                frame_interlace: false,
+                width: 256,
+                height: 0,
+            };
+            let gl = &gpu.gl;
+
+            gpu.create_texture(vram, gl::R16UI, 256, 128, gl::RED_INTEGER, gl::UNSIGNED_SHORT);
+            gpu.create_texture(cgram, gl::R16UI, 256, 1, gl::RED_INTEGER, gl::UNSIGNED_SHORT);
+            gpu.create_texture(oam, gl::R8UI, 0x220, 1, gl::RED_INTEGER, gl::UNSIGNED_BYTE);
+            gpu.create_texture(frame, gl::RGBA8, Ppu::FRAME_WIDTH, Ppu::FRAME_HEIGHT, gl::RGBA, gl::UNSIGNED_BYTE);
+
+            (gl.bind_framebuffer)(gl::FRAMEBUFFER, framebuffer);
+            (gl.framebuffer_texture_2d)(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, frame, 0);
+            let complete = (gl.check_framebuffer_status)(gl::FRAMEBUFFER) == gl::FRAMEBUFFER_COMPLETE;
+
+            // each sampler reads the texture unit of the same index
+            (gl.use_program)(program);
+            for (unit, name) in [c"vram", c"cgram", c"oam"].into_iter().enumerate() {
+                (gl.uniform_1i)((gl.get_uniform_location)(program, name.as_ptr()), unit as _);
+            }
+
+            let error = (gl.get_error)();
+            if !complete || error != gl::NO_ERROR {
+                return Err(format!("setup failed with error {error:#x}"))
+            }
+            Ok(gpu)
+        }
+    }
+
+    unsafe fn create_texture(&self, texture: GLuint, internal_format: GLint, width: usize, height: usize, format: GLenum, ty: GLenum) {
+        let gl = &self.gl;
+        unsafe {
+            (gl.bind_texture)(gl::TEXTURE_2D, texture);
+            (gl.tex_parameter_i)(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST);
+            (gl.tex_parameter_i)(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST);
+            (gl.tex_image_2d)(gl::TEXTURE_2D, 0, internal_format, width as _, height as _, 0, format, ty, ptr::null());
+        }
+    }
+
+    unsafe fn upload(&self, unit: u32, texture: GLuint, width: usize, height: usize, ty: GLenum, data: *const c_void) {
+        let gl = &self.gl;
+        unsafe {
+            (gl.active_texture)(gl::TEXTURE0 + unit);
+            (gl.bind_texture)(gl::TEXTURE_2D, texture);
+            (gl.tex_sub_image_2d)(gl::TEXTURE_2D, 0, 0, 0, width as _, height as _, gl::RED_INTEGER, ty, data);
+        }
+    }
+}
+
+impl Renderer for Gpu {
+    fn start_frame(&mut self, state: &LineState) {
+        self.frame_hires = false;
+        self.frame_interlace = state.interlace;
+    }
+
+    fn draw_line(&mut self, mem: &VideoMem, state: &LineState, y: usize) {
+        // interlaced frames weave the two fields together
+        let row = if self.frame_interlace { (y << 1) | state.field as usize } else { y };
+        self.frame_lines = y + 1;
+        self.frame_hires |= matches!(state.bg_mode, 5 | 6) || state.pseudo_hires;
+
+        let gl = &self.gl;
+        unsafe {
+            // the frontend may have left any of these set
+            for capability in [gl::BLEND, gl::DEPTH_TEST, gl::STENCIL_TEST, gl::SCISSOR_TEST, gl::CULL_FACE] {
+                (gl.disable)(capability);
+            }
+            (gl.pixel_store_i)(gl::UNPACK_ALIGNMENT, 1);
+            (gl.pixel_store_i)(gl::UNPACK_ROW_LENGTH, 0);
+
+            // cgram is small enough to send every line, the others only change between a few lines a frame
+            if mem.vram_changed {
+                self.upload(0, self.vram, 256, 128, gl::UNSIGNED_SHORT, mem.vram.as_ptr() as _);
+            }
+            self.upload(1, self.cgram, 256, 1, gl::UNSIGNED_SHORT, mem.cgram.as_ptr() as _);
+            if mem.oam_changed {
+                self.upload(2, self.oam, 0x220, 1, gl::UNSIGNED_BYTE, mem.oam.as_ptr() as _);
+            }
+            for (unit, texture) in [self.vram, self.cgram, self.oam].into_iter().enumerate() {
+                (gl.active_texture)(gl::TEXTURE0 + unit as u32);
+                (gl.bind_texture)(gl::TEXTURE_2D, texture);
+            }
+
+            (gl.use_program)(self.program);
+            (gl.uniform_1iv)(self.state_location, STATE_LEN as _, state.pack(y).as_ptr());
+            (gl.bind_vertex_array)(self.vertex_array);
+            (gl.bind_framebuffer)(gl::FRAMEBUFFER, self.framebuffer);
+            (gl.viewport)(0, row as _, Ppu::FRAME_WIDTH as _, 1);
+            (gl.draw_arrays)(gl::TRIANGLES, 0, 3);
+        }
+    }
+
+    // copies the frame to the frontend's framebuffer, which has its origin at the bottom left
+    fn end_frame(&mut self) {
+        self.width = if self.frame_hires { 512 } else { 256 };
+        self.height = if self.frame_interlace { self.frame_lines << 1 } else { self.frame_lines };
+
+        // lines that weren't hires have the same color in both halves of each pixel
+        let gl = &self.gl;
+        let (width, height) = (self.width as GLint, self.height as GLint);
+        unsafe {
+            (gl.bind_framebuffer)(gl::READ_FRAMEBUFFER, self.framebuffer);
+            (gl.bind_framebuffer)(gl::DRAW_FRAMEBUFFER, (self.target)() as _);
+            (gl.blit_framebuffer)(0, 0, Ppu::FRAME_WIDTH as _, height, 0, height, width, 0, gl::COLOR_BUFFER_BIT, gl::NEAREST as _);
+        }
+    }
+
+    fn frame(&self) -> (Option<&[u32]>, usize, usize) {
+        (None, self.width, self.height)
+    }
+}
+
+impl Drop for Gpu {
+    fn drop(&mut self) {
+        let gl = &self.gl;
+        unsafe {
+            (gl.delete_framebuffers)(1, &self.framebuffer);
+            (gl.delete_textures)(4, [self.vram, self.cgram, self.oam, self.frame].as_ptr());
+            (gl.delete_vertex_arrays)(1, &self.vertex_array);
+            (gl.delete_program)(self.program);
+        }
+    }
+}
+
+impl LineState {
+    // flattens the state for the shader's uniform
+    fn pack(&self, y: usize) -> [GLint; STATE_LEN] {
+        let scalars: [GLint; 24] = [
+            y as _,
+            self.bg_mode as _,
+            self.bg3_priority as _,
+            self.brightness as _,
+            self.forced_blank as _,
+            self.mosaic_size as _,
+            self.mosaic_enable as _,
+            self.extbg as _,
+            self.pseudo_hires as _,
+            self.interlace as _,
+            self.obj_interlace as _,
+            self.field as _,
+            self.direct_color as _,
+            self.main_screen as _,
+            self.sub_screen as _,
+            self.main_window as _,
+            self.sub_window as _,
+            self.clip_mode as _,
+            self.prevent_mode as _,
+            self.math_sub_screen as _,
+            self.math_subtract as _,
+            self.math_half as _,
+            self.math_layers as _,
+            self.fixed_color as _,
+        ];
+        let windows = [self.window_left, self.window_right].into_iter().flatten()
+            .chain(self.window_select)
+            .chain(self.window_logic);
+        let mode7 = [
+            self.m7a, self.m7b, self.m7c, self.m7d, self.m7x, self.m7y, self.m7_hofs, self.m7_vofs,
+            self.m7_over as _, self.m7_hflip as _, self.m7_vflip as _,
+        ];
+        let obj = [self.obj_size as _, self.obj_name_base, self.obj_name_select, self.obj_count as _];
+        let bgs = self.bg.iter().flat_map(|bg| [
+            bg.tilemap_base, bg.tilemap_wide as _, bg.tilemap_tall as _, bg.char_base, bg.hofs, bg.vofs, bg.big_tiles as _,
+        ]);
+
+        let mut packed = [0; STATE_LEN];
+        let values = scalars.into_iter()
+            .chain(windows.map(GLint::from))
+            .chain(mode7.map(GLint::from))
+            .chain(obj.map(GLint::from))
+            .chain(self.obj_range.map(GLint::from))
+            .chain(bgs.map(GLint::from));
+        for (packed, value) in packed.iter_mut().zip(values) {
+            *packed = value;
+        }
+        packed
+    }
+}
+
+unsafe fn compile(gl: &Gl, kind: GLenum, source: &str) -> Result<GLuint, String> {
+    unsafe {
+        let shader = (gl.create_shader)(kind);
+        (gl.shader_source)(shader, 1, &(source.as_ptr() as *const c_char), &(source.len() as GLint));
+        (gl.compile_shader)(shader);
+        let mut status = 0;
+        (gl.get_shader_iv)(shader, gl::COMPILE_STATUS, &mut status);
+        if status == 0 {
+            let log = info_log(shader, gl.get_shader_iv, gl.get_shader_info_log);
+            (gl.delete_shader)(shader);
+            return Err(log)
+        }
+        Ok(shader)
+    }
+}
+
+// shaders and programs have the same pair of functions for their logs
+unsafe fn info_log(
+    object: GLuint,
+    get_iv: unsafe extern "system" fn(GLuint, GLenum, *mut GLint),
+    get_log: unsafe extern "system" fn(GLuint, GLint, *mut GLint, *mut c_char),
+) -> String {
+    unsafe {
+        let mut len = 0;
+        get_iv(object, gl::INFO_LOG_LENGTH, &mut len);
+        let mut log = vec![0u8; len.max(1) as usize];
+        get_log(object, len, ptr::null_mut(), log.as_mut_ptr() as _);
+        CStr::from_bytes_until_nul(&log).map(|log| log.to_string_lossy().into_owned()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn state_layout_matches_shader() {
+        let source = include_str!("gpu.frag");
+        assert!(source.contains(&format!("uniform int state[{STATE_LEN}];")));
+        let offsets: HashMap<&str, usize> = source
+            .lines()
+            .filter_map(|line| {
+                let mut words = line.strip_prefix("#define ")?.split_whitespace();
+                Some((words.next()?, words.next()?.parse().ok()?))
+            })
+            .collect();
+
+        let mut state = LineState {
+            brightness: 9,
+            mosaic_size: 12,
+            direct_color: true,
+            fixed_color: 0x1234,
+            window_right: [0x40, 0x80],
+            window_logic: [0, 0, 0, 0, 0, 3],
+            m7_vofs: 0x1fff,
+            m7_vflip: true,
+            obj_name_select: 0x3000,
+            obj_count: 32,
+            ..Default::default()
+        };
+        state.obj_range[31] = 127;
+        state.bg[1].vofs = 0x155;
+        state.bg[3].big_tiles = true;
+
+        let packed = state.pack(200);
+        let at = |name: &str| packed[offsets[name]];
+        assert_eq!(at("LINE"), 200);
+        assert_eq!(at("BRIGHTNESS"), 9);
+        assert_eq!(at("MOSAIC_SIZE"), 12);
+        assert_eq!(at("DIRECT_COLOR"), 1);
+        assert_eq!(at("FIXED_COLOR"), 0x1234);
+        assert_eq!(packed[offsets["WINDOW_RIGHT"] + 1], 0x80);
+        assert_eq!(packed[offsets["WINDOW_LOGIC"] + 5], 3);
+        assert_eq!(at("M7_VOFS"), 0x1fff);
+        assert_eq!(at("M7_VFLIP"), 1);
+        assert_eq!(at("OBJ_NAME_SELECT"), 0x3000);
+        assert_eq!(at("OBJ_COUNT"), 32);
+        assert_eq!(packed[offsets["OBJ_RANGE"] + 31], 127);
+        assert_eq!(packed[offsets["BG"] + 7 + offsets["VOFS"]], 0x155);
+        assert_eq!(packed[offsets["BG"] + 3 * 7 + offsets["BIG_TILES"]], 1);
+        assert_eq!(offsets["BG"] + 4 * 7, STATE_LEN);
+    }
+}
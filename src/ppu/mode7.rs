@@ -0,0 +1,86 @@
+// mode 7 affine background
+
+use super::{LineState, Ppu, VideoMem};
+use super::render::{BG_Z, Pixel, direct_color};
+
+// m7sel screen over modes
+const OVER_WRAP: u8 = 1;
+const OVER_TRANSPARENT: u8 = 2;
+
+// sign extends the 13-bit center and scroll registers
+#[inline]
+const fn sext13(value: u16) -> i32 {
+    (((value << 3) as i16) >> 3) as i32
+}
+
+// offsets from the center are clipped to 10 bits, keeping the sign
+#[inline]
+const fn clip(value: i32) -> i32 {
+    if (value & 0x2000) != 0 { value | !0x3ff } else { value & 0x3ff }
+}
+
+impl Ppu {
+    // m7a times the high byte of m7b, readable at $2134-$2136
+    #[inline]
+    pub(super) const fn m7_product(&self) -> i32 {
+        (self.state.m7a as i16 as i32) * ((self.state.m7b >> 8) as i8 as i32)
+    }
+
+    // the matrix and center registers are written twice through a shared latch
+    pub(super) fn write_m7(&mut self, value: u8) -> u16 {
+        let word = ((value as u16) << 8) | self.m7_latch as u16;
+        self.m7_latch = value;
+        word
+    }
+}
+
+impl LineState {
+    pub(super) fn render_mode7(&self, mem: &VideoMem, index: usize, line: u16, out: &mut [Pixel; Ppu::LINE_WIDTH]) {
+        let a = self.m7a as i16 as i32;
+        let b = self.m7b as i16 as i32;
+        let c = self.m7c as i16 as i32;
+        let d = self.m7d as i16 as i32;
+        let hcenter = sext13(self.m7x);
+        let vcenter = sext13(self.m7y);
+        let hoffset = clip(sext13(self.m7_hofs) - hcenter);
+        let voffset = clip(sext13(self.m7_vofs) - vcenter);
+
+        let line = if self.m7_vflip { 255 - line as i32 } else { line as i32 };
+
+        // the hardware drops the low 6 bits of each product
+        let start_x = ((a * hoffset) & !63) + ((b * voffset) & !63) + ((b * line) & !63) + (hcenter << 8);
+        let start_y = ((c * hoffset) & !63) + ((d * voffset) & !63) + ((d * line) & !63) + (vcenter << 8);
+
+        for (x, out) in out.iter_mut().enumerate() {
+            let x = if self.m7_hflip { 255 - x as i32 } else { x as i32 };
+            let px = (start_x + a * x) >> 8;
+            let py = (start_y + c * x) >> 8;
+
+            // the playfield is 1024x1024, outside of it either wraps, is transparent or repeats tile 0
+            let outside = (px | py) & !0x3ff != 0;
+            let tile = if outside && self.m7_over > OVER_WRAP {
+                if self.m7_over == OVER_TRANSPARENT {
+                    continue
+                }
+                0
+            } else {
+                mem.vram[(((py & 0x3ff) >> 3) << 7 | ((px & 0x3ff) >> 3)) as usize] & 0xff
+            };
+            let pixel = (mem.vram[((tile << 6) | ((py & 7) << 3) as u16 | (px & 7) as u16) as usize] >> 8) as u8;
+
+            if index == 0 {
+                if pixel != 0 {
+                    let color = if self.direct_color { direct_color(pixel, 0) } else { mem.cgram[pixel as usize] };
+                    *out = Pixel { color, z: BG_Z[7][0][0], math: true };
+                }
+            } else if (pixel & 0x7f) != 0 {
+                // extbg reuses the same pixels as a 7-bit layer with a priority bit
+                *out = Pixel {
+                    color: mem.cgram[(pixel & 0x7f) as usize],
+                    z: BG_Z[7][1][(pixel >> 7) as usize],
+                    math: true,
+                };
+            }
+        }
+    }
+}
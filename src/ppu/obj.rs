@@ -0,0 +1,162 @@
+// sprite layer
+
+use super::{LineState, Ppu, VideoMem};
+use super::render::{OBJ_Z, Pixel};
+
+#[derive(Clone, Copy)]
+struct Obj {
+    x: i16,
+    y: u8,
+    tile: u16,
+    attr: u8,
+    width: u16,
+    height: u16,
+}
+
+impl Obj {
+    const H_FLIP: u8 = 0x40;
+    const V_FLIP: u8 = 0x80;
+
+    // interlaced sprites cover half as many lines, showing every other row in each field
+    #[inline]
+    const fn on_line(&self, y: u16, interlace: bool) -> bool {
+        // sprites wrap around the bottom of the screen
+        (y.wrapping_sub(self.y as u16) & 0xff) < (self.height >> interlace as u16)
+            && self.x < 256
+            && self.x + self.width as i16 > 0
+    }
+
+    // whether the 8 pixel column starting at x is on screen
+    #[inline]
+    const fn column_visible(x: i16) -> bool {
+        x > -8 && x < 256
+    }
+
+    #[inline]
+    const fn palette(&self) -> u16 {
+        ((self.attr >> 1) & 7) as u16
+    }
+
+    #[inline]
+    const fn priority(&self) -> usize {
+        ((self.attr >> 4) & 3) as usize
+    }
+}
+
+// small and large sprite sizes selected by obsel
+const OBJ_SIZES: [[(u16, u16); 2]; 8] = [
+    [(8, 8), (16, 16)],
+    [(8, 8), (32, 32)],
+    [(8, 8), (64, 64)],
+    [(16, 16), (32, 32)],
+    [(16, 16), (64, 64)],
+    [(32, 32), (64, 64)],
+    [(16, 32), (32, 64)],
+    [(16, 32), (32, 32)],
+];
+
+// sprites and 8x1 slivers the ppu can handle per scanline
+const OBJ_RANGE_LIMIT: usize = 32;
+const OBJ_TILE_LIMIT: usize = 34;
+
+impl Ppu {
+    // finds the sprites on a line for the renderer, setting the range and time over flags
+    pub(super) fn evaluate_obj(&mut self, y: u16) {
+        // range evaluation starts at the priority sprite when oamaddh bit 7 is set
+        let first = if self.oam_priority { ((self.oam_reload >> 1) & 0x7f) as usize } else { 0 };
+        let state = &mut self.state;
+        state.obj_count = 0;
+        for i in 0..128 {
+            let index = (first + i) & 0x7f;
+            if !state.obj(&self.oam, index).on_line(y, state.obj_interlace) {
+                continue
+            }
+            if state.obj_count == OBJ_RANGE_LIMIT {
+                self.range_over = true;
+                break
+            }
+            state.obj_range[state.obj_count] = index as u8;
+            state.obj_count += 1;
+        }
+
+        let mut tiles = 0;
+        for &index in &state.obj_range[..state.obj_count] {
+            let obj = state.obj(&self.oam, index as usize);
+            tiles += (0..obj.width >> 3).filter(|&column| Obj::column_visible(obj.x + (column << 3) as i16)).count();
+        }
+        if tiles > OBJ_TILE_LIMIT {
+            self.time_over = true;
+        }
+    }
+}
+
+impl LineState {
+    pub(super) fn render_obj(&self, mem: &VideoMem, y: u16, out: &mut [Pixel; Ppu::LINE_WIDTH]) {
+        // tiles are fetched from the last sprite in range backwards, so earlier sprites are the
+        // ones dropped when there are too many, and drawn over the later ones otherwise
+        let mut tiles = 0;
+        'fetch: for &index in self.obj_range[..self.obj_count].iter().rev() {
+            let obj = self.obj(mem.oam, index as usize);
+            let mut row = y.wrapping_sub(obj.y as u16) & 0xff;
+            if self.obj_interlace {
+                row = (row << 1) | self.field as u16;
+            }
+            if (obj.attr & Obj::V_FLIP) != 0 {
+                row = obj.height - 1 - row;
+            }
+            let base = if (obj.tile & 0x100) != 0 {
+                self.obj_name_base.wrapping_add(self.obj_name_select)
+            } else {
+                self.obj_name_base
+            };
+            let z = OBJ_Z[self.bg_mode as usize][obj.priority()];
+
+            let columns = obj.width >> 3;
+            for column in 0..columns {
+                let tx = obj.x + (column << 3) as i16;
+                if !Obj::column_visible(tx) {
+                    continue
+                }
+                if tiles == OBJ_TILE_LIMIT {
+                    break 'fetch
+                }
+                tiles += 1;
+
+                // large sprites wrap within a 16x16 grid of tiles
+                let column = if (obj.attr & Obj::H_FLIP) != 0 { columns - 1 - column } else { column };
+                let name = ((((obj.tile >> 4) + (row >> 3)) & 0xf) << 4) | ((obj.tile + column) & 0xf);
+                for px in 0..8 {
+                    let sx = tx + px as i16;
+                    if !(0..256).contains(&sx) {
+                        continue
+                    }
+                    let px = if (obj.attr & Obj::H_FLIP) != 0 { 7 - px } else { px };
+                    let pixel = self.tile_pixel(mem, base, name, 4, px, row & 7);
+                    if pixel != 0 {
+                        out[sx as usize] = Pixel {
+                            color: mem.cgram[(0x80 + (obj.palette() << 4) + pixel as u16) as usize],
+                            z,
+                            math: obj.palette() >= 4,
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    fn obj(&self, oam: &[u8; 0x220], index: usize) -> Obj {
+        let low = &oam[index << 2..][..4];
+        let high = oam[0x200 + (index >> 2)] >> ((index & 3) << 1);
+        let (width, height) = OBJ_SIZES[self.obj_size as usize][((high >> 1) & 1) as usize];
+        // x is 9 bits, sign extended
+        let x = (((high & 1) as u16) << 8) | low[0] as u16;
+        Obj {
+            x: ((x << 7) as i16) >> 7,
+            y: low[1],
+            tile: (((low[3] & 1) as u16) << 8) | low[2] as u16,
+            attr: low[3],
+            width,
+            height,
+        }
+    }
+}
@@ -0,0 +1,409 @@
+// software scanline renderer
+
+use super::{Bg, LineState, Ppu, Renderer, VideoMem};
+use super::compose::OBJ;
+
+// one line of bg1-bg4 and obj
+pub(super) type Layers = [[Pixel; Ppu::LINE_WIDTH]; 5];
+
+#[derive(Clone, Copy, Default)]
+pub(super) struct Pixel {
+    // bgr555
+    pub color: u16,
+    // depth in the current mode's priority order, 0 is transparent
+    pub z: u8,
+    // whether color math can apply, sprites only take part with palettes 4-7
+    pub math: bool,
+}
+
+// bits per pixel of each bg, 0 when the mode doesn't have it
+const BG_BPP: [[u8; 4]; 8] = [
+    [2, 2, 2, 2],
+    [4, 4, 2, 0],
+    [4, 4, 0, 0],
+    [8, 4, 0, 0],
+    [8, 2, 0, 0],
+    [4, 2, 0, 0],
+    [4, 0, 0, 0],
+    [8, 7, 0, 0],
+];
+
+// depth of each bg's low and high priority tiles, leaving room for sprites in between
+pub(super) const BG_Z: [[[u8; 2]; 4]; 8] = [
+    [[8, 11], [7, 10], [2, 5], [1, 4]],
+    [[6, 9], [5, 8], [1, 3], [0, 0]],
+    [[3, 7], [1, 5], [0, 0], [0, 0]],
+    [[3, 7], [1, 5], [0, 0], [0, 0]],
+    [[3, 7], [1, 5], [0, 0], [0, 0]],
+    [[3, 7], [1, 5], [0, 0], [0, 0]],
+    [[3, 7], [1, 5], [0, 0], [0, 0]],
+    [[3, 3], [1, 5], [0, 0], [0, 0]],
+];
+
+// depth of sprites at each of their 4 priorities
+pub(super) const OBJ_Z: [[u8; 4]; 8] = [
+    [3, 6, 9, 12],
+    [2, 4, 7, 10],
+    [2, 4, 6, 8],
+    [2, 4, 6, 8],
+    [2, 4, 6, 8],
+    [2, 4, 6, 8],
+    [2, 4, 6, 8],
+    [2, 4, 6, 7],
+];
+
+// bg3 high priority tiles go in front of everything in mode 1 with bgmode bit 3 set
+const BG3_PRIORITY_Z: u8 = 11;
+
+impl Ppu {
+    pub(super) const LINE_WIDTH: usize = 256;
+}
+
+// reference renderer, drawing each line into an xrgb8888 frame as it's reached
+pub struct Software {
+    frame: Box<[u32; Ppu::FRAME_WIDTH * Ppu::FRAME_HEIGHT]>,
+    // rows holding 512 pixels rather than 256
+    row_wide: [bool; Ppu::FRAME_HEIGHT],
+    frame_lines: usize,
+    frame_hires: bool,
+    frame_interlace: bool,
+    // size of the last finished frame, the next one has started by the time it's shown
+    width: usize,
+    height: usize,
+}
+
+impl Software {
+    pub fn new() -> Self {
+        Self {
+            frame: Box::new([0; _]),
+            row_wide: [false; _],
+            frame_lines: 0,
+            frame_hires: false,
+            frame_interlace: false,
+            width: 256,
+            height: 0,
+        }
+    }
+
+    #[inline]
+    const fn frame_height(&self) -> usize {
+        if self.frame_interlace { self.frame_lines << 1 } else { self.frame_lines }
+    }
+}
+
+impl Renderer for Software {
+    fn start_frame(&mut self, state: &LineState) {
+        self.frame_hires = false;
+        self.frame_interlace = state.interlace;
+    }
+
+    fn draw_line(&mut self, mem: &VideoMem, state: &LineState, y: usize) {
+        // interlaced frames weave the two fields together
+        let row = if self.frame_interlace { (y << 1) | state.field as usize } else { y };
+        let true_hires = matches!(state.bg_mode, 5 | 6);
+        let hires = true_hires || state.pseudo_hires;
+        self.frame_lines = y + 1;
+        self.frame_hires |= hires;
+        self.row_wide[row] = hires;
+
+        let line = &mut self.frame[row * Ppu::FRAME_WIDTH..][..Ppu::FRAME_WIDTH];
+        if state.forced_blank {
+            line.fill(0);
+            return
+        }
+
+        // in true hires the main screen shows the odd half-pixels and the sub screen the even ones
+        let screens = state.main_screen | state.sub_screen;
+        let mut layers = [[Pixel::default(); Ppu::LINE_WIDTH]; 5];
+        let mut sub_layers = [[Pixel::default(); Ppu::LINE_WIDTH]; 5];
+        for index in 0..OBJ {
+            if (screens & (1 << index)) == 0 || !state.bg_enabled(index) {
+                continue
+            }
+            let mosaic = (state.mosaic_enable & (1 << index)) != 0;
+            // bgs are fetched by the scanline counter, which is one ahead of the frame row
+            let line = if mosaic { y - y % state.mosaic_size } else { y } as u16 + 1;
+            if state.bg_mode == 7 {
+                state.render_mode7(mem, index, line, &mut layers[index]);
+            } else {
+                state.render_bg(mem, index, line, 1, &mut layers[index]);
+                if true_hires {
+                    state.render_bg(mem, index, line, 0, &mut sub_layers[index]);
+                }
+            }
+            if mosaic {
+                state.mosaic(&mut layers[index]);
+                state.mosaic(&mut sub_layers[index]);
+            }
+        }
+        if (screens & (1 << OBJ)) != 0 {
+            state.render_obj(mem, y as u16, &mut layers[OBJ]);
+        }
+        if true_hires {
+            sub_layers[OBJ] = layers[OBJ];
+            state.compose_line(mem, line, &layers, &sub_layers, hires);
+        } else {
+            state.compose_line(mem, line, &layers, &layers, hires);
+        }
+    }
+
+    // brings every row of the frame to the same width, once it's known if any line was hires
+    fn end_frame(&mut self) {
+        self.width = if self.frame_hires { 512 } else { 256 };
+        self.height = self.frame_height();
+        for row in 0..self.height {
+            let line = &mut self.frame[row * Ppu::FRAME_WIDTH..][..Ppu::FRAME_WIDTH];
+            if self.frame_hires && !self.row_wide[row] {
+                for x in (0..Ppu::FRAME_WIDTH / 2).rev() {
+                    line[(x << 1) + 1] = line[x];
+                    line[x << 1] = line[x];
+                }
+            } else if !self.frame_hires && self.row_wide[row] {
+                for x in 0..Ppu::FRAME_WIDTH / 2 {
+                    line[x] = line[(x << 1) + 1];
+                }
+            }
+            self.row_wide[row] = self.frame_hires;
+        }
+    }
+
+    fn frame(&self) -> (Option<&[u32]>, usize, usize) {
+        (Some(&self.frame[..]), self.width, self.height)
+    }
+}
+
+impl LineState {
+    // blows up the first pixel of each block horizontally, the vertical part happens by rendering an earlier line
+    fn mosaic(&self, layer: &mut [Pixel; Ppu::LINE_WIDTH]) {
+        for x in 0..Ppu::LINE_WIDTH {
+            layer[x] = layer[x - x % self.mosaic_size];
+        }
+    }
+
+    // whether the current mode has a bg, mode 7 only has bg2 with extbg
+    #[inline]
+    fn bg_enabled(&self, index: usize) -> bool {
+        BG_BPP[self.bg_mode as usize][index] != 0 && (self.bg_mode != 7 || index == 0 || self.extbg)
+    }
+
+    // in hires modes, half picks the even or odd half-pixels of the 512 pixel line
+    fn render_bg(&self, mem: &VideoMem, index: usize, line: u16, half: u16, out: &mut [Pixel; Ppu::LINE_WIDTH]) {
+        let bg = &self.bg[index];
+        let bpp = BG_BPP[self.bg_mode as usize][index];
+        let [z_low, mut z_high] = BG_Z[self.bg_mode as usize][index];
+        if self.bg_mode == 1 && index == 2 && self.bg3_priority {
+            z_high = BG3_PRIORITY_Z;
+        }
+
+        // modes 5 and 6 always use 16 pixel wide tiles, and render every line of both fields when interlaced
+        let hires = self.bg_mode >= 5;
+        let y = if hires && self.interlace { (line << 1) | self.field as u16 } else { line };
+        let tile_width = if bg.big_tiles || hires { 16 } else { 8 };
+        let tile_height = if bg.big_tiles { 16 } else { 8 };
+        let palette_base = if self.bg_mode == 0 { index as u16 * 32 } else { 0 };
+
+        for (x, out) in out.iter_mut().enumerate() {
+            let (hofs, vofs) = self.bg_offsets(mem, index, x as u16);
+            let sx = if hires { ((x as u16 + hofs) << 1) | half } else { x as u16 + hofs };
+            let sy = y + vofs;
+
+            let entry = self.tilemap_entry(mem, bg, sx / tile_width, sy / tile_height);
+            let mut px = sx % tile_width;
+            let mut py = sy % tile_height;
+            if (entry & 0x4000) != 0 {
+                px = tile_width - 1 - px;
+            }
+            if (entry & 0x8000) != 0 {
+                py = tile_height - 1 - py;
+            }
+
+            // big tiles are made of the neighbouring 8x8 tiles in vram
+            let tile = (entry & 0x3ff) + (px >> 3) + ((py >> 3) << 4);
+            let pixel = self.tile_pixel(mem, bg.char_base, tile, bpp, px & 7, py & 7);
+            if pixel == 0 {
+                continue
+            }
+
+            let palette = (entry >> 10) & 7;
+            let color = match bpp {
+                2 => mem.cgram[(palette_base + (palette << 2) + pixel as u16) as usize],
+                4 => mem.cgram[((palette << 4) + pixel as u16) as usize],
+                _ if self.direct_color => direct_color(pixel, palette),
+                _ => mem.cgram[pixel as usize],
+            };
+            *out = Pixel {
+                color,
+                z: if (entry & 0x2000) != 0 { z_high } else { z_low },
+                math: true,
+            };
+        }
+    }
+
+    // scroll offsets for a bg at column x, taking offset-per-tile into account
+    fn bg_offsets(&self, mem: &VideoMem, index: usize, x: u16) -> (u16, u16) {
+        let bg = &self.bg[index];
+        let (mut hofs, mut vofs) = (bg.hofs, bg.vofs);
+        if !matches!(self.bg_mode, 2 | 4 | 6) || index >= 2 {
+            return (hofs, vofs)
+        }
+
+        // the first column on screen is never affected
+        let column = (x + (hofs & 7)) >> 3;
+        if column == 0 {
+            return (hofs, vofs)
+        }
+
+        // bg3's tilemap holds the replacement offsets, bit 13 applies them to bg1 and bit 14 to bg2
+        let bg3 = &self.bg[2];
+        let tx = (column - 1) + (bg3.hofs >> 3);
+        let ty = bg3.vofs >> 3;
+        let enable = 0x2000 << index;
+        if self.bg_mode == 4 {
+            // mode 4 only has room for one offset per column, bit 15 picks which
+            let entry = self.tilemap_entry(mem, bg3, tx, ty);
+            if (entry & enable) != 0 {
+                if (entry & 0x8000) != 0 {
+                    vofs = entry & 0x3ff;
+                } else {
+                    hofs = (entry & 0x3f8) | (hofs & 7);
+                }
+            }
+        } else {
+            let h_entry = self.tilemap_entry(mem, bg3, tx, ty);
+            let v_entry = self.tilemap_entry(mem, bg3, tx, ty + 1);
+            if (h_entry & enable) != 0 {
+                hofs = (h_entry & 0x3f8) | (hofs & 7);
+            }
+            if (v_entry & enable) != 0 {
+                vofs = v_entry & 0x3ff;
+            }
+        }
+        (hofs, vofs)
+    }
+
+    fn tilemap_entry(&self, mem: &VideoMem, bg: &Bg, tx: u16, ty: u16) -> u16 {
+        // each screen is 32x32 tiles, bigger tilemaps lay screens out left to right then top to bottom
+        // and the ones past the top of vram wrap around to the start
+        let mut addr = bg.tilemap_base + ((ty & 0x1f) << 5) + (tx & 0x1f);
+        if bg.tilemap_wide && (tx & 0x20) != 0 {
+            addr = addr.wrapping_add(0x400);
+        }
+        if bg.tilemap_tall && (ty & 0x20) != 0 {
+            addr = addr.wrapping_add(if bg.tilemap_wide { 0x800 } else { 0x400 });
+        }
+        mem.vram[(addr & 0x7fff) as usize]
+    }
+
+    pub(super) fn tile_pixel(&self, mem: &VideoMem, char_base: u16, tile: u16, bpp: u8, px: u16, py: u16) -> u8 {
+        // each pair of bitplanes is interleaved into one word per row
+        let addr = char_base.wrapping_add(tile.wrapping_mul(bpp as u16 * 4)).wrapping_add(py);
+        let mut pixel = 0;
+        for plane in 0..(bpp as u16 / 2) {
+            let word = mem.vram[(addr.wrapping_add(plane * 8) & 0x7fff) as usize];
+            let bits = ((word >> (7 - px)) & 1) | (((word >> (15 - px)) & 1) << 1);
+            pixel |= (bits as u8) << (plane * 2);
+        }
+        pixel
+    }
+}
+
+// 8bpp pixels as bbgggrrr plus the low bit of each channel from the palette, when cgwsel bit 0 is set
+#[inline]
+pub(super) const fn direct_color(pixel: u8, palette: u16) -> u16 {
+    let r = (((pixel & 7) as u16) << 2) | ((palette & 1) << 1);
+    let g = ((((pixel >> 3) & 7) as u16) << 2) | (palette & 2);
+    let b = (((pixel >> 6) as u16) << 3) | (palette & 4);
+    r | (g << 5) | (b << 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bg_line_from_vram() {
+        let mut vram = Box::new([0; 0x8000]);
+        let mut cgram = Box::new([0; 0x100]);
+        let oam = Box::new([0; 0x220]);
+
+        // tile 1 only has pixels on its second row, the left four at color 1
+        vram[0x1010 + 1] = 0x00f0;
+        // the first two tilemap entries use it with palettes 0 and 2
+        vram[0] = 0x0001;
+        vram[1] = 0x0801;
+        cgram[0x00] = 0x03e0;
+        cgram[0x01] = 0x001f;
+        cgram[0x21] = 0x7c00;
+
+        let mut state = LineState { brightness: 15, bg_mode: 1, main_screen: 1, mosaic_size: 1, ..Default::default() };
+        state.bg[0].char_base = 0x1000;
+
+        let mem = VideoMem { vram: &vram, cgram: &cgram, oam: &oam, vram_changed: true, oam_changed: true };
+        let mut software = Software::new();
+        software.start_frame(&state);
+        software.draw_line(&mem, &state, 0);
+        software.draw_line(&mem, &state, 1);
+        software.end_frame();
+
+        let (red, green, blue) = (0xff0000, 0x00ff00, 0x0000ff);
+        // the first frame row is scanline 1, which shows the second row of the tiles
+        let row = &software.frame[..16];
+        assert_eq!(row, [red, red, red, red, green, green, green, green, blue, blue, blue, blue, green, green, green, green]);
+        let row = &software.frame[Ppu::FRAME_WIDTH..][..16];
+        assert!(row.iter().all(|&pixel| pixel == green));
+    }
+
+    #[test]
+    fn tilemap_wraps_past_the_top_of_vram() {
+        let mut vram = Box::new([0; 0x8000]);
+        let mut cgram = Box::new([0; 0x100]);
+        let oam = Box::new([0; 0x220]);
+
+        // the bottom right screen of a 64x64 tilemap at $fc00 is at $0800 once wrapped
+        vram[0x1010] = 0x00f0;
+        vram[0x0800] = 0x0001;
+        cgram[0x01] = 0x001f;
+
+        let mut state = LineState { brightness: 15, bg_mode: 1, main_screen: 1, mosaic_size: 1, ..Default::default() };
+        state.bg[0] = Bg {
+            tilemap_base: 0xfc00,
+            tilemap_wide: true,
+            tilemap_tall: true,
+            char_base: 0x1000,
+            hofs: 0x100,
+            vofs: 0xff,
+            big_tiles: false,
+        };
+
+        let mem = VideoMem { vram: &vram, cgram: &cgram, oam: &oam, vram_changed: true, oam_changed: true };
+        let mut software = Software::new();
+        software.start_frame(&state);
+        software.draw_line(&mem, &state, 0);
+        software.end_frame();
+
+        let (red, black) = (0xff0000, 0x000000);
+        assert_eq!(software.frame[..8], [red, red, red, red, black, black, black, black]);
+    }
+
+    #[test]
+    fn frame_size_outlasts_the_next_frame_start() {
+        let vram = Box::new([0; 0x8000]);
+        let cgram = Box::new([0; 0x100]);
+        let oam = Box::new([0; 0x220]);
+        let mem = VideoMem { vram: &vram, cgram: &cgram, oam: &oam, vram_changed: true, oam_changed: true };
+
+        let hires = LineState { brightness: 15, bg_mode: 5, interlace: true, mosaic_size: 1, ..Default::default() };
+        let mut software = Software::new();
+        software.start_frame(&hires);
+        for y in 0..224 {
+            software.draw_line(&mem, &hires, y);
+        }
+        software.end_frame();
+
+        // the frame is shown after the next one starts, which may be neither hires nor interlaced
+        software.start_frame(&LineState { mosaic_size: 1, ..Default::default() });
+        let (frame, width, height) = software.frame();
+        assert!(frame.is_some());
+        assert_eq!((width, height), (512, 448));
+    }
+}
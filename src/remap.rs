@@ -0,0 +1,114 @@
+// core-side button remapping between the frontend's buttons and the pads
+
+use std::ffi::CStr;
+
+// snes buttons in report order
+pub const BUTTONS: usize = 12;
+const B: usize = 0;
+const A: usize = 8;
+const R: usize = 11;
+
+// what each button does in smw
+const LABELS: [&CStr; BUTTONS] = [
+    c"Jump",
+    c"Run/Grab",
+    c"Item Box",
+    c"Pause",
+    c"D-Pad Up",
+    c"D-Pad Down",
+    c"D-Pad Left",
+    c"D-Pad Right",
+    c"Spin Jump",
+    c"Run/Grab",
+    c"Scroll Left",
+    c"Scroll Right",
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Remap {
+    // the snes button each frontend button presses
+    targets: [usize; BUTTONS],
+}
+
+impl Default for Remap {
+    fn default() -> Self {
+        Self { targets: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11] }
+    }
+}
+
+impl Remap {
+    #[inline]
+    pub const fn swap_jump(mut self, swap_jump: bool) -> Self {
+        if swap_jump {
+            self.targets[B] = A;
+            self.targets[A] = B;
+        }
+        self
+    }
+
+    // r stops scrolling the screen and spin jumps instead
+    #[inline]
+    pub const fn spin_on_r(mut self, spin_on_r: bool) -> Self {
+        if spin_on_r {
+            self.targets[R] = A;
+        }
+        self
+    }
+
+    // remaps buttons in report order, b in the top bit
+    pub const fn apply(&self, buttons: u16) -> u16 {
+        let mut result = 0;
+        let mut button = 0;
+        while button < BUTTONS {
+            if (buttons & (0x8000 >> button)) != 0 {
+                result |= 0x8000 >> self.targets[button];
+            }
+            button += 1;
+        }
+        result
+    }
+
+    // what a frontend button does once remapped
+    #[inline]
+    pub const fn label(&self, button: usize) -> &'static CStr {
+        LABELS[self.targets[button]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUTTON_B: u16 = 0x8000;
+    const BUTTON_A: u16 = 0x0080;
+    const BUTTON_R: u16 = 0x0010;
+
+    #[test]
+    fn default_passes_buttons_through() {
+        let remap = Remap::default();
+        assert_eq!(remap.apply(0xfff0), 0xfff0);
+        assert_eq!(remap.label(B), c"Jump");
+        assert_eq!(remap.label(A), c"Spin Jump");
+    }
+
+    #[test]
+    fn swap_jump() {
+        let remap = Remap::default().swap_jump(true);
+        assert_eq!(remap.apply(BUTTON_B), BUTTON_A);
+        assert_eq!(remap.apply(BUTTON_A), BUTTON_B);
+        assert_eq!(remap.apply(BUTTON_A | BUTTON_B), BUTTON_A | BUTTON_B);
+        assert_eq!(remap.label(B), c"Spin Jump");
+        assert_eq!(remap.label(A), c"Jump");
+    }
+
+    #[test]
+    fn spin_on_r() {
+        let remap = Remap::default().spin_on_r(true);
+        assert_eq!(remap.apply(BUTTON_R), BUTTON_A);
+        assert_eq!(remap.label(R), c"Spin Jump");
+        // with jumps swapped r still spin jumps
+        let remap = remap.swap_jump(true);
+        assert_eq!(remap.apply(BUTTON_R | BUTTON_A), BUTTON_A | BUTTON_B);
+        assert_eq!(remap.label(R), c"Spin Jump");
+    }
+}
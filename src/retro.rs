@@ -1,4 +1,5 @@
-#![allow(non_camel_case_types)]
+// bindings for libretro.h, not all of which are used
+#![allow(non_camel_case_types, dead_code)]
 
 use std::{ffi::{c_char, c_uint, c_void, CStr}, marker::PhantomData};
 
@@ -8,15 +9,41 @@ pub const API_VERSION: c_uint = 1;
 // 443
 pub const REGION_NTSC: c_uint = 0;
 
+pub const DEVICE_NONE: c_uint = 0;
+pub const DEVICE_JOYPAD: c_uint = 1;
+pub const DEVICE_MOUSE: c_uint = 2;
+
+#[inline]
+pub const fn device_subclass(base: c_uint, id: c_uint) -> c_uint {
+    ((id + 1) << 8) | base
+}
+
+// buttons in the same order as the snes report
+pub const DEVICE_ID_JOYPAD_B: c_uint = 0;
+pub const DEVICE_ID_JOYPAD_R: c_uint = 11;
+
+pub const DEVICE_ID_MOUSE_X: c_uint = 0;
+pub const DEVICE_ID_MOUSE_Y: c_uint = 1;
+pub const DEVICE_ID_MOUSE_LEFT: c_uint = 2;
+pub const DEVICE_ID_MOUSE_RIGHT: c_uint = 3;
+
 // 865
 pub const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
 
+pub const ENVIRONMENT_SET_INPUT_DESCRIPTORS: c_uint = 11;
+
 // 945
 pub const ENVIRONMENT_SET_HW_RENDER: c_uint = 14;
 
 // 1051
 pub const ENVIRONMENT_SET_SUPPORT_NO_GAME: c_uint = 18;
 
+pub const ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+pub const ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+pub const ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+
+pub const ENVIRONMENT_SET_CONTROLLER_INFO: c_uint = 35;
+
 // 3986
 pub type proc_address_t = Option<unsafe extern "C" fn()>;
 
@@ -30,6 +57,8 @@ pub type hw_get_proc_address_t = Option<unsafe extern "C" fn(sym: *const c_char)
 pub type hw_context_type = c_uint;
 pub const HW_CONTEXT_OPENGL_CORE: hw_context_type = 3;
 
+pub const HW_FRAME_BUFFER_VALID: *const c_void = usize::MAX as _;
+
 #[repr(C)]
 pub struct hw_render_callback {
     pub context_type: hw_context_type,
@@ -46,6 +75,37 @@ pub struct hw_render_callback {
     pub debug_context: bool,
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[repr(C)]
+pub struct input_descriptor {
+    pub port: c_uint,
+    pub device: c_uint,
+    pub index: c_uint,
+    pub id: c_uint,
+    pub description: *const c_char,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[repr(C)]
+pub struct variable {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[repr(C)]
+pub struct controller_description {
+    pub desc: *const c_char,
+    pub id: c_uint,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[repr(C)]
+pub struct controller_info {
+    pub types: *const controller_description,
+    pub num_types: c_uint,
+}
+
 // 5620
 pub type pixel_format = c_uint;
 pub const PIXEL_FORMAT_XRGB8888: pixel_format = 1;
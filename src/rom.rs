@@ -23,6 +23,14 @@ impl Rom {
             }
         }
     }
+
+    // tests run their own code rather than the game
+    #[cfg(test)]
+    pub fn unchecked(data: &[u8]) -> Self {
+        let mut new_data = Box::new([0; Self::DATA_LEN]);
+        new_data[..data.len()].copy_from_slice(data);
+        Self { data: new_data }
+    }
 }
 
 impl Deref for Rom {
@@ -0,0 +1,902 @@
+use crate::{apu::Apu, state::Serializer};
+
+pub struct Spc {
+    // cpu state
+    a: u8,
+    x: u8,
+    y: u8,
+    sp: u8,
+    pc: u16,
+    psw: u8,
+    stopped: bool,
+
+    // spc cycles taken by the current instruction
+    cycles: u32,
+    // master cycles owed to the spc, scaled by its clock rate
+    clock: i64,
+
+    // memory
+    apu: Apu,
+}
+
+impl Spc {
+    const CARRY: u8 = 0x01;
+    const ZERO: u8 = 0x02;
+    const INTERRUPT: u8 = 0x04;
+    const HALF_CARRY: u8 = 0x08;
+    const BREAK: u8 = 0x10;
+    const DIRECT_PAGE: u8 = 0x20;
+    const OVERFLOW: u8 = 0x40;
+    const NEGATIVE: u8 = 0x80;
+
+    const RESET_VECTOR: u16 = 0xfffe;
+    const TCALL_VECTOR: u16 = 0xffde;
+
+    // the spc runs at 24.576mhz / 24 against the 21.477mhz master clock
+    const MASTER_CLOCK: i64 = 21_477_272;
+    const CLOCK: i64 = 1_024_000;
+
+    // spc cycles per opcode, taken branches add 2 more
+    const CYCLES: [u8; 0x100] = [
+        2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 5, 4, 5, 4, 6, 8,
+        2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 6, 5, 2, 2, 4, 6,
+        2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 5, 4, 5, 4, 5, 4,
+        2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 6, 5, 2, 2, 3, 8,
+        2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 4, 4, 5, 4, 6, 6,
+        2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 4, 5, 2, 2, 4, 3,
+        2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 4, 4, 5, 4, 5, 5,
+        2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 5, 5, 2, 2, 3, 6,
+        2, 8, 4, 5, 3, 4, 3, 6, 2, 6, 5, 4, 5, 2, 4, 5,
+        2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 5, 5, 2, 2, 12, 5,
+        3, 8, 4, 5, 3, 4, 3, 6, 2, 6, 4, 4, 5, 2, 4, 4,
+        2, 8, 4, 5, 4, 5, 5, 6, 5, 5, 5, 5, 2, 2, 3, 4,
+        3, 8, 4, 5, 4, 5, 4, 7, 2, 5, 6, 4, 5, 2, 4, 9,
+        2, 8, 4, 5, 5, 6, 6, 7, 4, 5, 5, 5, 2, 2, 6, 3,
+        2, 8, 4, 5, 3, 4, 3, 6, 2, 4, 5, 3, 4, 3, 4, 3,
+        2, 8, 4, 5, 4, 5, 5, 6, 3, 4, 5, 4, 2, 2, 4, 3,
+    ];
+
+    pub fn new() -> Self {
+        let mut apu = Apu::new();
+        let pc = (apu.read(Self::RESET_VECTOR) as u16) | ((apu.read(Self::RESET_VECTOR + 1) as u16) << 8);
+        Self {
+            a: 0,
+            x: 0,
+            y: 0,
+            sp: 0xef,
+            pc,
+            psw: 0,
+            stopped: false,
+            cycles: 0,
+            clock: 0,
+            apu,
+        }
+    }
+
+    pub fn state(&mut self, s: &mut Serializer) {
+        s.section(*b"SPC ", |s| {
+            s.value(&mut self.a);
+            s.value(&mut self.x);
+            s.value(&mut self.y);
+            s.value(&mut self.sp);
+            s.value(&mut self.pc);
+            s.value(&mut self.psw);
+            s.value(&mut self.stopped);
+            s.value(&mut self.clock);
+        });
+        self.apu.state(s);
+    }
+
+    // catches the spc up with the master clock
+    pub fn run(&mut self, cycles: u32) {
+        self.clock += cycles as i64 * Self::CLOCK;
+        while self.clock > 0 {
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        // sleep and stop both halt until reset
+        if self.stopped {
+            self.cycles = 2
+        } else {
+            self.exec()
+        }
+        self.apu.tick(self.cycles);
+        self.clock -= self.cycles as i64 * Self::MASTER_CLOCK;
+    }
+
+    // the cpu side of the communication ports
+    #[inline]
+    pub const fn read_port(&self, index: usize) -> u8 {
+        self.apu.read_port(index)
+    }
+    #[inline]
+    pub const fn write_port(&mut self, index: usize, value: u8) {
+        self.apu.write_port(index, value)
+    }
+
+    // interleaved stereo samples from the dsp
+    #[inline]
+    pub fn samples(&self) -> &[i16] {
+        self.apu.samples()
+    }
+    #[inline]
+    pub fn clear_samples(&mut self) {
+        self.apu.clear_samples()
+    }
+
+    fn exec(&mut self) {
+        let opcode = self.fetch();
+        self.cycles = Self::CYCLES[opcode as usize] as u32;
+        match opcode {
+            0x00 => {},
+            0x01 => self.tcall(0),
+            0x02 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs | 0x01) },
+            0x03 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x01) != 0) },
+            0x04 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.a = self.or(self.a, rhs) },
+            0x05 => { let addr = self.addr_abs(); let rhs = self.read(addr); self.a = self.or(self.a, rhs) },
+            0x06 => { let addr = self.addr_x(); let rhs = self.read(addr); self.a = self.or(self.a, rhs) },
+            0x07 => { let addr = self.addr_dp_x_ind(); let rhs = self.read(addr); self.a = self.or(self.a, rhs) },
+            0x08 => { let rhs = self.fetch(); self.a = self.or(self.a, rhs) },
+            0x09 => { let src = self.addr_dp(); let rhs = self.read(src); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.or(lhs, rhs); self.write(addr, result) },
+            0x0a => { let (addr, bit) = self.addr_bit(); let rhs = self.read_bit(addr, bit); self.set_carry(self.carry() | rhs) },
+            0x0b => { let addr = self.addr_dp(); self.modify(addr, Self::asl) },
+            0x0c => { let addr = self.addr_abs(); self.modify(addr, Self::asl) },
+            0x0d => self.push(self.psw),
+            0x0e => { let addr = self.addr_abs(); let rhs = self.read(addr); self.set_zero_negative(self.a.wrapping_sub(rhs)); self.write(addr, rhs | self.a) },
+            0x0f => self.brk(),
+            0x10 => { let rel = self.fetch(); self.branch(rel, !self.negative()) },
+            0x11 => self.tcall(1),
+            0x12 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs & !0x01) },
+            0x13 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x01) == 0) },
+            0x14 => { let addr = self.addr_dp_x(); let rhs = self.read(addr); self.a = self.or(self.a, rhs) },
+            0x15 => { let addr = self.addr_abs_x(); let rhs = self.read(addr); self.a = self.or(self.a, rhs) },
+            0x16 => { let addr = self.addr_abs_y(); let rhs = self.read(addr); self.a = self.or(self.a, rhs) },
+            0x17 => { let addr = self.addr_dp_ind_y(); let rhs = self.read(addr); self.a = self.or(self.a, rhs) },
+            0x18 => { let rhs = self.fetch(); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.or(lhs, rhs); self.write(addr, result) },
+            0x19 => { let rhs = self.read(self.addr_y()); let addr = self.addr_x(); let lhs = self.read(addr); let result = self.or(lhs, rhs); self.write(addr, result) },
+            0x1a => { let addr = self.fetch(); let rhs = self.read_dp16(addr); let result = rhs.wrapping_sub(1); self.write_dp16(addr, result); self.set_zero_negative16(result) },
+            0x1b => { let addr = self.addr_dp_x(); self.modify(addr, Self::asl) },
+            0x1c => self.a = self.asl(self.a),
+            0x1d => self.x = self.dec(self.x),
+            0x1e => { let addr = self.addr_abs(); let rhs = self.read(addr); self.cmp(self.x, rhs) },
+            0x1f => { let addr = self.addr_abs_x(); self.pc = self.read16(addr) },
+            0x20 => self.set_direct_page(false),
+            0x21 => self.tcall(2),
+            0x22 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs | 0x02) },
+            0x23 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x02) != 0) },
+            0x24 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.a = self.and(self.a, rhs) },
+            0x25 => { let addr = self.addr_abs(); let rhs = self.read(addr); self.a = self.and(self.a, rhs) },
+            0x26 => { let addr = self.addr_x(); let rhs = self.read(addr); self.a = self.and(self.a, rhs) },
+            0x27 => { let addr = self.addr_dp_x_ind(); let rhs = self.read(addr); self.a = self.and(self.a, rhs) },
+            0x28 => { let rhs = self.fetch(); self.a = self.and(self.a, rhs) },
+            0x29 => { let src = self.addr_dp(); let rhs = self.read(src); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.and(lhs, rhs); self.write(addr, result) },
+            0x2a => { let (addr, bit) = self.addr_bit(); let rhs = self.read_bit(addr, bit); self.set_carry(self.carry() | !rhs) },
+            0x2b => { let addr = self.addr_dp(); self.modify(addr, Self::rol) },
+            0x2c => { let addr = self.addr_abs(); self.modify(addr, Self::rol) },
+            0x2d => self.push(self.a),
+            0x2e => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, self.a != rhs) },
+            0x2f => { let rel = self.fetch(); self.pc = self.pc.wrapping_add(rel as i8 as u16) },
+            0x30 => { let rel = self.fetch(); self.branch(rel, self.negative()) },
+            0x31 => self.tcall(3),
+            0x32 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs & !0x02) },
+            0x33 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x02) == 0) },
+            0x34 => { let addr = self.addr_dp_x(); let rhs = self.read(addr); self.a = self.and(self.a, rhs) },
+            0x35 => { let addr = self.addr_abs_x(); let rhs = self.read(addr); self.a = self.and(self.a, rhs) },
+            0x36 => { let addr = self.addr_abs_y(); let rhs = self.read(addr); self.a = self.and(self.a, rhs) },
+            0x37 => { let addr = self.addr_dp_ind_y(); let rhs = self.read(addr); self.a = self.and(self.a, rhs) },
+            0x38 => { let rhs = self.fetch(); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.and(lhs, rhs); self.write(addr, result) },
+            0x39 => { let rhs = self.read(self.addr_y()); let addr = self.addr_x(); let lhs = self.read(addr); let result = self.and(lhs, rhs); self.write(addr, result) },
+            0x3a => { let addr = self.fetch(); let rhs = self.read_dp16(addr); let result = rhs.wrapping_add(1); self.write_dp16(addr, result); self.set_zero_negative16(result) },
+            0x3b => { let addr = self.addr_dp_x(); self.modify(addr, Self::rol) },
+            0x3c => self.a = self.rol(self.a),
+            0x3d => self.x = self.inc(self.x),
+            0x3e => { let addr = self.addr_dp(); let rhs = self.read(addr); self.cmp(self.x, rhs) },
+            0x3f => { let addr = self.addr_abs(); self.call(addr) },
+            0x40 => self.set_direct_page(true),
+            0x41 => self.tcall(4),
+            0x42 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs | 0x04) },
+            0x43 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x04) != 0) },
+            0x44 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.a = self.eor(self.a, rhs) },
+            0x45 => { let addr = self.addr_abs(); let rhs = self.read(addr); self.a = self.eor(self.a, rhs) },
+            0x46 => { let addr = self.addr_x(); let rhs = self.read(addr); self.a = self.eor(self.a, rhs) },
+            0x47 => { let addr = self.addr_dp_x_ind(); let rhs = self.read(addr); self.a = self.eor(self.a, rhs) },
+            0x48 => { let rhs = self.fetch(); self.a = self.eor(self.a, rhs) },
+            0x49 => { let src = self.addr_dp(); let rhs = self.read(src); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.eor(lhs, rhs); self.write(addr, result) },
+            0x4a => { let (addr, bit) = self.addr_bit(); let rhs = self.read_bit(addr, bit); self.set_carry(self.carry() & rhs) },
+            0x4b => { let addr = self.addr_dp(); self.modify(addr, Self::lsr) },
+            0x4c => { let addr = self.addr_abs(); self.modify(addr, Self::lsr) },
+            0x4d => self.push(self.x),
+            0x4e => { let addr = self.addr_abs(); let rhs = self.read(addr); self.set_zero_negative(self.a.wrapping_sub(rhs)); self.write(addr, rhs & !self.a) },
+            0x4f => { let addr = self.fetch(); self.call(0xff00 | addr as u16) },
+            0x50 => { let rel = self.fetch(); self.branch(rel, !self.overflow()) },
+            0x51 => self.tcall(5),
+            0x52 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs & !0x04) },
+            0x53 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x04) == 0) },
+            0x54 => { let addr = self.addr_dp_x(); let rhs = self.read(addr); self.a = self.eor(self.a, rhs) },
+            0x55 => { let addr = self.addr_abs_x(); let rhs = self.read(addr); self.a = self.eor(self.a, rhs) },
+            0x56 => { let addr = self.addr_abs_y(); let rhs = self.read(addr); self.a = self.eor(self.a, rhs) },
+            0x57 => { let addr = self.addr_dp_ind_y(); let rhs = self.read(addr); self.a = self.eor(self.a, rhs) },
+            0x58 => { let rhs = self.fetch(); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.eor(lhs, rhs); self.write(addr, result) },
+            0x59 => { let rhs = self.read(self.addr_y()); let addr = self.addr_x(); let lhs = self.read(addr); let result = self.eor(lhs, rhs); self.write(addr, result) },
+            0x5a => { let addr = self.fetch(); let rhs = self.read_dp16(addr); self.cmpw(rhs) },
+            0x5b => { let addr = self.addr_dp_x(); self.modify(addr, Self::lsr) },
+            0x5c => self.a = self.lsr(self.a),
+            0x5d => { self.x = self.a; self.set_zero_negative(self.x) },
+            0x5e => { let addr = self.addr_abs(); let rhs = self.read(addr); self.cmp(self.y, rhs) },
+            0x5f => self.pc = self.addr_abs(),
+            0x60 => self.set_carry(false),
+            0x61 => self.tcall(6),
+            0x62 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs | 0x08) },
+            0x63 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x08) != 0) },
+            0x64 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.cmp(self.a, rhs) },
+            0x65 => { let addr = self.addr_abs(); let rhs = self.read(addr); self.cmp(self.a, rhs) },
+            0x66 => { let addr = self.addr_x(); let rhs = self.read(addr); self.cmp(self.a, rhs) },
+            0x67 => { let addr = self.addr_dp_x_ind(); let rhs = self.read(addr); self.cmp(self.a, rhs) },
+            0x68 => { let rhs = self.fetch(); self.cmp(self.a, rhs) },
+            0x69 => { let src = self.addr_dp(); let rhs = self.read(src); let addr = self.addr_dp(); let lhs = self.read(addr); self.cmp(lhs, rhs) },
+            0x6a => { let (addr, bit) = self.addr_bit(); let rhs = self.read_bit(addr, bit); self.set_carry(self.carry() & !rhs) },
+            0x6b => { let addr = self.addr_dp(); self.modify(addr, Self::ror) },
+            0x6c => { let addr = self.addr_abs(); self.modify(addr, Self::ror) },
+            0x6d => self.push(self.y),
+            0x6e => { let addr = self.addr_dp(); let result = self.read(addr).wrapping_sub(1); self.write(addr, result); let rel = self.fetch(); self.branch(rel, result != 0) },
+            0x6f => self.pc = self.pop16(),
+            0x70 => { let rel = self.fetch(); self.branch(rel, self.overflow()) },
+            0x71 => self.tcall(7),
+            0x72 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs & !0x08) },
+            0x73 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x08) == 0) },
+            0x74 => { let addr = self.addr_dp_x(); let rhs = self.read(addr); self.cmp(self.a, rhs) },
+            0x75 => { let addr = self.addr_abs_x(); let rhs = self.read(addr); self.cmp(self.a, rhs) },
+            0x76 => { let addr = self.addr_abs_y(); let rhs = self.read(addr); self.cmp(self.a, rhs) },
+            0x77 => { let addr = self.addr_dp_ind_y(); let rhs = self.read(addr); self.cmp(self.a, rhs) },
+            0x78 => { let rhs = self.fetch(); let addr = self.addr_dp(); let lhs = self.read(addr); self.cmp(lhs, rhs) },
+            0x79 => { let rhs = self.read(self.addr_y()); let addr = self.addr_x(); let lhs = self.read(addr); self.cmp(lhs, rhs) },
+            0x7a => { let addr = self.fetch(); let rhs = self.read_dp16(addr); self.addw(rhs) },
+            0x7b => { let addr = self.addr_dp_x(); self.modify(addr, Self::ror) },
+            0x7c => self.a = self.ror(self.a),
+            0x7d => { self.a = self.x; self.set_zero_negative(self.a) },
+            0x7e => { let addr = self.addr_dp(); let rhs = self.read(addr); self.cmp(self.y, rhs) },
+            0x7f => { self.psw = self.pop(); self.pc = self.pop16() },
+            0x80 => self.set_carry(true),
+            0x81 => self.tcall(8),
+            0x82 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs | 0x10) },
+            0x83 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x10) != 0) },
+            0x84 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.a = self.adc(self.a, rhs) },
+            0x85 => { let addr = self.addr_abs(); let rhs = self.read(addr); self.a = self.adc(self.a, rhs) },
+            0x86 => { let addr = self.addr_x(); let rhs = self.read(addr); self.a = self.adc(self.a, rhs) },
+            0x87 => { let addr = self.addr_dp_x_ind(); let rhs = self.read(addr); self.a = self.adc(self.a, rhs) },
+            0x88 => { let rhs = self.fetch(); self.a = self.adc(self.a, rhs) },
+            0x89 => { let src = self.addr_dp(); let rhs = self.read(src); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.adc(lhs, rhs); self.write(addr, result) },
+            0x8a => { let (addr, bit) = self.addr_bit(); let rhs = self.read_bit(addr, bit); self.set_carry(self.carry() != rhs) },
+            0x8b => { let addr = self.addr_dp(); self.modify(addr, Self::dec) },
+            0x8c => { let addr = self.addr_abs(); self.modify(addr, Self::dec) },
+            0x8d => { self.y = self.fetch(); self.set_zero_negative(self.y) },
+            0x8e => self.psw = self.pop(),
+            0x8f => { let rhs = self.fetch(); let addr = self.addr_dp(); self.write(addr, rhs) },
+            0x90 => { let rel = self.fetch(); self.branch(rel, !self.carry()) },
+            0x91 => self.tcall(9),
+            0x92 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs & !0x10) },
+            0x93 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x10) == 0) },
+            0x94 => { let addr = self.addr_dp_x(); let rhs = self.read(addr); self.a = self.adc(self.a, rhs) },
+            0x95 => { let addr = self.addr_abs_x(); let rhs = self.read(addr); self.a = self.adc(self.a, rhs) },
+            0x96 => { let addr = self.addr_abs_y(); let rhs = self.read(addr); self.a = self.adc(self.a, rhs) },
+            0x97 => { let addr = self.addr_dp_ind_y(); let rhs = self.read(addr); self.a = self.adc(self.a, rhs) },
+            0x98 => { let rhs = self.fetch(); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.adc(lhs, rhs); self.write(addr, result) },
+            0x99 => { let rhs = self.read(self.addr_y()); let addr = self.addr_x(); let lhs = self.read(addr); let result = self.adc(lhs, rhs); self.write(addr, result) },
+            0x9a => { let addr = self.fetch(); let rhs = self.read_dp16(addr); self.subw(rhs) },
+            0x9b => { let addr = self.addr_dp_x(); self.modify(addr, Self::dec) },
+            0x9c => self.a = self.dec(self.a),
+            0x9d => { self.x = self.sp; self.set_zero_negative(self.x) },
+            0x9e => self.div(),
+            0x9f => { self.a = self.a.rotate_left(4); self.set_zero_negative(self.a) },
+            0xa0 => self.psw |= Self::INTERRUPT,
+            0xa1 => self.tcall(10),
+            0xa2 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs | 0x20) },
+            0xa3 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x20) != 0) },
+            0xa4 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.a = self.sbc(self.a, rhs) },
+            0xa5 => { let addr = self.addr_abs(); let rhs = self.read(addr); self.a = self.sbc(self.a, rhs) },
+            0xa6 => { let addr = self.addr_x(); let rhs = self.read(addr); self.a = self.sbc(self.a, rhs) },
+            0xa7 => { let addr = self.addr_dp_x_ind(); let rhs = self.read(addr); self.a = self.sbc(self.a, rhs) },
+            0xa8 => { let rhs = self.fetch(); self.a = self.sbc(self.a, rhs) },
+            0xa9 => { let src = self.addr_dp(); let rhs = self.read(src); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.sbc(lhs, rhs); self.write(addr, result) },
+            0xaa => { let (addr, bit) = self.addr_bit(); let rhs = self.read_bit(addr, bit); self.set_carry(rhs) },
+            0xab => { let addr = self.addr_dp(); self.modify(addr, Self::inc) },
+            0xac => { let addr = self.addr_abs(); self.modify(addr, Self::inc) },
+            0xad => { let rhs = self.fetch(); self.cmp(self.y, rhs) },
+            0xae => self.a = self.pop(),
+            0xaf => { let addr = self.addr_x(); self.write(addr, self.a); self.x = self.x.wrapping_add(1) },
+            0xb0 => { let rel = self.fetch(); self.branch(rel, self.carry()) },
+            0xb1 => self.tcall(11),
+            0xb2 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs & !0x20) },
+            0xb3 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x20) == 0) },
+            0xb4 => { let addr = self.addr_dp_x(); let rhs = self.read(addr); self.a = self.sbc(self.a, rhs) },
+            0xb5 => { let addr = self.addr_abs_x(); let rhs = self.read(addr); self.a = self.sbc(self.a, rhs) },
+            0xb6 => { let addr = self.addr_abs_y(); let rhs = self.read(addr); self.a = self.sbc(self.a, rhs) },
+            0xb7 => { let addr = self.addr_dp_ind_y(); let rhs = self.read(addr); self.a = self.sbc(self.a, rhs) },
+            0xb8 => { let rhs = self.fetch(); let addr = self.addr_dp(); let lhs = self.read(addr); let result = self.sbc(lhs, rhs); self.write(addr, result) },
+            0xb9 => { let rhs = self.read(self.addr_y()); let addr = self.addr_x(); let lhs = self.read(addr); let result = self.sbc(lhs, rhs); self.write(addr, result) },
+            0xba => { let addr = self.fetch(); let rhs = self.read_dp16(addr); self.set_ya(rhs); self.set_zero_negative16(rhs) },
+            0xbb => { let addr = self.addr_dp_x(); self.modify(addr, Self::inc) },
+            0xbc => self.a = self.inc(self.a),
+            0xbd => self.sp = self.x,
+            0xbe => self.das(),
+            0xbf => { let addr = self.addr_x(); self.a = self.read(addr); self.x = self.x.wrapping_add(1); self.set_zero_negative(self.a) },
+            0xc0 => self.psw &= !Self::INTERRUPT,
+            0xc1 => self.tcall(12),
+            0xc2 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs | 0x40) },
+            0xc3 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x40) != 0) },
+            0xc4 => { let addr = self.addr_dp(); self.write(addr, self.a) },
+            0xc5 => { let addr = self.addr_abs(); self.write(addr, self.a) },
+            0xc6 => { let addr = self.addr_x(); self.write(addr, self.a) },
+            0xc7 => { let addr = self.addr_dp_x_ind(); self.write(addr, self.a) },
+            0xc8 => { let rhs = self.fetch(); self.cmp(self.x, rhs) },
+            0xc9 => { let addr = self.addr_abs(); self.write(addr, self.x) },
+            0xca => { let (addr, bit) = self.addr_bit(); let rhs = self.read(addr); self.write(addr, (rhs & !(1 << bit)) | ((self.carry() as u8) << bit)) },
+            0xcb => { let addr = self.addr_dp(); self.write(addr, self.y) },
+            0xcc => { let addr = self.addr_abs(); self.write(addr, self.y) },
+            0xcd => { self.x = self.fetch(); self.set_zero_negative(self.x) },
+            0xce => self.x = self.pop(),
+            0xcf => { let result = self.y as u16 * self.a as u16; self.set_ya(result); self.set_zero_negative(self.y) },
+            0xd0 => { let rel = self.fetch(); self.branch(rel, !self.zero()) },
+            0xd1 => self.tcall(13),
+            0xd2 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs & !0x40) },
+            0xd3 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x40) == 0) },
+            0xd4 => { let addr = self.addr_dp_x(); self.write(addr, self.a) },
+            0xd5 => { let addr = self.addr_abs_x(); self.write(addr, self.a) },
+            0xd6 => { let addr = self.addr_abs_y(); self.write(addr, self.a) },
+            0xd7 => { let addr = self.addr_dp_ind_y(); self.write(addr, self.a) },
+            0xd8 => { let addr = self.addr_dp(); self.write(addr, self.x) },
+            0xd9 => { let addr = self.addr_dp_y(); self.write(addr, self.x) },
+            0xda => { let addr = self.fetch(); self.write_dp16(addr, self.ya()) },
+            0xdb => { let addr = self.addr_dp_x(); self.write(addr, self.y) },
+            0xdc => self.y = self.dec(self.y),
+            0xdd => { self.a = self.y; self.set_zero_negative(self.a) },
+            0xde => { let addr = self.addr_dp_x(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, self.a != rhs) },
+            0xdf => self.daa(),
+            0xe0 => self.psw &= !(Self::OVERFLOW | Self::HALF_CARRY),
+            0xe1 => self.tcall(14),
+            0xe2 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs | 0x80) },
+            0xe3 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x80) != 0) },
+            0xe4 => { let addr = self.addr_dp(); self.a = self.read(addr); self.set_zero_negative(self.a) },
+            0xe5 => { let addr = self.addr_abs(); self.a = self.read(addr); self.set_zero_negative(self.a) },
+            0xe6 => { let addr = self.addr_x(); self.a = self.read(addr); self.set_zero_negative(self.a) },
+            0xe7 => { let addr = self.addr_dp_x_ind(); self.a = self.read(addr); self.set_zero_negative(self.a) },
+            0xe8 => { self.a = self.fetch(); self.set_zero_negative(self.a) },
+            0xe9 => { let addr = self.addr_abs(); self.x = self.read(addr); self.set_zero_negative(self.x) },
+            0xea => { let (addr, bit) = self.addr_bit(); let rhs = self.read(addr); self.write(addr, rhs ^ (1 << bit)) },
+            0xeb => { let addr = self.addr_dp(); self.y = self.read(addr); self.set_zero_negative(self.y) },
+            0xec => { let addr = self.addr_abs(); self.y = self.read(addr); self.set_zero_negative(self.y) },
+            0xed => self.psw ^= Self::CARRY,
+            0xee => self.y = self.pop(),
+            0xef => self.stopped = true,
+            0xf0 => { let rel = self.fetch(); self.branch(rel, self.zero()) },
+            0xf1 => self.tcall(15),
+            0xf2 => { let addr = self.addr_dp(); let rhs = self.read(addr); self.write(addr, rhs & !0x80) },
+            0xf3 => { let addr = self.addr_dp(); let rhs = self.read(addr); let rel = self.fetch(); self.branch(rel, (rhs & 0x80) == 0) },
+            0xf4 => { let addr = self.addr_dp_x(); self.a = self.read(addr); self.set_zero_negative(self.a) },
+            0xf5 => { let addr = self.addr_abs_x(); self.a = self.read(addr); self.set_zero_negative(self.a) },
+            0xf6 => { let addr = self.addr_abs_y(); self.a = self.read(addr); self.set_zero_negative(self.a) },
+            0xf7 => { let addr = self.addr_dp_ind_y(); self.a = self.read(addr); self.set_zero_negative(self.a) },
+            0xf8 => { let addr = self.addr_dp(); self.x = self.read(addr); self.set_zero_negative(self.x) },
+            0xf9 => { let addr = self.addr_dp_y(); self.x = self.read(addr); self.set_zero_negative(self.x) },
+            0xfa => { let src = self.addr_dp(); let rhs = self.read(src); let addr = self.addr_dp(); self.write(addr, rhs) },
+            0xfb => { let addr = self.addr_dp_x(); self.y = self.read(addr); self.set_zero_negative(self.y) },
+            0xfc => self.y = self.inc(self.y),
+            0xfd => { self.y = self.a; self.set_zero_negative(self.y) },
+            0xfe => { self.y = self.y.wrapping_sub(1); let rel = self.fetch(); self.branch(rel, self.y != 0) },
+            0xff => self.stopped = true,
+        }
+    }
+
+    // flags
+    #[inline]
+    const fn carry(&self) -> bool {
+        (self.psw & Self::CARRY) != 0
+    }
+    #[inline]
+    const fn set_carry(&mut self, carry: bool) {
+        if carry {
+            self.psw |= Self::CARRY
+        } else {
+            self.psw &= !Self::CARRY
+        }
+    }
+    #[inline]
+    const fn zero(&self) -> bool {
+        (self.psw & Self::ZERO) != 0
+    }
+    #[inline]
+    const fn set_zero(&mut self, zero: bool) {
+        if zero {
+            self.psw |= Self::ZERO
+        } else {
+            self.psw &= !Self::ZERO
+        }
+    }
+    #[inline]
+    const fn half_carry(&self) -> bool {
+        (self.psw & Self::HALF_CARRY) != 0
+    }
+    #[inline]
+    const fn set_half_carry(&mut self, half_carry: bool) {
+        if half_carry {
+            self.psw |= Self::HALF_CARRY
+        } else {
+            self.psw &= !Self::HALF_CARRY
+        }
+    }
+    #[inline]
+    const fn direct_page(&self) -> bool {
+        (self.psw & Self::DIRECT_PAGE) != 0
+    }
+    #[inline]
+    const fn set_direct_page(&mut self, direct_page: bool) {
+        if direct_page {
+            self.psw |= Self::DIRECT_PAGE
+        } else {
+            self.psw &= !Self::DIRECT_PAGE
+        }
+    }
+    #[inline]
+    const fn overflow(&self) -> bool {
+        (self.psw & Self::OVERFLOW) != 0
+    }
+    #[inline]
+    const fn set_overflow(&mut self, overflow: bool) {
+        if overflow {
+            self.psw |= Self::OVERFLOW
+        } else {
+            self.psw &= !Self::OVERFLOW
+        }
+    }
+    #[inline]
+    const fn negative(&self) -> bool {
+        (self.psw & Self::NEGATIVE) != 0
+    }
+    #[inline]
+    const fn set_negative(&mut self, negative: bool) {
+        if negative {
+            self.psw |= Self::NEGATIVE
+        } else {
+            self.psw &= !Self::NEGATIVE
+        }
+    }
+
+    #[inline]
+    const fn set_zero_negative(&mut self, value: u8) {
+        self.set_zero(value == 0);
+        self.set_negative((value as i8) < 0);
+    }
+
+    #[inline]
+    const fn set_zero_negative16(&mut self, value: u16) {
+        self.set_zero(value == 0);
+        self.set_negative((value as i16) < 0);
+    }
+
+    #[inline]
+    const fn ya(&self) -> u16 {
+        ((self.y as u16) << 8) | self.a as u16
+    }
+    #[inline]
+    const fn set_ya(&mut self, value: u16) {
+        self.a = value as u8;
+        self.y = (value >> 8) as u8;
+    }
+
+    // memory
+    #[inline]
+    fn read(&mut self, addr: u16) -> u8 {
+        self.apu.read(addr)
+    }
+    #[inline]
+    fn write(&mut self, addr: u16, value: u8) {
+        self.apu.write(addr, value)
+    }
+    #[inline]
+    fn read16(&mut self, addr: u16) -> u16 {
+        let lo = self.read(addr);
+        let hi = self.read(addr.wrapping_add(1));
+        ((hi as u16) << 8) | lo as u16
+    }
+    #[inline]
+    fn fetch(&mut self) -> u8 {
+        let value = self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+    #[inline]
+    fn fetch16(&mut self) -> u16 {
+        let lo = self.fetch();
+        let hi = self.fetch();
+        ((hi as u16) << 8) | lo as u16
+    }
+
+    // words in the direct page wrap within it
+    #[inline]
+    fn read_dp16(&mut self, addr: u8) -> u16 {
+        let lo = self.read(self.dp(addr));
+        let hi = self.read(self.dp(addr.wrapping_add(1)));
+        ((hi as u16) << 8) | lo as u16
+    }
+    #[inline]
+    fn write_dp16(&mut self, addr: u8, value: u16) {
+        self.write(self.dp(addr), value as u8);
+        self.write(self.dp(addr.wrapping_add(1)), (value >> 8) as u8);
+    }
+
+    #[inline]
+    fn modify(&mut self, addr: u16, op: fn(&mut Self, u8) -> u8) {
+        let rhs = self.read(addr);
+        let result = op(self, rhs);
+        self.write(addr, result)
+    }
+
+    // stack
+    #[inline]
+    fn push(&mut self, value: u8) {
+        self.write(0x100 | self.sp as u16, value);
+        self.sp = self.sp.wrapping_sub(1);
+    }
+    #[inline]
+    fn pop(&mut self) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        self.read(0x100 | self.sp as u16)
+    }
+    #[inline]
+    fn push16(&mut self, value: u16) {
+        self.push((value >> 8) as u8);
+        self.push(value as u8);
+    }
+    #[inline]
+    fn pop16(&mut self) -> u16 {
+        let lo = self.pop();
+        let hi = self.pop();
+        ((hi as u16) << 8) | lo as u16
+    }
+
+    // addressing modes
+    #[inline]
+    const fn dp(&self, addr: u8) -> u16 {
+        ((self.direct_page() as u16) << 8) | addr as u16
+    }
+    #[inline]
+    fn addr_dp(&mut self) -> u16 {
+        let addr = self.fetch();
+        self.dp(addr)
+    }
+    #[inline]
+    fn addr_dp_x(&mut self) -> u16 {
+        let addr = self.fetch();
+        self.dp(addr.wrapping_add(self.x))
+    }
+    #[inline]
+    fn addr_dp_y(&mut self) -> u16 {
+        let addr = self.fetch();
+        self.dp(addr.wrapping_add(self.y))
+    }
+    #[inline]
+    const fn addr_x(&self) -> u16 {
+        self.dp(self.x)
+    }
+    #[inline]
+    const fn addr_y(&self) -> u16 {
+        self.dp(self.y)
+    }
+    #[inline]
+    fn addr_abs(&mut self) -> u16 {
+        self.fetch16()
+    }
+    #[inline]
+    fn addr_abs_x(&mut self) -> u16 {
+        self.fetch16().wrapping_add(self.x as u16)
+    }
+    #[inline]
+    fn addr_abs_y(&mut self) -> u16 {
+        self.fetch16().wrapping_add(self.y as u16)
+    }
+    #[inline]
+    fn addr_dp_x_ind(&mut self) -> u16 {
+        let addr = self.fetch();
+        self.read_dp16(addr.wrapping_add(self.x))
+    }
+    #[inline]
+    fn addr_dp_ind_y(&mut self) -> u16 {
+        let addr = self.fetch();
+        self.read_dp16(addr).wrapping_add(self.y as u16)
+    }
+    // a 13-bit address with the bit number in the top 3 bits
+    #[inline]
+    fn addr_bit(&mut self) -> (u16, u8) {
+        let operand = self.fetch16();
+        (operand & 0x1fff, (operand >> 13) as u8)
+    }
+    #[inline]
+    fn read_bit(&mut self, addr: u16, bit: u8) -> bool {
+        (self.read(addr) & (1 << bit)) != 0
+    }
+
+    // control flow
+    #[inline]
+    const fn branch(&mut self, rel: u8, cond: bool) {
+        if cond {
+            self.cycles += 2;
+            self.pc = self.pc.wrapping_add(rel as i8 as u16);
+        }
+    }
+    #[inline]
+    fn call(&mut self, addr: u16) {
+        self.push16(self.pc);
+        self.pc = addr;
+    }
+    #[inline]
+    fn tcall(&mut self, index: u16) {
+        let addr = self.read16(Self::TCALL_VECTOR - (index << 1));
+        self.call(addr)
+    }
+    fn brk(&mut self) {
+        self.push16(self.pc);
+        self.push(self.psw);
+        self.psw = (self.psw | Self::BREAK) & !Self::INTERRUPT;
+        self.pc = self.read16(Self::TCALL_VECTOR);
+    }
+
+    // alu
+    #[inline]
+    const fn or(&mut self, lhs: u8, rhs: u8) -> u8 {
+        let result = lhs | rhs;
+        self.set_zero_negative(result);
+        result
+    }
+    #[inline]
+    const fn and(&mut self, lhs: u8, rhs: u8) -> u8 {
+        let result = lhs & rhs;
+        self.set_zero_negative(result);
+        result
+    }
+    #[inline]
+    const fn eor(&mut self, lhs: u8, rhs: u8) -> u8 {
+        let result = lhs ^ rhs;
+        self.set_zero_negative(result);
+        result
+    }
+    #[inline]
+    const fn cmp(&mut self, lhs: u8, rhs: u8) {
+        self.set_carry(lhs >= rhs);
+        self.set_zero_negative(lhs.wrapping_sub(rhs));
+    }
+    #[inline]
+    const fn adc(&mut self, lhs: u8, rhs: u8) -> u8 {
+        let result = lhs as u16 + rhs as u16 + self.carry() as u16;
+        self.set_overflow((!(lhs ^ rhs) & (lhs ^ result as u8) & 0x80) != 0);
+        self.set_half_carry(((lhs ^ rhs ^ result as u8) & 0x10) != 0);
+        self.set_carry(result > 0xff);
+        self.set_zero_negative(result as u8);
+        result as u8
+    }
+    #[inline]
+    const fn sbc(&mut self, lhs: u8, rhs: u8) -> u8 {
+        self.adc(lhs, !rhs)
+    }
+
+    #[inline]
+    const fn asl(&mut self, rhs: u8) -> u8 {
+        self.set_carry((rhs & 0x80) != 0);
+        let result = rhs << 1;
+        self.set_zero_negative(result);
+        result
+    }
+    #[inline]
+    const fn lsr(&mut self, rhs: u8) -> u8 {
+        self.set_carry((rhs & 0x01) != 0);
+        let result = rhs >> 1;
+        self.set_zero_negative(result);
+        result
+    }
+    #[inline]
+    const fn rol(&mut self, rhs: u8) -> u8 {
+        let result = (rhs << 1) | self.carry() as u8;
+        self.set_carry((rhs & 0x80) != 0);
+        self.set_zero_negative(result);
+        result
+    }
+    #[inline]
+    const fn ror(&mut self, rhs: u8) -> u8 {
+        let result = (rhs >> 1) | ((self.carry() as u8) << 7);
+        self.set_carry((rhs & 0x01) != 0);
+        self.set_zero_negative(result);
+        result
+    }
+    #[inline]
+    const fn inc(&mut self, rhs: u8) -> u8 {
+        let result = rhs.wrapping_add(1);
+        self.set_zero_negative(result);
+        result
+    }
+    #[inline]
+    const fn dec(&mut self, rhs: u8) -> u8 {
+        let result = rhs.wrapping_sub(1);
+        self.set_zero_negative(result);
+        result
+    }
+
+    // 16-bit ops run through the 8-bit adder twice, so h and v come from the high byte
+    #[inline]
+    const fn addw(&mut self, rhs: u16) {
+        self.set_carry(false);
+        let lo = self.adc(self.a, rhs as u8);
+        let hi = self.adc(self.y, (rhs >> 8) as u8);
+        let result = ((hi as u16) << 8) | lo as u16;
+        self.set_ya(result);
+        self.set_zero_negative16(result);
+    }
+    #[inline]
+    const fn subw(&mut self, rhs: u16) {
+        self.set_carry(true);
+        let lo = self.sbc(self.a, rhs as u8);
+        let hi = self.sbc(self.y, (rhs >> 8) as u8);
+        let result = ((hi as u16) << 8) | lo as u16;
+        self.set_ya(result);
+        self.set_zero_negative16(result);
+    }
+    #[inline]
+    const fn cmpw(&mut self, rhs: u16) {
+        let ya = self.ya();
+        self.set_carry(ya >= rhs);
+        self.set_zero_negative16(ya.wrapping_sub(rhs));
+    }
+
+    const fn div(&mut self) {
+        let ya = self.ya() as u32;
+        let x = self.x as u32;
+        self.set_overflow(self.y as u32 >= x);
+        self.set_half_carry((self.y & 0x0f) >= (self.x & 0x0f));
+        if (self.y as u32) < (x << 1) {
+            self.a = (ya / x) as u8;
+            self.y = (ya % x) as u8;
+        } else {
+            // the quotient overflows 9 bits, the hardware gives these results
+            self.a = (255 - (ya - (x << 9)) / (256 - x)) as u8;
+            self.y = (x + (ya - (x << 9)) % (256 - x)) as u8;
+        }
+        self.set_zero_negative(self.a);
+    }
+
+    const fn daa(&mut self) {
+        if self.carry() || self.a > 0x99 {
+            self.a = self.a.wrapping_add(0x60);
+            self.set_carry(true);
+        }
+        if self.half_carry() || (self.a & 0x0f) > 0x09 {
+            self.a = self.a.wrapping_add(0x06);
+        }
+        self.set_zero_negative(self.a);
+    }
+
+    const fn das(&mut self) {
+        if !self.carry() || self.a > 0x99 {
+            self.a = self.a.wrapping_sub(0x60);
+            self.set_carry(false);
+        }
+        if !self.half_carry() || (self.a & 0x0f) > 0x09 {
+            self.a = self.a.wrapping_sub(0x06);
+        }
+        self.set_zero_negative(self.a);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // runs code from $0200 until it hits stp
+    fn run(code: &[u8]) -> Spc {
+        let mut spc = Spc::new();
+        for (addr, &value) in (0x0200..).zip(code) {
+            spc.apu.write(addr, value);
+        }
+        spc.pc = 0x0200;
+        for _ in 0..1000 {
+            if spc.stopped {
+                return spc
+            }
+            spc.step();
+        }
+        panic!("still running at {:04x}", spc.pc)
+    }
+
+    // runs until the spc writes a value to one of the cpu's ports, checking every few instructions
+    fn wait_port(spc: &mut Spc, index: usize, value: u8) {
+        for _ in 0..10000 {
+            if spc.read_port(index) == value {
+                return
+            }
+            spc.run(64);
+        }
+        panic!("port {index} is {:02x} at {:04x}, expected {value:02x}", spc.read_port(index), spc.pc)
+    }
+
+    #[test]
+    fn ipl_upload_and_timer() {
+        let mut spc = Spc::new();
+        wait_port(&mut spc, 0, 0xaa);
+        wait_port(&mut spc, 1, 0xbb);
+
+        // mov a,#$5a; mov $f4,a; mov $fa,#$10; mov $f1,#$81; bra *
+        let code = [0xe8, 0x5a, 0xc4, 0xf4, 0x8f, 0x10, 0xfa, 0x8f, 0x81, 0xf1, 0x2f, 0xfe];
+
+        // the cpu sends the destination and $cc, then each byte with an incrementing index
+        spc.write_port(2, 0x00);
+        spc.write_port(3, 0x02);
+        spc.write_port(1, 0x01);
+        spc.write_port(0, 0xcc);
+        wait_port(&mut spc, 0, 0xcc);
+        for (index, &value) in code.iter().enumerate() {
+            spc.write_port(1, value);
+            spc.write_port(0, index as u8);
+            wait_port(&mut spc, 0, index as u8);
+        }
+        // a zero in port 1 jumps to the address in ports 2-3
+        spc.write_port(2, 0x00);
+        spc.write_port(3, 0x02);
+        spc.write_port(1, 0x00);
+        spc.write_port(0, code.len() as u8 + 1);
+        wait_port(&mut spc, 0, 0x5a);
+
+        // timer 0 counts 8khz ticks up to 16, so 5 times in 10ms
+        spc.run(Spc::MASTER_CLOCK as u32 / 100);
+        assert!((4..=5).contains(&spc.apu.read(0xfd)));
+        // and reading it cleared it
+        assert_eq!(spc.apu.read(0xfd), 0);
+    }
+
+    #[test]
+    fn multiply_and_divide() {
+        let spc = run(&[
+            0xe8, 0x34, 0x8d, 0x12, 0xcf, 0xda, 0x00, // mov a,#$34; mov y,#$12; mul ya; movw $00,ya
+            0xe8, 0x64, 0x8d, 0x00, 0xcd, 0x07, 0x9e, 0xc4, 0x02, 0xcb, 0x03, // ya = 100; mov x,#7; div ya,x; mov $02,a; mov $03,y
+            0xe8, 0x34, 0x8d, 0x12, 0xcd, 0x10, 0x9e, 0xc4, 0x04, 0xcb, 0x05, // ya = $1234; mov x,#$10; div ya,x; mov $04,a; mov $05,y
+            0xff, // stp
+        ]);
+        let mut apu = spc.apu;
+        assert_eq!((apu.read(0x00), apu.read(0x01)), (0xa8, 0x03));
+        assert_eq!((apu.read(0x02), apu.read(0x03)), (14, 2));
+        // a quotient over 8 bits keeps its low byte and sets overflow
+        assert_eq!((apu.read(0x04), apu.read(0x05)), (0x23, 0x04));
+        assert_ne!(spc.psw & Spc::OVERFLOW, 0);
+    }
+
+    #[test]
+    fn decimal_adjust() {
+        let spc = run(&[
+            0x60, 0xe8, 0x19, 0x88, 0x28, 0xdf, 0xc4, 0x00, // clrc; mov a,#$19; adc a,#$28; daa a; mov $00,a
+            0x80, 0xe8, 0x47, 0xa8, 0x28, 0xbe, 0xc4, 0x01, // setc; mov a,#$47; sbc a,#$28; das a; mov $01,a
+            0x60, 0xe8, 0x99, 0x88, 0x01, 0xdf, 0xc4, 0x02, // clrc; mov a,#$99; adc a,#$01; daa a; mov $02,a
+            0xff, // stp
+        ]);
+        let mut apu = spc.apu;
+        assert_eq!(apu.read(0x00), 0x47);
+        assert_eq!(apu.read(0x01), 0x19);
+        // 99 + 1 wraps to 00 with carry
+        assert_eq!(apu.read(0x02), 0x00);
+        assert_ne!(spc.psw & Spc::CARRY, 0);
+    }
+
+    #[test]
+    fn calls_and_branches() {
+        let mut code = vec![
+            0x3f, 0x00, 0x03, 0xc4, 0x00, // call $0300; mov $00,a
+            0xcd, 0x05, 0xab, 0x01, 0x1d, 0xd0, 0xfb, // mov x,#5; inc $01; dec x; bne -5
+            0xff, // stp
+        ];
+        code.resize(0x100, 0x00);
+        // mov a,#$77; ret
+        code.extend([0xe8, 0x77, 0x6f]);
+        let spc = run(&code);
+        let mut apu = spc.apu;
+        assert_eq!(apu.read(0x00), 0x77);
+        assert_eq!(apu.read(0x01), 5);
+        assert_eq!(spc.sp, 0xef);
+    }
+}
@@ -0,0 +1,278 @@
+// savestates: a header followed by tagged sections, each one owned by a component
+//
+// sections are length prefixed, so unknown tags are skipped and missing ones leave the component as it is.
+// fields are only ever appended to a section, a short section loads its leading fields and keeps the rest.
+// loaded values go straight into the components, which check the ones that would panic if out of range.
+
+pub struct Serializer<'a> {
+    mode: Mode<'a>,
+    // cleared when a loaded value is out of range
+    valid: bool,
+}
+
+enum Mode<'a> {
+    Save(Vec<u8>),
+    Load {
+        sections: Vec<([u8; 4], &'a [u8])>,
+        // what's left of the section being loaded
+        data: &'a [u8],
+    },
+}
+
+impl<'a> Serializer<'a> {
+    const MAGIC: [u8; 4] = *b"SMWS";
+    // only bumped when old states can't be loaded at all
+    const VERSION: u16 = 1;
+
+    pub fn save() -> Self {
+        let mut data = Vec::new();
+        data.extend(Self::MAGIC);
+        data.extend(Self::VERSION.to_le_bytes());
+        Self { mode: Mode::Save(data), valid: true }
+    }
+
+    // splits a state into its sections, or returns none if the header doesn't match
+    pub fn load(data: &'a [u8]) -> Option<Self> {
+        let (magic, data) = data.split_first_chunk::<4>()?;
+        let (version, mut data) = data.split_first_chunk::<2>()?;
+        if *magic != Self::MAGIC || u16::from_le_bytes(*version) != Self::VERSION {
+            return None
+        }
+
+        let mut sections = Vec::new();
+        while !data.is_empty() {
+            let (tag, rest) = data.split_first_chunk::<4>()?;
+            let (len, rest) = rest.split_first_chunk::<4>()?;
+            let len = u32::from_le_bytes(*len) as usize;
+            if rest.len() < len {
+                return None
+            }
+            let (section, rest) = rest.split_at(len);
+            sections.push((*tag, section));
+            data = rest;
+        }
+        Some(Self { mode: Mode::Load { sections, data: &[] }, valid: true })
+    }
+
+    // the saved state
+    pub fn finish(self) -> Vec<u8> {
+        match self.mode {
+            Mode::Save(data) => data,
+            Mode::Load { .. } => Vec::new(),
+        }
+    }
+
+    pub fn section(&mut self, tag: [u8; 4], f: impl FnOnce(&mut Self)) {
+        match &mut self.mode {
+            Mode::Save(data) => {
+                data.extend(tag);
+                let start = data.len();
+                data.extend([0; 4]);
+                f(self);
+                let Mode::Save(data) = &mut self.mode else { unreachable!() };
+                let len = (data.len() - start - 4) as u32;
+                data[start..start + 4].copy_from_slice(&len.to_le_bytes());
+            },
+            Mode::Load { sections, data } => {
+                if let Some(&(_, section)) = sections.iter().find(|(section_tag, _)| *section_tag == tag) {
+                    *data = section;
+                    f(self);
+                }
+            },
+        }
+    }
+
+    // a record that always takes len bytes, padded with zeros, so its size doesn't depend on what's in it
+    pub fn fixed(&mut self, len: usize, f: impl FnOnce(&mut Self)) {
+        match &mut self.mode {
+            Mode::Save(data) => {
+                let end = data.len() + len;
+                f(self);
+                let Mode::Save(data) = &mut self.mode else { unreachable!() };
+                assert!(data.len() <= end, "record longer than {len} bytes");
+                data.resize(end, 0);
+            },
+            Mode::Load { data, .. } => {
+                let (record, rest) = data.split_at(len.min(data.len()));
+                *data = record;
+                f(self);
+                let Mode::Load { data, .. } = &mut self.mode else { unreachable!() };
+                *data = rest;
+            },
+        }
+    }
+
+    #[inline]
+    pub fn value<T: Value + ?Sized>(&mut self, value: &mut T) {
+        value.serialize(self)
+    }
+
+    // marks the state as invalid if a value can't be used as it is
+    #[inline]
+    pub const fn check(&mut self, valid: bool) {
+        self.valid &= valid
+    }
+
+    #[inline]
+    pub const fn valid(&self) -> bool {
+        self.valid
+    }
+
+    fn bytes(&mut self, bytes: &mut [u8]) {
+        match &mut self.mode {
+            Mode::Save(data) => data.extend_from_slice(bytes),
+            Mode::Load { data, .. } => {
+                // past the end of an older section, the value is kept
+                if let Some((value, rest)) = data.split_at_checked(bytes.len()) {
+                    bytes.copy_from_slice(value);
+                    *data = rest;
+                } else {
+                    *data = &[];
+                }
+            },
+        }
+    }
+}
+
+pub trait Value {
+    fn serialize(&mut self, s: &mut Serializer);
+}
+
+macro_rules! int_value {
+    ($($t:ty),*) => {$(
+        impl Value for $t {
+            #[inline]
+            fn serialize(&mut self, s: &mut Serializer) {
+                let mut bytes = self.to_le_bytes();
+                s.bytes(&mut bytes);
+                *self = <$t>::from_le_bytes(bytes);
+            }
+        }
+    )*};
+}
+
+int_value!(u8, u16, u32, u64, i16, i32, i64);
+
+impl Value for usize {
+    #[inline]
+    fn serialize(&mut self, s: &mut Serializer) {
+        let mut value = *self as u64;
+        s.value(&mut value);
+        *self = value as usize;
+    }
+}
+
+impl Value for bool {
+    #[inline]
+    fn serialize(&mut self, s: &mut Serializer) {
+        let mut value = *self as u8;
+        s.value(&mut value);
+        *self = value != 0;
+    }
+}
+
+impl<T: Value, const N: usize> Value for [T; N] {
+    fn serialize(&mut self, s: &mut Serializer) {
+        for value in self {
+            s.value(value);
+        }
+    }
+}
+
+impl<T: Value + ?Sized> Value for Box<T> {
+    #[inline]
+    fn serialize(&mut self, s: &mut Serializer) {
+        s.value(&mut **self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save(f: impl FnOnce(&mut Serializer)) -> Vec<u8> {
+        let mut s = Serializer::save();
+        f(&mut s);
+        s.finish()
+    }
+
+    #[test]
+    fn sections_round_trip() {
+        let data = save(|s| {
+            s.section(*b"ONE ", |s| {
+                s.value(&mut 0x1234u16);
+                s.value(&mut true);
+            });
+            s.section(*b"TWO ", |s| s.value(&mut [1u8, 2, 3]));
+        });
+        assert_eq!(data, [
+            b"SMWS\x01\x00".as_slice(),
+            b"ONE \x03\x00\x00\x00\x34\x12\x01",
+            b"TWO \x03\x00\x00\x00\x01\x02\x03",
+        ].concat());
+
+        // sections are found by tag, in any order
+        let (mut a, mut b, mut c) = (0u16, false, [0u8; 3]);
+        let mut s = Serializer::load(&data).unwrap();
+        s.section(*b"TWO ", |s| s.value(&mut c));
+        s.section(*b"ONE ", |s| {
+            s.value(&mut a);
+            s.value(&mut b);
+        });
+        assert!(s.valid());
+        assert_eq!((a, b, c), (0x1234, true, [1, 2, 3]));
+    }
+
+    #[test]
+    fn older_states_keep_newer_fields() {
+        let data = save(|s| {
+            s.section(*b"ONE ", |s| s.value(&mut 0x12u8));
+            s.section(*b"OLD ", |s| s.value(&mut 0x34u8));
+        });
+        let (mut a, mut b, mut c) = (0u8, 0xbeefu16, 0x56u8);
+        let mut s = Serializer::load(&data).unwrap();
+        s.section(*b"ONE ", |s| {
+            s.value(&mut a);
+            s.value(&mut b);
+        });
+        s.section(*b"NEW ", |s| s.value(&mut c));
+        assert!(s.valid());
+        assert_eq!((a, b, c), (0x12, 0xbeef, 0x56));
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let data = save(|s| s.section(*b"ONE ", |s| s.value(&mut 0u32)));
+        assert!(Serializer::load(&data).is_some());
+        assert!(Serializer::load(b"SMWS\x02\x00").is_none());
+        assert!(Serializer::load(b"SNES\x01\x00").is_none());
+        assert!(Serializer::load(b"SMWS\x01").is_none());
+        // a section running past the end of the state
+        assert!(Serializer::load(&data[..data.len() - 1]).is_none());
+
+        let mut s = Serializer::load(&data).unwrap();
+        s.check(true);
+        assert!(s.valid());
+        s.check(false);
+        s.check(true);
+        assert!(!s.valid());
+    }
+
+    #[test]
+    fn fixed_records() {
+        let data = save(|s| s.section(*b"ONE ", |s| {
+            s.fixed(4, |s| s.value(&mut 0x12u8));
+            s.value(&mut 0x34u8);
+        }));
+        assert_eq!(data[6..], *b"ONE \x05\x00\x00\x00\x12\x00\x00\x00\x34");
+
+        // the record is skipped whole, however much of it is read
+        let (mut a, mut b) = (0u8, 0u8);
+        let mut s = Serializer::load(&data).unwrap();
+        s.section(*b"ONE ", |s| {
+            s.fixed(4, |s| s.value(&mut a));
+            s.value(&mut b);
+        });
+        assert_eq!((a, b), (0x12, 0x34));
+    }
+}